@@ -1,181 +1,1907 @@
 //! # Archives utilities
-//! A toolbox of small utilities that extract files from archives.
-//! Useful for retrieving files from various types of archives like tar, tar.gz, zip.
+//! A toolbox of small utilities that extract files from archives and create new ones.
+//! Useful for retrieving files from various types of archives like tar, tar.gz, zip
+//! and for packaging directories before shipping them off a device.
 
 use anyhow::{Result, bail};
-use flate2::read::GzDecoder;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use regex::Regex;
 use std::{
-    fs::File,
-    io::{self, Read},
-    path::PathBuf,
+    cell::RefCell,
+    fs::{File, Metadata},
+    io::{self, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
-use tar::Archive;
-use zip::ZipArchive;
+use tar::{Archive, Builder};
+use zip::{
+    AesMode, CompressionMethod, DateTime, ZipArchive, ZipWriter, read::ZipFile, result::ZipError,
+    write::SimpleFileOptions,
+};
+
+use utils_box_logger::log_info;
+
+use crate::error::ArchiveError;
+use crate::limits::{Budget, LimitExceeded};
+use crate::policy::{ExtractPolicy, ExtractReport, Hazard, PolicyAction, normalize};
+use crate::progress::{CancelToken, ExtractProgress, TrackedReader, Tracker};
+
+pub static GZ_SIGNATURE: [u8; 3] = [0x1F, 0x8B, 0x08];
+pub static ZIP_SIGNATURE: [u8; 3] = [0x50, 0x4B, 0x03];
+pub static XZ_SIGNATURE: [u8; 6] = [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+pub static BZ2_SIGNATURE: [u8; 3] = [0x42, 0x5A, 0x68];
+pub static ZST_SIGNATURE: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+/// POSIX and GNU tar headers hold the `ustar` magic at offset 257
+pub static TAR_SIGNATURE: [u8; 5] = [0x75, 0x73, 0x74, 0x61, 0x72];
+pub static TAR_SIGNATURE_OFFSET: usize = 257;
+
+/// Size of a single tar header block
+const TAR_BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveType {
+    Tar,
+    /// Tar archive compressed with gzip (`.tar.gz`)
+    Gz,
+    Zip,
+    /// Tar archive compressed with xz (`.tar.xz`), requires the `xz` feature
+    Xz,
+    /// Tar archive compressed with bzip2 (`.tar.bz2`), requires the `bzip2` feature
+    Bz2,
+    /// Tar archive compressed with zstd (`.tar.zst`), requires the `zstd` feature
+    Zst,
+    /// Single file compressed with gzip (`.gz`) that is not a tar archive
+    GzFile,
+}
+
+impl ArchiveType {
+    /// Whether the archive is a (possibly compressed) tar stream
+    pub fn is_tar(&self) -> bool {
+        !matches!(self, ArchiveType::Zip | ArchiveType::GzFile)
+    }
+}
+
+impl std::fmt::Display for ArchiveType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let extension = match self {
+            ArchiveType::Tar => "tar",
+            ArchiveType::Gz => "tar.gz",
+            ArchiveType::Zip => "zip",
+            ArchiveType::Xz => "tar.xz",
+            ArchiveType::Bz2 => "tar.bz2",
+            ArchiveType::Zst => "tar.zst",
+            ArchiveType::GzFile => "gz",
+        };
+
+        write!(f, "{extension}")
+    }
+}
+
+/// Kinds of entries found inside archives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    HardLink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Other,
+}
+
+impl EntryKind {
+    pub fn is_device(&self) -> bool {
+        matches!(
+            self,
+            EntryKind::CharDevice | EntryKind::BlockDevice | EntryKind::Fifo
+        )
+    }
+}
+
+impl From<tar::EntryType> for EntryKind {
+    fn from(entry_type: tar::EntryType) -> Self {
+        match entry_type {
+            tar::EntryType::Regular | tar::EntryType::Continuous => EntryKind::File,
+            tar::EntryType::Directory => EntryKind::Directory,
+            tar::EntryType::Symlink => EntryKind::Symlink,
+            tar::EntryType::Link => EntryKind::HardLink,
+            tar::EntryType::Char => EntryKind::CharDevice,
+            tar::EntryType::Block => EntryKind::BlockDevice,
+            tar::EntryType::Fifo => EntryKind::Fifo,
+            _ => EntryKind::Other,
+        }
+    }
+}
+
+impl EntryKind {
+    /// Detect the kind of a zip entry from the unix file type bits stored in its external attributes
+    pub fn from_zip(is_dir: bool, unix_mode: Option<u32>) -> Self {
+        if is_dir {
+            return EntryKind::Directory;
+        }
+
+        match unix_mode.map(|mode| mode & 0o170000) {
+            Some(0o120000) => EntryKind::Symlink,
+            Some(0o020000) => EntryKind::CharDevice,
+            Some(0o060000) => EntryKind::BlockDevice,
+            Some(0o010000) => EntryKind::Fifo,
+            Some(0o040000) => EntryKind::Directory,
+            _ => EntryKind::File,
+        }
+    }
+}
+
+/// Entry Point:
+/// Extract the selected file from the provided archive into the selected destination directory
+/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
+/// The default `ExtractPolicy` is applied to the requested file
+pub fn archive_extract_file(
+    archive: PathBuf,
+    filename: PathBuf,
+    destination: PathBuf,
+) -> Result<(), ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_file")?;
+
+    Ok(extract_file(archive, archive_type, filename, destination)?)
+}
+
+/// Entry Point:
+/// Extract the selected file from the provided archive into the selected destination directory
+/// applying the provided `ExtractPolicy` and report what was extracted, skipped or sanitized
+pub fn archive_extract_file_with_policy(
+    archive: PathBuf,
+    filename: PathBuf,
+    destination: PathBuf,
+    policy: &ExtractPolicy,
+) -> Result<ExtractReport, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_file_with_policy")?;
+
+    Ok(extract_file_with_policy(
+        archive,
+        archive_type,
+        filename,
+        destination,
+        policy,
+        None,
+    )?)
+}
+
+/// Entry Point:
+/// Extract the selected file from the provided password protected zip archive into the selected destination directory
+/// Both ZipCrypto and AES encrypted entries are supported, unencrypted entries and other archive types are extracted as usual
+/// The default `ExtractPolicy` is applied to the requested file
+pub fn archive_extract_file_with_password(
+    archive: PathBuf,
+    filename: PathBuf,
+    destination: PathBuf,
+    password: &str,
+) -> Result<ExtractReport, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_file_with_password")?;
+
+    Ok(extract_file_with_policy(
+        archive,
+        archive_type,
+        filename,
+        destination,
+        &ExtractPolicy::default(),
+        Some(password),
+    )?)
+}
+
+/// Entry Point:
+/// Extract all contents from the provided archive into the selected destination directory
+/// The destination directory will be created if not already available
+/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
+/// The default `ExtractPolicy` is applied to all entries
+pub fn archive_extract_all(archive: PathBuf, destination: PathBuf) -> Result<(), ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_all")?;
+
+    Ok(extract_all(archive, archive_type, destination)?)
+}
+
+/// Entry Point:
+/// Extract all contents from the provided archive into the selected destination directory
+/// applying the provided `ExtractPolicy` and report what was extracted, skipped or sanitized
+pub fn archive_extract_all_with_policy(
+    archive: PathBuf,
+    destination: PathBuf,
+    policy: &ExtractPolicy,
+) -> Result<ExtractReport, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_all_with_policy")?;
+
+    Ok(extract_all_with_policy(
+        archive,
+        archive_type,
+        destination,
+        policy,
+    )?)
+}
+
+/// Detect the type of the archive from its magic number
+/// Files that match none of the known signatures are reported as an unknown format
+pub(crate) fn detect_archive_type(archive: &Path, caller: &str) -> Result<ArchiveType> {
+    let archive_type = match sniff_archive_type(archive)? {
+        Some(archive_type) => archive_type,
+        None => bail!(ArchiveError::UnsupportedFormat(format!(
+            "[{}] Unknown archive format for [{}]!",
+            caller,
+            archive.display()
+        ))),
+    };
+
+    log_info!("[{}] Detected [{}] archive", caller, archive_type);
+
+    Ok(archive_type)
+}
+
+/// Match the first block of the file against the known signatures, `None` if it is not an archive
+pub(crate) fn sniff_archive_type(archive: &Path) -> Result<Option<ArchiveType>> {
+    let mut magic_number: Vec<u8> = vec![];
+    File::open(archive)?
+        .take(TAR_BLOCK_SIZE as u64)
+        .read_to_end(&mut magic_number)?;
+
+    let archive_type = if magic_number.starts_with(&GZ_SIGNATURE) {
+        // Peek inside the compressed stream to tell a tar.gz from a plain .gz file
+        let mut block: Vec<u8> = vec![];
+        GzDecoder::new(File::open(archive)?)
+            .take(TAR_BLOCK_SIZE as u64)
+            .read_to_end(&mut block)?;
+
+        if is_tar_header(&block) {
+            ArchiveType::Gz
+        } else {
+            ArchiveType::GzFile
+        }
+    } else if magic_number.starts_with(&ZIP_SIGNATURE) {
+        ArchiveType::Zip
+    } else if magic_number.starts_with(&XZ_SIGNATURE) {
+        ArchiveType::Xz
+    } else if magic_number.starts_with(&BZ2_SIGNATURE) {
+        ArchiveType::Bz2
+    } else if magic_number.starts_with(&ZST_SIGNATURE) {
+        ArchiveType::Zst
+    } else if is_tar_header(&magic_number) {
+        ArchiveType::Tar
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(archive_type))
+}
+
+/// Check if the block is a tar header, either by the `ustar` magic or by a valid header checksum (old v7 tar)
+fn is_tar_header(block: &[u8]) -> bool {
+    if block.len() < TAR_BLOCK_SIZE {
+        return false;
+    }
+
+    if block[TAR_SIGNATURE_OFFSET..].starts_with(&TAR_SIGNATURE) {
+        return true;
+    }
+
+    // The checksum is calculated with its own field filled with spaces
+    let checksum: u32 = block[..TAR_BLOCK_SIZE]
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                0x20
+            } else {
+                b as u32
+            }
+        })
+        .sum();
+
+    let stored = std::str::from_utf8(&block[148..156])
+        .ok()
+        .map(|field| field.trim_matches(|c: char| c == '\0' || c == ' '))
+        .and_then(|field| u32::from_str_radix(field, 8).ok());
+
+    block[0] != 0 && stored == Some(checksum)
+}
+
+/// Open the archive and return its decompressed stream
+pub(crate) fn decompressed_stream(
+    archive: &Path,
+    archive_type: &ArchiveType,
+) -> Result<Box<dyn Read>> {
+    decoder(File::open(archive)?, archive_type)
+}
+
+/// Wrap the raw contents of the archive with the matching decompressor
+pub(crate) fn decoder<'a, R: Read + 'a>(
+    reader: R,
+    archive_type: &ArchiveType,
+) -> Result<Box<dyn Read + 'a>> {
+    match archive_type {
+        ArchiveType::Tar => Ok(Box::new(reader)),
+        ArchiveType::Gz | ArchiveType::GzFile => Ok(Box::new(GzDecoder::new(reader))),
+        ArchiveType::Xz => xz_decoder(reader),
+        ArchiveType::Bz2 => bz2_decoder(reader),
+        ArchiveType::Zst => zst_decoder(reader),
+        ArchiveType::Zip => bail!(ArchiveError::UnsupportedFormat(
+            "[decompressed_stream] Zip archives cannot be streamed!".to_string()
+        )),
+    }
+}
+
+#[cfg(feature = "xz")]
+fn xz_decoder<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(xz2::read::XzDecoder::new(reader)))
+}
+
+#[cfg(not(feature = "xz"))]
+fn xz_decoder<'a, R: Read + 'a>(_reader: R) -> Result<Box<dyn Read + 'a>> {
+    bail!(ArchiveError::UnsupportedFormat(
+        "[xz_decoder] Support for [tar.xz] archives requires the `xz` feature!".to_string()
+    ))
+}
+
+#[cfg(feature = "bzip2")]
+fn bz2_decoder<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(bzip2::read::BzDecoder::new(reader)))
+}
+
+#[cfg(not(feature = "bzip2"))]
+fn bz2_decoder<'a, R: Read + 'a>(_reader: R) -> Result<Box<dyn Read + 'a>> {
+    bail!(ArchiveError::UnsupportedFormat(
+        "[bz2_decoder] Support for [tar.bz2] archives requires the `bzip2` feature!".to_string()
+    ))
+}
+
+#[cfg(feature = "zstd")]
+fn zst_decoder<'a, R: Read + 'a>(reader: R) -> Result<Box<dyn Read + 'a>> {
+    Ok(Box::new(zstd::stream::read::Decoder::new(reader)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zst_decoder<'a, R: Read + 'a>(_reader: R) -> Result<Box<dyn Read + 'a>> {
+    bail!(ArchiveError::UnsupportedFormat(
+        "[zst_decoder] Support for [tar.zst] archives requires the `zstd` feature!".to_string()
+    ))
+}
+
+/// Name of the file stored in a plain .gz file.
+/// Uses the original name from the gzip header if available, otherwise the archive name without `.gz`
+pub(crate) fn gz_file_name(archive: &Path, header: Option<&flate2::GzHeader>) -> PathBuf {
+    if let Some(name) = header.and_then(|h| h.filename()) {
+        return PathBuf::from(String::from_utf8_lossy(name).to_string());
+    }
+
+    match archive.extension() {
+        Some(extension) if extension == "gz" => archive.file_stem().map(PathBuf::from),
+        _ => archive
+            .file_name()
+            .map(|name| PathBuf::from(format!("{}.out", name.to_string_lossy()))),
+    }
+    .unwrap_or_default()
+}
+
+/// Decompress a plain .gz file into the destination directory applying the policy on its name
+fn unpack_gz_file(
+    archive: &Path,
+    reader: impl Read,
+    selected: impl Fn(&Path) -> bool,
+    destination: &Path,
+    policy: &ExtractPolicy,
+    budget: &mut Budget,
+    report: &mut ExtractReport,
+) -> Result<bool> {
+    let mut decoder = GzDecoder::new(reader);
+
+    // The header is parsed with the first read
+    let mut buffer: Vec<u8> = vec![];
+    (&mut decoder).take(1).read_to_end(&mut buffer)?;
+
+    let name = gz_file_name(archive, decoder.header());
+
+    if !selected(&name) {
+        return Ok(false);
+    }
+
+    // The decompressed size is unknown up front, the limits are checked while streaming
+    budget.start_entry(&name, None, None)?;
+
+    let checked = match policy.check(&name, EntryKind::File, None, None, report)? {
+        Some(checked) => checked,
+        None => return Ok(true),
+    };
+
+    if checked.path.as_os_str().is_empty()
+        || !ensure_inside_destination(destination, &checked.path, &name, policy, report)?
+    {
+        return Ok(true);
+    }
+
+    let target = destination.join(&checked.path);
+    let mut outfile = create_new_file(&target)?;
+
+    if let Err(e) = budget.copy(
+        &name,
+        &mut io::Cursor::new(buffer).chain(&mut decoder),
+        &mut outfile,
+    ) {
+        let _ = std::fs::remove_file(&target);
+        return Err(e);
+    }
+
+    report.extracted.push(checked.path);
+
+    Ok(true)
+}
+
+/// Metadata of a single entry inside an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Path of the entry as stored in the archive
+    pub path: PathBuf,
+    /// Uncompressed size in bytes
+    pub size: u64,
+    /// Compressed size in bytes (only available for zip archives)
+    pub compressed_size: Option<u64>,
+    /// Unix permissions (without the file type bits)
+    pub mode: Option<u32>,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: Option<u64>,
+    pub kind: EntryKind,
+    /// CRC32 of the uncompressed contents (only available for zip archives)
+    pub crc32: Option<u32>,
+    /// Target of symbolic and hard links (only available for tar archives)
+    pub link: Option<PathBuf>,
+}
+
+/// Entry Point:
+/// List the entries of the provided archive without extracting anything on disk
+/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
+pub fn archive_list(archive: PathBuf) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_list")?;
+
+    Ok(list(archive, archive_type)?)
+}
+
+/// List the entries of the provided archive without extracting anything on disk
+fn list(archive: PathBuf, archive_type: ArchiveType) -> Result<Vec<ArchiveEntry>> {
+    match archive_type {
+        ArchiveType::GzFile => {
+            let compressed_size = std::fs::metadata(&archive)?.len();
+            let mut decoder = GzDecoder::new(File::open(&archive)?);
+            let size = io::copy(&mut decoder, &mut io::sink())?;
+
+            let header = decoder.header();
+
+            Ok(vec![ArchiveEntry {
+                path: gz_file_name(&archive, header),
+                size,
+                compressed_size: Some(compressed_size),
+                mode: None,
+                mtime: header.map(|h| h.mtime() as u64).filter(|&mtime| mtime != 0),
+                kind: EntryKind::File,
+                crc32: None,
+                link: None,
+            }])
+        }
+        ArchiveType::Zip => {
+            let file = File::open(archive)?;
+            let mut ar = ZipArchive::new(file)?;
+
+            let mut entries: Vec<ArchiveEntry> = vec![];
+
+            // The central directory holds all metadata, no decompression is needed
+            for i in 0..ar.len() {
+                let in_file = ar.by_index_raw(i)?;
+
+                entries.push(ArchiveEntry {
+                    path: PathBuf::from(in_file.name()),
+                    size: in_file.size(),
+                    compressed_size: Some(in_file.compressed_size()),
+                    mode: in_file.unix_mode().map(|mode| mode & 0o7777),
+                    mtime: in_file.last_modified().and_then(zip_unix_time),
+                    kind: EntryKind::from_zip(in_file.is_dir(), in_file.unix_mode()),
+                    crc32: Some(in_file.crc32()),
+                    link: None,
+                });
+            }
+
+            Ok(entries)
+        }
+        _ => {
+            let mut ar = Archive::new(decompressed_stream(&archive, &archive_type)?);
+            list_tar(&mut ar)
+        }
+    }
+}
+
+/// Walk the tar headers only, the contents of each entry are skipped
+fn list_tar<R: Read>(ar: &mut Archive<R>) -> Result<Vec<ArchiveEntry>> {
+    let mut entries: Vec<ArchiveEntry> = vec![];
+
+    for archived_file in ar.entries()? {
+        let ar_file = archived_file.map_err(tar_error)?;
+        let header = ar_file.header();
+
+        entries.push(ArchiveEntry {
+            path: ar_file.path()?.to_path_buf(),
+            size: ar_file.size(),
+            compressed_size: None,
+            mode: header.mode().ok().map(|mode| mode & 0o7777),
+            mtime: header.mtime().ok(),
+            kind: EntryKind::from(header.entry_type()),
+            crc32: None,
+            link: ar_file.link_name()?.map(|link| link.to_path_buf()),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Extract the selected file from the provided archive into the selected destination directory
+fn extract_file(
+    archive: PathBuf,
+    archive_type: ArchiveType,
+    filename: PathBuf,
+    destination: PathBuf,
+) -> Result<()> {
+    extract_file_with_policy(
+        archive,
+        archive_type,
+        filename,
+        destination,
+        &ExtractPolicy::default(),
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// Extract the selected file from the provided archive into the selected destination directory
+/// Fails if the file is not found or if it was skipped by the policy
+fn extract_file_with_policy(
+    archive: PathBuf,
+    archive_type: ArchiveType,
+    filename: PathBuf,
+    destination: PathBuf,
+    policy: &ExtractPolicy,
+    password: Option<&str>,
+) -> Result<ExtractReport> {
+    let mut report = ExtractReport::new();
+    let mut budget = Budget::new(&policy.limits, std::fs::metadata(&archive)?.len());
+
+    match archive_type {
+        ArchiveType::Zip => {
+            let file = File::open(archive)?;
+            let mut ar = ZipArchive::new(file)?;
+
+            for i in 0..ar.len() {
+                let name = PathBuf::from(ar.name_for_index(i).unwrap_or_default());
+
+                if name.file_name() == Some(filename.as_os_str()) {
+                    let mut in_file = zip_entry(&mut ar, i, password, "extract_file")?;
+                    let _ = std::fs::create_dir(destination.clone());
+                    unpack_zip_entry(
+                        &mut in_file,
+                        &destination,
+                        true,
+                        policy,
+                        &mut budget,
+                        &mut report,
+                    )?;
+
+                    return requested_file_report(report);
+                }
+            }
+
+            bail!(ArchiveError::NotFound(format!(
+                "[extract_file][zip] Failed to find requested file [{}]!",
+                filename.display()
+            )));
+        }
+        ArchiveType::GzFile => {
+            let _ = std::fs::create_dir(destination.clone());
+
+            if unpack_gz_file(
+                &archive,
+                File::open(&archive)?,
+                |name| name == filename,
+                &destination,
+                policy,
+                &mut budget,
+                &mut report,
+            )? {
+                return requested_file_report(report);
+            }
+
+            bail!(ArchiveError::NotFound(format!(
+                "[extract_file][gz] Failed to find requested file [{}]!",
+                filename.display()
+            )));
+        }
+        _ => {
+            let mut ar = Archive::new(decompressed_stream(&archive, &archive_type)?);
+
+            // Check the contents for the requested file
+            for archived_file in ar.entries()? {
+                // Unwrap the file
+                let mut ar_file = archived_file.map_err(tar_error)?;
+
+                // Check if it is the file we need
+                if ar_file.path()? == filename {
+                    let _ = std::fs::create_dir(destination.clone());
+                    unpack_tar_entry(
+                        &mut ar_file,
+                        &destination,
+                        false,
+                        policy,
+                        &mut budget,
+                        &mut report,
+                    )?;
+
+                    return requested_file_report(report);
+                }
+            }
+
+            bail!(ArchiveError::NotFound(format!(
+                "[extract_file][{}] Failed to find requested file [{}]!",
+                archive_type,
+                filename.display()
+            )));
+        }
+    };
+}
+
+/// The requested file must not be silently skipped by the policy
+fn requested_file_report(report: ExtractReport) -> Result<ExtractReport> {
+    if let Some((entry, hazard)) = report.skipped.first() {
+        bail!(ArchiveError::PolicyViolation {
+            entry: entry.clone(),
+            hazard: *hazard,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Extract all contents from the provided archive into the selected destination directory
+/// The destination directory will be created if not already available
+fn extract_all(archive: PathBuf, archive_type: ArchiveType, destination: PathBuf) -> Result<()> {
+    extract_all_with_policy(
+        archive,
+        archive_type,
+        destination,
+        &ExtractPolicy::default(),
+    )?;
+
+    Ok(())
+}
+
+/// Extract all contents from the provided archive into the selected destination directory
+/// The destination directory will be created if not already available
+fn extract_all_with_policy(
+    archive: PathBuf,
+    archive_type: ArchiveType,
+    destination: PathBuf,
+    policy: &ExtractPolicy,
+) -> Result<ExtractReport> {
+    extract_all_with_progress(
+        archive,
+        archive_type,
+        destination,
+        policy,
+        None,
+        &mut |_| {},
+        &CancelToken::new(),
+        &|_| true,
+    )
+}
+
+/// Entry Point:
+/// Extract all contents from the provided password protected zip archive into the selected destination directory
+/// Both ZipCrypto and AES encrypted entries are supported, unencrypted entries and other archive types are extracted as usual
+/// The default `ExtractPolicy` is applied to all entries
+pub fn archive_extract_all_with_password(
+    archive: PathBuf,
+    destination: PathBuf,
+    password: &str,
+) -> Result<ExtractReport, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_all_with_password")?;
+
+    Ok(extract_all_with_progress(
+        archive,
+        archive_type,
+        destination,
+        &ExtractPolicy::default(),
+        Some(password),
+        &mut |_| {},
+        &CancelToken::new(),
+        &|_| true,
+    )?)
+}
+
+/// Entry Point:
+/// Extract all contents from the provided archive into the selected destination directory
+/// applying the provided `ExtractPolicy`, reporting the progress after every chunk read and every entry.
+/// The extraction stops as soon as the token is cancelled and everything written so far is removed.
+pub fn archive_extract_all_with_progress(
+    archive: PathBuf,
+    destination: PathBuf,
+    policy: &ExtractPolicy,
+    mut progress: impl FnMut(&ExtractProgress),
+    cancel: &CancelToken,
+) -> Result<ExtractReport, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_all_with_progress")?;
+
+    Ok(extract_all_with_progress(
+        archive,
+        archive_type,
+        destination,
+        policy,
+        None,
+        &mut progress,
+        cancel,
+        &|_| true,
+    )?)
+}
+
+/// Extract all contents from the provided archive into the selected destination directory
+/// The destination directory will be created if not already available
+/// Only the entries whose names (as in the archive) are `selected` are extracted
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn extract_all_with_progress(
+    archive: PathBuf,
+    archive_type: ArchiveType,
+    destination: PathBuf,
+    policy: &ExtractPolicy,
+    password: Option<&str>,
+    progress: &mut dyn FnMut(&ExtractProgress),
+    cancel: &CancelToken,
+    selected: &dyn Fn(&Path) -> bool,
+) -> Result<ExtractReport> {
+    let mut report = ExtractReport::new();
+
+    let existed = destination.exists();
+    let bytes_total = std::fs::metadata(&archive)?.len();
+    let tracker = RefCell::new(Tracker::new(Some(bytes_total), progress, cancel.clone()));
+
+    if let Err(e) = unpack_all(
+        &archive,
+        &archive_type,
+        &destination,
+        policy,
+        password,
+        &tracker,
+        &mut report,
+        selected,
+    ) {
+        if !cancel.is_cancelled() {
+            return Err(e);
+        }
+
+        remove_partial_output(&destination, existed, &report, tracker.borrow().partial());
+        bail!(ArchiveError::Cancelled);
+    }
+
+    log_info!(
+        "[extract_all] Extracted [{}] entries, skipped [{}], sanitized [{}]",
+        report.extracted.len(),
+        report.skipped.len(),
+        report.sanitized.len()
+    );
+
+    Ok(report)
+}
+
+/// Unpack every selected entry of the archive, checking for cancellation before each one
+#[allow(clippy::too_many_arguments)]
+fn unpack_all(
+    archive: &Path,
+    archive_type: &ArchiveType,
+    destination: &Path,
+    policy: &ExtractPolicy,
+    password: Option<&str>,
+    tracker: &RefCell<Tracker>,
+    report: &mut ExtractReport,
+    selected: &dyn Fn(&Path) -> bool,
+) -> Result<()> {
+    let reader = TrackedReader::new(File::open(archive)?, tracker);
+    let mut budget = Budget::new(&policy.limits, std::fs::metadata(archive)?.len());
+
+    match archive_type {
+        ArchiveType::Zip => {
+            let mut ar = ZipArchive::new(reader)?;
+            tracker.borrow_mut().set_entries_total(ar.len() as u64);
+            let _ = std::fs::create_dir(destination);
+
+            for i in 0..ar.len() {
+                if tracker.borrow().is_cancelled() {
+                    bail!(ArchiveError::Cancelled);
+                }
+
+                let name = PathBuf::from(ar.name_for_index(i).unwrap_or_default());
+
+                // Entries are only opened (and decrypted) once they are selected
+                tracker.borrow_mut().start_entry(name.clone());
+                if selected(&name) {
+                    let mut in_file = zip_entry(&mut ar, i, password, "extract_all")?;
+                    unpack_zip_entry(
+                        &mut in_file,
+                        destination,
+                        false,
+                        policy,
+                        &mut budget,
+                        report,
+                    )?;
+                }
+                tracker.borrow_mut().finish_entry();
+            }
+        }
+        ArchiveType::GzFile => {
+            tracker.borrow_mut().set_entries_total(1);
+            let _ = std::fs::create_dir(destination);
+
+            let name = gz_file_name(archive, None);
+
+            tracker.borrow_mut().start_entry(name);
+            unpack_gz_file(
+                archive,
+                reader,
+                selected,
+                destination,
+                policy,
+                &mut budget,
+                report,
+            )?;
+            tracker.borrow_mut().finish_entry();
+        }
+        _ => {
+            let mut ar = Archive::new(decoder(reader, archive_type)?);
+            let _ = std::fs::create_dir(destination);
+
+            for archived_file in ar.entries()? {
+                if tracker.borrow().is_cancelled() {
+                    bail!(ArchiveError::Cancelled);
+                }
+
+                let mut ar_file = archived_file.map_err(tar_error)?;
+                let name = ar_file.path()?.to_path_buf();
+
+                tracker.borrow_mut().start_entry(name.clone());
+                if selected(&name) {
+                    unpack_tar_entry(
+                        &mut ar_file,
+                        destination,
+                        false,
+                        policy,
+                        &mut budget,
+                        report,
+                    )?;
+                }
+                tracker.borrow_mut().finish_entry();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove everything written by a cancelled extraction.
+/// A destination created by the extraction is removed completely,
+/// otherwise only the extracted entries and the interrupted one are removed.
+fn remove_partial_output(
+    destination: &Path,
+    existed: bool,
+    report: &ExtractReport,
+    partial: Option<&PathBuf>,
+) {
+    if !existed {
+        let _ = std::fs::remove_dir_all(destination);
+        return;
+    }
+
+    let partial = partial.map(|path| normalize(path));
+
+    for path in partial.iter().chain(report.extracted.iter().rev()) {
+        if path.as_os_str().is_empty() {
+            continue;
+        }
+
+        let target = destination.join(path);
+
+        match std::fs::symlink_metadata(&target) {
+            Ok(metadata) if metadata.is_dir() => {
+                let _ = std::fs::remove_dir(&target);
+            }
+            Ok(_) => {
+                let _ = std::fs::remove_file(&target);
+            }
+            Err(_) => {}
+        }
+
+        // Parent directories created for the entry are removed once empty
+        for parent in path.ancestors().skip(1) {
+            if parent.as_os_str().is_empty()
+                || std::fs::remove_dir(destination.join(parent)).is_err()
+            {
+                break;
+            }
+        }
+    }
+
+    log_info!(
+        "[extract_all] Removed the partial output of the cancelled extraction from [{}]",
+        destination.display()
+    );
+}
+
+/// Selects archive entries by their full path inside the archive
+#[derive(Debug, Clone)]
+pub enum EntrySelector {
+    /// Shell-like glob pattern. `*` also matches `/`, so `*.hex` selects `.hex` files in any directory
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl EntrySelector {
+    /// Create a new selector from a glob pattern
+    pub fn glob(pattern: &str) -> Result<Self, ArchiveError> {
+        match glob::Pattern::new(pattern) {
+            Ok(pattern) => Ok(Self::Glob(pattern)),
+            Err(e) => Err(ArchiveError::InvalidInput(format!(
+                "[entry_selector] Invalid glob pattern [{pattern}]: {e}"
+            ))),
+        }
+    }
+
+    /// Create a new selector from a regular expression
+    pub fn regex(pattern: &str) -> Result<Self, ArchiveError> {
+        match Regex::new(pattern) {
+            Ok(regex) => Ok(Self::Regex(regex)),
+            Err(e) => Err(ArchiveError::InvalidInput(format!(
+                "[entry_selector] Invalid regular expression [{pattern}]: {e}"
+            ))),
+        }
+    }
+
+    /// Check if the path of an archive entry is selected
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+
+        match self {
+            EntrySelector::Glob(pattern) => pattern.matches_with(
+                &path,
+                glob::MatchOptions {
+                    case_sensitive: true,
+                    require_literal_separator: false,
+                    require_literal_leading_dot: false,
+                },
+            ),
+            EntrySelector::Regex(regex) => regex.is_match(&path),
+        }
+    }
+}
+
+/// Entry Point:
+/// Extract all entries whose path matches the selector into the selected destination directory
+/// If `flatten` is set, the files are written directly in the destination without their parent directories
+/// (later entries with the same name overwrite earlier ones) and directory entries are ignored
+/// Returns the paths of all extracted entries
+pub fn archive_extract_matching(
+    archive: PathBuf,
+    selector: EntrySelector,
+    destination: PathBuf,
+    flatten: bool,
+) -> Result<Vec<PathBuf>, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_matching")?;
+
+    Ok(extract_matching(
+        archive,
+        archive_type,
+        &selector,
+        destination,
+        flatten,
+        None,
+    )?)
+}
+
+/// Entry Point:
+/// Extract all entries whose path matches the selector from the provided password protected zip archive
+/// into the selected destination directory, like `archive_extract_matching`
+/// Both ZipCrypto and AES encrypted entries are supported, unencrypted entries and other archive types are extracted as usual
+pub fn archive_extract_matching_with_password(
+    archive: PathBuf,
+    selector: EntrySelector,
+    destination: PathBuf,
+    flatten: bool,
+    password: &str,
+) -> Result<Vec<PathBuf>, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_extract_matching_with_password")?;
+
+    Ok(extract_matching(
+        archive,
+        archive_type,
+        &selector,
+        destination,
+        flatten,
+        Some(password),
+    )?)
+}
+
+/// Extract all entries whose path matches the selector into the selected destination directory
+/// Encrypted zip entries are decrypted with the password if provided
+fn extract_matching(
+    archive: PathBuf,
+    archive_type: ArchiveType,
+    selector: &EntrySelector,
+    destination: PathBuf,
+    flatten: bool,
+    password: Option<&str>,
+) -> Result<Vec<PathBuf>> {
+    let policy = ExtractPolicy::default();
+    let mut report = ExtractReport::new();
+    let mut budget = Budget::new(&policy.limits, std::fs::metadata(&archive)?.len());
+
+    let _ = std::fs::create_dir(destination.clone());
+
+    match archive_type {
+        ArchiveType::Zip => {
+            let file = File::open(archive)?;
+            let mut ar = ZipArchive::new(file)?;
+
+            for i in 0..ar.len() {
+                // Entries are only opened (and decrypted) once they are selected
+                let name = ar.name_for_index(i).unwrap_or_default();
+
+                if (flatten && is_zip_dir(name)) || !selector.matches(Path::new(name)) {
+                    continue;
+                }
+
+                let mut in_file = zip_entry(&mut ar, i, password, "extract_matching")?;
+
+                unpack_zip_entry(
+                    &mut in_file,
+                    &destination,
+                    flatten,
+                    &policy,
+                    &mut budget,
+                    &mut report,
+                )?;
+            }
+        }
+        ArchiveType::GzFile => {
+            unpack_gz_file(
+                &archive,
+                File::open(&archive)?,
+                |name| selector.matches(name),
+                &destination,
+                &policy,
+                &mut budget,
+                &mut report,
+            )?;
+        }
+        _ => {
+            let mut ar = Archive::new(decompressed_stream(&archive, &archive_type)?);
+
+            for archived_file in ar.entries()? {
+                let mut ar_file = archived_file.map_err(tar_error)?;
+
+                let is_dir = ar_file.header().entry_type().is_dir();
+                if (flatten && is_dir) || !selector.matches(&ar_file.path()?) {
+                    continue;
+                }
+
+                unpack_tar_entry(
+                    &mut ar_file,
+                    &destination,
+                    flatten,
+                    &policy,
+                    &mut budget,
+                    &mut report,
+                )?;
+            }
+        }
+    }
+
+    log_info!(
+        "[extract_matching] Extracted [{}] matching entries, skipped [{}]",
+        report.extracted.len(),
+        report.skipped.len()
+    );
+
+    Ok(report
+        .extracted
+        .into_iter()
+        .map(|path| destination.join(path))
+        .collect())
+}
+
+/// The tar reader reports truncated and corrupted streams as generic I/O errors
+fn tar_error(e: io::Error) -> ArchiveError {
+    match e.kind() {
+        io::ErrorKind::Other | io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            ArchiveError::Corrupt(format!("[archives][tar] Corrupted archive: {e}"))
+        }
+        _ => ArchiveError::Io(e),
+    }
+}
+
+/// Apply the policy on a single tar entry and unpack it inside the destination directory
+/// If `flatten` is set, the entry is written directly in the destination without its parent directories
+fn unpack_tar_entry<R: Read>(
+    entry: &mut tar::Entry<R>,
+    destination: &Path,
+    flatten: bool,
+    policy: &ExtractPolicy,
+    budget: &mut Budget,
+    report: &mut ExtractReport,
+) -> Result<()> {
+    let name = entry.path()?.to_path_buf();
+
+    // Tar headers hold the exact size of the data that follows
+    budget.start_entry(&name, Some(entry.size()), None)?;
+
+    let kind = EntryKind::from(entry.header().entry_type());
+    let mode = entry.header().mode().ok();
+    let link = entry.link_name()?.map(|link| link.to_path_buf());
+
+    let mut checked = match policy.check(&name, kind, mode, link.as_deref(), report)? {
+        Some(checked) => checked,
+        None => return Ok(()),
+    };
+
+    if flatten {
+        checked.path = checked
+            .path
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+    }
+
+    // Entries like `./` point to the destination itself
+    if checked.path.as_os_str().is_empty() {
+        return Ok(());
+    }
+
+    let target = destination.join(&checked.path);
+
+    if !ensure_inside_destination(destination, &checked.path, &name, policy, report)? {
+        return Ok(());
+    }
+
+    // Link targets are checked again against the links already extracted
+    if let (EntryKind::Symlink, Some(link)) = (kind, &link)
+        && !symlink_inside_destination(destination, &checked.path, link)?
+        && !external_link(&name, policy, report)?
+    {
+        return Ok(());
+    }
+
+    match (kind, link) {
+        (EntryKind::HardLink, Some(source)) => {
+            let source = match flatten {
+                true => source.file_name().map(PathBuf::from).unwrap_or_default(),
+                false => source,
+            };
+
+            let source = match hard_link_source(destination, &source)? {
+                Some(source) => source,
+                None if external_link(&name, policy, report)? => destination.join(source),
+                None => return Ok(()),
+            };
+
+            let _ = std::fs::remove_file(&target);
+            std::fs::hard_link(source, &target)?;
+        }
+        (
+            EntryKind::Directory
+            | EntryKind::Symlink
+            | EntryKind::CharDevice
+            | EntryKind::BlockDevice
+            | EntryKind::Fifo,
+            _,
+        ) => {
+            entry.set_preserve_permissions(checked.preserve_special_bits);
+            entry.unpack(&target)?;
+        }
+        _ => {
+            // Written through the budget, sparse entries expand to more than their header size
+            let mut outfile = create_new_file(&target)?;
+
+            if let Err(e) = budget.copy(&name, entry, &mut outfile) {
+                let _ = std::fs::remove_file(&target);
+                return Err(e);
+            }
+
+            if let Ok(mtime) = entry.header().mtime() {
+                outfile
+                    .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))?;
+            }
+
+            #[cfg(unix)]
+            if let Some(mode) = checked.mode {
+                use std::os::unix::fs::PermissionsExt;
+                outfile.set_permissions(std::fs::Permissions::from_mode(mode & 0o7777))?;
+            }
+        }
+    }
+
+    report.extracted.push(checked.path);
+
+    Ok(())
+}
+
+/// Open a zip entry for reading, decrypting it with the password if provided
+/// Wrong or missing passwords are reported separately from corrupted entries
+fn zip_entry<'a, R: Read + Seek>(
+    ar: &'a mut ZipArchive<R>,
+    index: usize,
+    password: Option<&str>,
+    caller: &str,
+) -> Result<ZipFile<'a>> {
+    let name = ar.name_for_index(index).unwrap_or_default().to_string();
+
+    let entry = match password {
+        Some(password) => ar.by_index_decrypt(index, password.as_bytes()),
+        None => ar.by_index(index),
+    };
+
+    match entry {
+        Ok(entry) => Ok(entry),
+        Err(ZipError::InvalidPassword) => bail!(ArchiveError::Password(format!(
+            "[{}][zip] Wrong password for entry [{}]!",
+            caller, name
+        ))),
+        Err(ZipError::UnsupportedArchive(reason)) if reason == ZipError::PASSWORD_REQUIRED => {
+            bail!(ArchiveError::Password(format!(
+                "[{}][zip] Entry [{}] is encrypted, a password is required!",
+                caller, name
+            )))
+        }
+        Err(ZipError::Io(e)) => Err(e.into()),
+        Err(e) => bail!(ArchiveError::Corrupt(format!(
+            "[{}][zip] Corrupted entry [{}]: {}",
+            caller, name, e
+        ))),
+    }
+}
+
+/// Zip archives mark directory entries with a trailing separator in their name
+pub(crate) fn is_zip_dir(name: &str) -> bool {
+    name.ends_with('/') || name.ends_with('\\')
+}
+
+/// Apply the policy on a single zip entry and write it inside the destination directory
+/// If `flatten` is set, the entry is written directly in the destination without its parent directories
+fn unpack_zip_entry(
+    entry: &mut ZipFile,
+    destination: &Path,
+    flatten: bool,
+    policy: &ExtractPolicy,
+    budget: &mut Budget,
+    report: &mut ExtractReport,
+) -> Result<()> {
+    let name = PathBuf::from(entry.name());
+
+    // The declared sizes are checked up front and the actual ones while writing
+    budget.start_entry(&name, Some(entry.size()), Some(entry.compressed_size()))?;
+
+    let kind = EntryKind::from_zip(entry.is_dir(), entry.unix_mode());
+    let mode = entry.unix_mode().map(|mode| mode & 0o7777);
+
+    // Symbolic links store their target as the contents of the entry
+    let link = if kind == EntryKind::Symlink {
+        let mut target = String::new();
+        entry.read_to_string(&mut target)?;
+        Some(PathBuf::from(target))
+    } else {
+        None
+    };
+
+    let mut checked = match policy.check(&name, kind, mode, link.as_deref(), report)? {
+        Some(checked) => checked,
+        None => return Ok(()),
+    };
+
+    if flatten {
+        checked.path = checked
+            .path
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+    }
+
+    if checked.path.as_os_str().is_empty() {
+        return Ok(());
+    }
+
+    let target = destination.join(&checked.path);
+
+    if !ensure_inside_destination(destination, &checked.path, &name, policy, report)? {
+        return Ok(());
+    }
+
+    // Link targets are checked again against the links already extracted
+    if let (EntryKind::Symlink, Some(link)) = (kind, &link)
+        && !symlink_inside_destination(destination, &checked.path, link)?
+        && !external_link(&name, policy, report)?
+    {
+        return Ok(());
+    }
+
+    match (kind, link) {
+        (EntryKind::Directory, _) => {
+            std::fs::create_dir_all(&target)?;
+        }
+        (EntryKind::Symlink, Some(source)) => {
+            let _ = std::fs::remove_file(&target);
+            symlink(&source, &target)?;
+        }
+        _ => {
+            let mut outfile = create_new_file(&target)?;
+
+            // Wrong ZipCrypto passwords can pass the header check and only fail on the CRC32
+            if let Err(e) = budget.copy(&name, entry, &mut outfile) {
+                let _ = std::fs::remove_file(&target);
+
+                if e.is::<LimitExceeded>() {
+                    return Err(e);
+                }
+
+                match entry.encrypted() {
+                    true => bail!(ArchiveError::Password(format!(
+                        "[unpack_zip_entry] Failed to decrypt entry [{}], wrong password or corrupted data: {}",
+                        name.display(),
+                        e
+                    ))),
+                    false => bail!(ArchiveError::Corrupt(format!(
+                        "[unpack_zip_entry] Corrupted data in entry [{}]: {}",
+                        name.display(),
+                        e
+                    ))),
+                }
+            }
+
+            if let Some(mtime) = entry.last_modified().and_then(zip_unix_time) {
+                outfile
+                    .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))?;
+            }
+
+            #[cfg(unix)]
+            if let Some(mode) = checked.mode {
+                use std::os::unix::fs::PermissionsExt;
+                outfile.set_permissions(std::fs::Permissions::from_mode(mode))?;
+            }
+        }
+    }
+
+    report.extracted.push(checked.path);
+
+    Ok(())
+}
+
+/// Create the parent directories of the entry and make sure that they do not escape the destination
+/// through symbolic links already present there. Escaping entries are handled as `Hazard::ExternalLink`.
+/// A symbolic link already present at the entry path is removed, so that it is never written through.
+fn ensure_inside_destination(
+    destination: &Path,
+    path: &Path,
+    name: &Path,
+    policy: &ExtractPolicy,
+    report: &mut ExtractReport,
+) -> Result<bool> {
+    let target = destination.join(path);
+    let parent = target.parent().unwrap_or(destination);
+
+    // Raw paths are only kept when the policy allows them
+    if *path != normalize(path) {
+        std::fs::create_dir_all(parent)?;
+        return Ok(true);
+    }
+
+    // Nothing is created before the existing part of the path is known to stay inside
+    if !is_inside_destination(destination, parent)? && !external_link(name, policy, report)? {
+        return Ok(false);
+    }
+
+    std::fs::create_dir_all(parent)?;
+
+    if target
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        std::fs::remove_file(&target)?;
+    }
+
+    Ok(true)
+}
+
+/// Create the file, replacing (instead of writing through) anything already present at its path
+/// Existing symbolic or hard links could otherwise point the write outside of the destination.
+fn create_new_file(target: &Path) -> io::Result<File> {
+    let _ = std::fs::remove_file(target);
+
+    File::options().write(true).create_new(true).open(target)
+}
+
+/// Whether the deepest existing ancestor of `path` resolves (following symbolic links) inside the destination
+fn is_inside_destination(destination: &Path, path: &Path) -> Result<bool> {
+    let existing = match path.ancestors().find(|p| p.symlink_metadata().is_ok()) {
+        Some(existing) => existing,
+        None => return Ok(false),
+    };
+
+    // Dangling symbolic links cannot be proven to stay inside
+    Ok(match existing.canonicalize() {
+        Ok(resolved) => resolved.starts_with(destination.canonicalize()?),
+        Err(_) => false,
+    })
+}
+
+/// Whether the symbolic link entry at `path` points inside the destination,
+/// following the links that are already present there
+fn symlink_inside_destination(destination: &Path, path: &Path, link: &Path) -> Result<bool> {
+    let base = match destination.join(path).parent() {
+        Some(parent) => parent.canonicalize()?,
+        None => return Ok(false),
+    };
+
+    let destination = destination.canonicalize()?;
+
+    Ok(resolve_on_disk(&base, link).is_some_and(|resolved| resolved.starts_with(destination)))
+}
+
+/// Canonical path of the hard link source (which must already be extracted), if it is inside the destination
+fn hard_link_source(destination: &Path, source: &Path) -> Result<Option<PathBuf>> {
+    let source = destination.join(source).canonicalize()?;
+
+    Ok(source
+        .starts_with(destination.canonicalize()?)
+        .then_some(source))
+}
+
+/// Resolve a relative link `target` from the canonical `base` directory, following the links present on disk.
+/// Returns `None` for absolute targets and for `..` components after a path that does not exist yet,
+/// since an entry extracted later could turn it into a link.
+fn resolve_on_disk(base: &Path, target: &Path) -> Option<PathBuf> {
+    let mut resolved = base.to_path_buf();
+    let mut missing = false;
+
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => {
+                resolved.push(part);
+
+                if !missing {
+                    match resolved.canonicalize() {
+                        Ok(canonical) => resolved = canonical,
+                        Err(_) => missing = true,
+                    }
+                }
+            }
+            Component::ParentDir => {
+                if missing || !resolved.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => {}
+            Component::Prefix(_) | Component::RootDir => return None,
+        }
+    }
+
+    Some(resolved)
+}
+
+/// Handle an entry that would escape the destination through a link as `Hazard::ExternalLink`
+/// Returns whether the entry may still be extracted
+fn external_link(name: &Path, policy: &ExtractPolicy, report: &mut ExtractReport) -> Result<bool> {
+    match policy.action(Hazard::ExternalLink) {
+        PolicyAction::Allow => Ok(true),
+        PolicyAction::Reject => bail!(ArchiveError::PolicyViolation {
+            entry: name.to_path_buf(),
+            hazard: Hazard::ExternalLink,
+        }),
+        PolicyAction::Skip | PolicyAction::Sanitize => {
+            report
+                .skipped
+                .push((name.to_path_buf(), Hazard::ExternalLink));
+            Ok(false)
+        }
+    }
+}
 
-use utils_box_logger::{log_info, log_trace};
+#[cfg(unix)]
+fn symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
 
-pub static GZ_SIGNATURE: [u8; 3] = [0x1F, 0x8B, 0x08];
-pub static ZIP_SIGNATURE: [u8; 3] = [0x50, 0x4B, 0x03];
+#[cfg(windows)]
+fn symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(source, target)
+}
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ArchiveType {
-    Tar,
-    Gz,
-    Zip,
+#[cfg(not(any(unix, windows)))]
+fn symlink(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::other("symbolic links are not supported"))
 }
 
 /// Entry Point:
-/// Extract the selected file from the provided archive into the selected destination directory
-/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
-pub fn archive_extract_file(
+/// Create a new archive of the selected type with all contents of the provided source directory
+/// The paths inside the archive are stored relative to the source directory
+/// Permissions and modification times of the files and directories are preserved
+/// Symbolic links are stored as links and never followed (plain `.gz` files hold the target)
+pub fn archive_create_all(
+    source: PathBuf,
+    archive_type: ArchiveType,
     archive: PathBuf,
-    filename: PathBuf,
-    destination: PathBuf,
-) -> Result<()> {
-    // Check archive type
-    let mut file = File::open(&archive)?;
-    let mut magic_number: Vec<u8> = vec![0x0; 3];
-
-    file.read_exact(&mut magic_number)?;
-
-    if magic_number == GZ_SIGNATURE {
-        log_info!("[archive_extract_file] Detected [tar.gz] archive");
-        extract_file(archive, ArchiveType::Gz, filename, destination)
-    } else if magic_number == ZIP_SIGNATURE {
-        log_info!("[archive_extract_all] Detected [zip] archive");
-        extract_file(archive, ArchiveType::Zip, filename, destination)
-    } else {
-        log_info!("[archive_extract_file] Detected [tar] archive");
-        extract_file(archive, ArchiveType::Tar, filename, destination)
-    }
+) -> Result<(), ArchiveError> {
+    Ok(create_all(
+        source,
+        archive_type,
+        archive,
+        None,
+        "archive_create_all",
+    )?)
 }
 
 /// Entry Point:
-/// Extract all contents from the provided archive into the selected destination directory
-/// The destination directory will be created if not already available
-/// It detects if it is a compressed archive using the magic numbers from: https://www.garykessler.net/library/file_sigs.html
-pub fn archive_extract_all(archive: PathBuf, destination: PathBuf) -> Result<()> {
-    // Check archive type
-    let mut file = File::open(&archive)?;
-    let mut magic_number: Vec<u8> = vec![0x0; 3];
-
-    file.read_exact(&mut magic_number)?;
-
-    if magic_number == GZ_SIGNATURE {
-        log_info!("[archive_extract_all] Detected [tar.gz] archive");
-        extract_all(archive, ArchiveType::Gz, destination)
-    } else if magic_number == ZIP_SIGNATURE {
-        log_info!("[archive_extract_all] Detected [zip] archive");
-        extract_all(archive, ArchiveType::Zip, destination)
-    } else {
-        log_info!("[archive_extract_all] Detected [tar.gz] archive");
-        extract_all(archive, ArchiveType::Tar, destination)
+/// Create a new archive of the selected type with the provided list of files
+/// Directories in the list are added together with all their contents
+/// The paths inside the archive are stored relative to the `base` directory
+/// Permissions and modification times of the files and directories are preserved
+/// Symbolic links are stored as links and never followed (plain `.gz` files hold the target)
+pub fn archive_create_files(
+    files: Vec<PathBuf>,
+    base: PathBuf,
+    archive_type: ArchiveType,
+    archive: PathBuf,
+) -> Result<(), ArchiveError> {
+    let mut entries: Vec<(PathBuf, PathBuf)> = vec![];
+
+    for file in files {
+        // Relative paths are considered relative to the base directory
+        let file = if file.is_relative() {
+            base.join(file)
+        } else {
+            file
+        };
+
+        // Symbolic links are stored as links, a dangling one is still a valid entry
+        let Ok(metadata) = std::fs::symlink_metadata(&file) else {
+            return Err(ArchiveError::NotFound(format!(
+                "[archive_create_files] Requested file [{}] does not exist!",
+                file.display()
+            )));
+        };
+
+        let name = match file.strip_prefix(&base) {
+            Ok(name) => name.to_path_buf(),
+            Err(_) => {
+                return Err(ArchiveError::InvalidInput(format!(
+                    "[archive_create_files] Requested file [{}] is not inside base directory [{}]!",
+                    file.display(),
+                    base.display()
+                )));
+            }
+        };
+
+        if name.as_os_str().is_empty() {
+            collect_entries(&base, &file, &mut entries)?;
+        } else if metadata.is_dir() {
+            entries.push((file.clone(), name));
+            collect_entries(&base, &file, &mut entries)?;
+        } else {
+            entries.push((file, name));
+        }
     }
+
+    log_info!(
+        "[archive_create_files] Packaging [{}] entries into [{}] archive",
+        entries.len(),
+        archive_type
+    );
+
+    Ok(create_archive(entries, archive_type, archive, None)?)
 }
 
-/// Extract the selected file from the provided archive into the selected destination directory
-fn extract_file(
+/// Entry Point:
+/// Create a new AES-256 encrypted zip archive with all contents of the provided source directory
+/// Only the contents of the files are encrypted, the names of the entries remain visible
+/// Other archive types do not support encryption and are rejected
+pub fn archive_create_all_with_password(
+    source: PathBuf,
+    archive_type: ArchiveType,
     archive: PathBuf,
+    password: &str,
+) -> Result<(), ArchiveError> {
+    Ok(create_all(
+        source,
+        archive_type,
+        archive,
+        Some(password),
+        "archive_create_all_with_password",
+    )?)
+}
+
+/// Create a new archive of the selected type with all contents of the source directory,
+/// encrypting the contents of the files if a password is provided (zip only)
+fn create_all(
+    source: PathBuf,
     archive_type: ArchiveType,
-    filename: PathBuf,
-    destination: PathBuf,
+    archive: PathBuf,
+    password: Option<&str>,
+    caller: &str,
 ) -> Result<()> {
-    match archive_type {
-        ArchiveType::Tar => {
-            let mut ar = Archive::new(File::open(archive)?);
+    if password.is_some() && archive_type != ArchiveType::Zip {
+        bail!(ArchiveError::UnsupportedFormat(format!(
+            "[{}][{}] Encryption is only supported for zip archives!",
+            caller, archive_type
+        )));
+    }
 
-            // Check the contents for the requested file
-            for archived_file in ar.entries()? {
-                // Unwrap the file
-                let mut ar_file = archived_file?;
+    if !source.is_dir() {
+        bail!(ArchiveError::InvalidInput(format!(
+            "[{}] Source [{}] is not a directory!",
+            caller,
+            source.display()
+        )));
+    }
 
-                // Check if it is the file we need
-                if ar_file.path()? == filename {
-                    let _ = std::fs::create_dir(destination.clone());
-                    ar_file.unpack_in(&destination)?;
+    let mut entries: Vec<(PathBuf, PathBuf)> = vec![];
+    collect_entries(&source, &source, &mut entries)?;
 
-                    return Ok(());
-                }
-            }
+    log_info!(
+        "[{}] Packaging [{}] entries from [{}] into {}[{}] archive",
+        caller,
+        entries.len(),
+        source.display(),
+        if password.is_some() { "encrypted " } else { "" },
+        archive_type
+    );
 
-            bail!("[extract_file][tar] Failed to find requested file!");
-        }
-        ArchiveType::Gz => {
-            let file = File::open(archive)?;
-            let decompressed = GzDecoder::new(file);
-            let mut ar = Archive::new(decompressed);
+    create_archive(entries, archive_type, archive, password)
+}
 
-            // Check the contents for the requested file
-            for archived_file in ar.entries()? {
-                // Unwrap the file
-                let mut ar_file = archived_file?;
+/// Walk the selected directory recursively and collect `(path on disk, path inside archive)` pairs
+/// Symbolic links are collected as entries but never followed
+/// Entries are sorted so that the generated archives are reproducible
+fn collect_entries(base: &Path, dir: &Path, entries: &mut Vec<(PathBuf, PathBuf)>) -> Result<()> {
+    let mut dir_entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
 
-                // Check if it is the file we need
-                if ar_file.path()? == filename {
-                    let _ = std::fs::create_dir(destination.clone());
-                    ar_file.unpack_in(&destination)?;
+    dir_entries.sort();
 
-                    return Ok(());
-                }
-            }
+    for path in dir_entries {
+        let name = path.strip_prefix(base)?.to_path_buf();
 
-            bail!("[extract_file][gz] Failed to find requested file!");
+        if std::fs::symlink_metadata(&path)?.is_dir() {
+            entries.push((path.clone(), name));
+            collect_entries(base, &path, entries)?;
+        } else {
+            entries.push((path, name));
         }
-        ArchiveType::Zip => {
-            let file = File::open(archive)?;
-            let mut ar = ZipArchive::new(file)?;
-
-            for i in 0..ar.len() {
-                let mut in_file = ar.by_index(i)?;
-                let outpath = match in_file.enclosed_name() {
-                    Some(path) => path,
-                    None => {
-                        log_trace!("Entry {} has a suspicious path", in_file.name());
-                        continue;
-                    }
-                };
-
-                if outpath.file_name() == Some(filename.as_os_str()) {
-                    let _ = std::fs::create_dir(destination.clone());
-                    let mut outfile = File::create(destination.join(filename))?;
-                    io::copy(&mut in_file, &mut outfile)?;
-
-                    return Ok(());
-                }
-            }
+    }
 
-            bail!("[extract_file][zip] Failed to find requested file !");
-        }
-    };
+    Ok(())
 }
 
-/// Extract all contents from the provided archive into the selected destination directory
-/// The destination directory will be created if not already available
-fn extract_all(archive: PathBuf, archive_type: ArchiveType, destination: PathBuf) -> Result<()> {
+/// Write the collected entries into a new archive of the selected type
+fn create_archive(
+    entries: Vec<(PathBuf, PathBuf)>,
+    archive_type: ArchiveType,
+    archive: PathBuf,
+    password: Option<&str>,
+) -> Result<()> {
     match archive_type {
         ArchiveType::Tar => {
-            let mut ar = Archive::new(File::open(archive)?);
-            let _ = std::fs::create_dir(destination.clone());
-            ar.unpack(destination)?;
+            write_tar(&entries, File::create(archive)?)?.flush()?;
         }
         ArchiveType::Gz => {
-            let file = File::open(archive)?;
-            let decompressed = GzDecoder::new(file);
-            let mut ar = Archive::new(decompressed);
-            let _ = std::fs::create_dir(destination.clone());
-            ar.unpack(destination)?;
+            let compressed = GzEncoder::new(File::create(archive)?, Compression::default());
+            write_tar(&entries, compressed)?.finish()?.flush()?;
+        }
+        ArchiveType::Xz => xz_create(&entries, File::create(archive)?)?,
+        ArchiveType::Bz2 => bz2_create(&entries, File::create(archive)?)?,
+        ArchiveType::Zst => zst_create(&entries, File::create(archive)?)?,
+        ArchiveType::GzFile => {
+            let (path, name) = match entries.as_slice() {
+                [(path, name)] if path.is_file() => (path, name),
+                _ => bail!(ArchiveError::InvalidInput(
+                    "[create_archive][gz] Plain .gz files can hold exactly one file!".to_string()
+                )),
+            };
+
+            let mtime = std::fs::metadata(path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs() as u32)
+                .unwrap_or_default();
+
+            let mut compressed = flate2::GzBuilder::new()
+                .filename(name.to_string_lossy().as_bytes())
+                .mtime(mtime)
+                .write(File::create(archive)?, Compression::default());
+
+            io::copy(&mut File::open(path)?, &mut compressed)?;
+            compressed.finish()?.flush()?;
         }
         ArchiveType::Zip => {
-            let file = File::open(archive)?;
-            let mut ar = ZipArchive::new(file)?;
-            let _ = std::fs::create_dir(destination.clone());
-            ar.extract(destination)?;
+            let mut zip = ZipWriter::new(File::create(archive)?);
+
+            for (path, name) in entries.iter() {
+                let metadata = std::fs::symlink_metadata(path)?;
+                let name = zip_entry_name(name)?;
+
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .unix_permissions(unix_mode(&metadata))
+                    .last_modified_time(zip_date_time(metadata.modified()?))
+                    .large_file(metadata.len() > u32::MAX as u64);
+
+                if metadata.is_dir() {
+                    zip.add_directory(name, options)?;
+                } else if metadata.is_symlink() {
+                    let target = std::fs::read_link(path)?;
+                    zip.add_symlink(name, target.to_string_lossy(), options)?;
+                } else if let Some(password) = password {
+                    zip.start_file(name, options.with_aes_encryption(AesMode::Aes256, password))?;
+                    io::copy(&mut File::open(path)?, &mut zip)?;
+                } else {
+                    zip.start_file(name, options)?;
+                    io::copy(&mut File::open(path)?, &mut zip)?;
+                }
+            }
+
+            zip.finish()?.flush()?;
         }
     }
 
     Ok(())
 }
 
+/// Append all entries in a tar stream and return the underlying writer
+fn write_tar<W: Write>(entries: &[(PathBuf, PathBuf)], writer: W) -> Result<W> {
+    let mut builder = Builder::new(writer);
+    builder.follow_symlinks(false);
+
+    for (path, name) in entries.iter() {
+        builder.append_path_with_name(path, name)?;
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+#[cfg(feature = "xz")]
+fn xz_create(entries: &[(PathBuf, PathBuf)], file: File) -> Result<()> {
+    let compressed = xz2::write::XzEncoder::new(file, 6);
+    write_tar(entries, compressed)?.finish()?.flush()?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "xz"))]
+fn xz_create(_entries: &[(PathBuf, PathBuf)], _file: File) -> Result<()> {
+    bail!(ArchiveError::UnsupportedFormat(
+        "[xz_create] Support for [tar.xz] archives requires the `xz` feature!".to_string()
+    ))
+}
+
+#[cfg(feature = "bzip2")]
+fn bz2_create(entries: &[(PathBuf, PathBuf)], file: File) -> Result<()> {
+    let compressed = bzip2::write::BzEncoder::new(file, bzip2::Compression::default());
+    write_tar(entries, compressed)?.finish()?.flush()?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "bzip2"))]
+fn bz2_create(_entries: &[(PathBuf, PathBuf)], _file: File) -> Result<()> {
+    bail!(ArchiveError::UnsupportedFormat(
+        "[bz2_create] Support for [tar.bz2] archives requires the `bzip2` feature!".to_string()
+    ))
+}
+
+#[cfg(feature = "zstd")]
+fn zst_create(entries: &[(PathBuf, PathBuf)], file: File) -> Result<()> {
+    let compressed = zstd::stream::write::Encoder::new(file, 0)?;
+    write_tar(entries, compressed)?.finish()?.flush()?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zst_create(_entries: &[(PathBuf, PathBuf)], _file: File) -> Result<()> {
+    bail!(ArchiveError::UnsupportedFormat(
+        "[zst_create] Support for [tar.zst] archives requires the `zstd` feature!".to_string()
+    ))
+}
+
+/// Zip entries always use `/` as separator regardless of the platform
+fn zip_entry_name(name: &Path) -> Result<String> {
+    let mut parts: Vec<String> = vec![];
+
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            _ => bail!(ArchiveError::InvalidInput(format!(
+                "[zip_entry_name] Entry [{}] is not a plain relative path!",
+                name.display()
+            ))),
+        }
+    }
+
+    Ok(parts.join("/"))
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn unix_mode(metadata: &Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+/// Convert a system timestamp into the MS-DOS date & time stored in zip entries (UTC)
+/// Timestamps before 1980 cannot be represented and fall back to the zip default
+fn zip_date_time(modified: SystemTime) -> DateTime {
+    let secs = match modified.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => return DateTime::default(),
+    };
+
+    // Civil date from days since epoch: <https://howardhinnant.github.io/date_algorithms.html>
+    let days = secs.div_euclid(86_400);
+    let secs_of_day = secs.rem_euclid(86_400);
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    DateTime::from_date_and_time(
+        year.clamp(0, u16::MAX as i64) as u16,
+        month as u8,
+        day as u8,
+        (secs_of_day / 3_600) as u8,
+        (secs_of_day % 3_600 / 60) as u8,
+        (secs_of_day % 60) as u8,
+    )
+    .unwrap_or_default()
+}
+
+/// Convert the MS-DOS date & time stored in zip entries (UTC) into seconds since the Unix epoch
+fn zip_unix_time(date_time: DateTime) -> Option<u64> {
+    // Days since epoch from civil date: <https://howardhinnant.github.io/date_algorithms.html>
+    let year = i64::from(date_time.year()) - i64::from(date_time.month() <= 2);
+    let month = i64::from(date_time.month());
+    let day = i64::from(date_time.day());
+
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400
+        + i64::from(date_time.hour()) * 3_600
+        + i64::from(date_time.minute()) * 60
+        + i64::from(date_time.second());
+
+    u64::try_from(secs).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::archives::*;
@@ -325,4 +2051,672 @@ mod tests {
 
         archive_extract_all(archive[0].clone(), destination).unwrap();
     }
+
+    /// Extract the tar fixture, package it again with the requested type and extract the new archive
+    fn create_round_trip(archive_type: ArchiveType, archive_name: &str) {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let archive = paths.search_glob("test_archives.tar");
+
+        // Create a named lock to make sure no other test messes with the same files!
+        let lock = NamedLock::create("archives_tests").unwrap();
+        let _guard = lock.lock().unwrap();
+
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        extract_all(
+            archive[0].clone(),
+            ArchiveType::Tar,
+            source.path().to_path_buf(),
+        )
+        .unwrap();
+
+        let created = destination.path().join(archive_name);
+
+        archive_create_all(source.path().to_path_buf(), archive_type, created.clone()).unwrap();
+
+        let extracted = destination.path().join("extracted");
+        archive_extract_all(created, extracted.clone()).unwrap();
+
+        for file in ["happy_cloud.jpg", "sad_cloud.jpg"] {
+            let original = std::fs::read(source.path().join(file)).unwrap();
+            let round_trip = std::fs::read(extracted.join(file)).unwrap();
+
+            assert_eq!(original, round_trip);
+        }
+    }
+
+    #[test]
+    fn archive_create_all_tar_test() {
+        create_round_trip(ArchiveType::Tar, "round_trip.tar");
+    }
+
+    #[test]
+    fn archive_create_all_targz_test() {
+        create_round_trip(ArchiveType::Gz, "round_trip.tar.gz");
+    }
+
+    #[test]
+    fn archive_create_all_zip_test() {
+        create_round_trip(ArchiveType::Zip, "round_trip.zip");
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn archive_create_all_tarxz_test() {
+        create_round_trip(ArchiveType::Xz, "round_trip.tar.xz");
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn archive_create_all_tarbz2_test() {
+        create_round_trip(ArchiveType::Bz2, "round_trip.tar.bz2");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn archive_create_all_tarzst_test() {
+        create_round_trip(ArchiveType::Zst, "round_trip.tar.zst");
+    }
+
+    #[cfg(not(feature = "xz"))]
+    #[test]
+    fn archive_xz_feature_disabled_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("bundle.tar.xz");
+
+        // Only the magic number is needed for detection
+        std::fs::write(&archive, XZ_SIGNATURE).unwrap();
+
+        let error = archive_extract_all(archive, dir.path().join("out")).unwrap_err();
+        assert!(error.to_string().contains("`xz` feature"));
+    }
+
+    #[test]
+    fn archive_gz_file_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let dump = dir.path().join("calibration.csv");
+        std::fs::write(&dump, "x,y\n1,2\n").unwrap();
+
+        // The original name is kept in the gzip header
+        let archive = dir.path().join("dump.gz");
+        archive_create_files(
+            vec![dump.clone()],
+            dir.path().to_path_buf(),
+            ArchiveType::GzFile,
+            archive.clone(),
+        )
+        .unwrap();
+
+        let entries = archive_list(archive.clone()).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(PathBuf::from("calibration.csv"), entries[0].path);
+        assert_eq!(8, entries[0].size);
+
+        archive_extract_all(archive, dir.path().join("out")).unwrap();
+        assert_eq!(
+            "x,y\n1,2\n",
+            std::fs::read_to_string(dir.path().join("out/calibration.csv")).unwrap()
+        );
+
+        // Without a name in the header the archive name without `.gz` is used
+        let archive = dir.path().join("readings.txt.gz");
+        let mut encoder = GzEncoder::new(File::create(&archive).unwrap(), Compression::default());
+        encoder.write_all(b"42").unwrap();
+        encoder.finish().unwrap();
+
+        archive_extract_file(archive, "readings.txt".into(), dir.path().join("out")).unwrap();
+        assert_eq!(
+            "42",
+            std::fs::read_to_string(dir.path().join("out/readings.txt")).unwrap()
+        );
+    }
+
+    #[test]
+    fn archive_unknown_format_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("notes.txt");
+        std::fs::write(&archive, "definitely not an archive").unwrap();
+
+        assert!(archive_list(archive.clone()).is_err());
+        assert!(archive_extract_all(archive, dir.path().join("out")).is_err());
+    }
+
+    #[test]
+    fn archive_create_files_test() {
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        std::fs::create_dir_all(source.path().join("logs/2024")).unwrap();
+        std::fs::write(source.path().join("logs/2024/app.log"), "boot ok").unwrap();
+        std::fs::write(source.path().join("calibration.bin"), [0xCA, 0xFE]).unwrap();
+        std::fs::write(source.path().join("ignored.txt"), "not packaged").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(
+                source.path().join("calibration.bin"),
+                std::fs::Permissions::from_mode(0o640),
+            )
+            .unwrap();
+        }
+
+        let mtime = std::fs::metadata(source.path().join("calibration.bin"))
+            .unwrap()
+            .modified()
+            .unwrap();
+
+        for (archive_type, name) in [
+            (ArchiveType::Tar, "files.tar"),
+            (ArchiveType::Gz, "files.tar.gz"),
+            (ArchiveType::Zip, "files.zip"),
+        ] {
+            let created = destination.path().join(name);
+
+            archive_create_files(
+                vec!["logs".into(), source.path().join("calibration.bin")],
+                source.path().to_path_buf(),
+                archive_type.clone(),
+                created.clone(),
+            )
+            .unwrap();
+
+            let extracted = destination.path().join(format!("{name}_extracted"));
+            archive_extract_all(created, extracted.clone()).unwrap();
+
+            assert_eq!(
+                "boot ok",
+                std::fs::read_to_string(extracted.join("logs/2024/app.log")).unwrap()
+            );
+            assert_eq!(
+                vec![0xCA, 0xFE],
+                std::fs::read(extracted.join("calibration.bin")).unwrap()
+            );
+            assert!(!extracted.join("ignored.txt").exists());
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let metadata = std::fs::metadata(extracted.join("calibration.bin")).unwrap();
+                assert_eq!(0o640, metadata.permissions().mode() & 0o777);
+            }
+
+            // Tar headers keep the modification time with a precision of seconds, zip entries of two seconds
+            let precision = match archive_type {
+                ArchiveType::Zip => 2,
+                _ => 1,
+            };
+            let extracted_mtime = std::fs::metadata(extracted.join("calibration.bin"))
+                .unwrap()
+                .modified()
+                .unwrap();
+            let secs = mtime.duration_since(UNIX_EPOCH).unwrap().as_secs();
+            assert_eq!(
+                secs - secs % precision,
+                extracted_mtime
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs()
+            );
+        }
+
+        // Files outside of the base directory cannot be stored with a relative path
+        let outside = destination.path().join("outside.txt");
+        std::fs::write(&outside, "outside").unwrap();
+
+        assert!(
+            archive_create_files(
+                vec![outside],
+                source.path().to_path_buf(),
+                ArchiveType::Tar,
+                destination.path().join("outside.tar"),
+            )
+            .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archive_create_symlinks_test() {
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        // Links to an ancestor, to the root and to themselves must not be followed
+        let data = source.path().join("data");
+        std::fs::create_dir(&data).unwrap();
+        std::fs::write(data.join("app.log"), "boot ok").unwrap();
+        std::os::unix::fs::symlink("..", data.join("parent")).unwrap();
+        std::os::unix::fs::symlink("/", data.join("root")).unwrap();
+        std::os::unix::fs::symlink("loop", data.join("loop")).unwrap();
+
+        for (archive_type, name) in [
+            (ArchiveType::Tar, "links.tar"),
+            (ArchiveType::Gz, "links.tar.gz"),
+            (ArchiveType::Zip, "links.zip"),
+        ] {
+            let created = destination.path().join(name);
+            archive_create_all(
+                source.path().to_path_buf(),
+                archive_type.clone(),
+                created.clone(),
+            )
+            .unwrap();
+
+            let entries: Vec<(String, EntryKind)> = archive_list(created)
+                .unwrap()
+                .into_iter()
+                .map(|entry| {
+                    let path = entry
+                        .path
+                        .to_string_lossy()
+                        .trim_end_matches('/')
+                        .to_string();
+                    (path, entry.kind)
+                })
+                .collect();
+
+            assert_eq!(
+                vec![
+                    ("data".to_string(), EntryKind::Directory),
+                    ("data/app.log".to_string(), EntryKind::File),
+                    ("data/loop".to_string(), EntryKind::Symlink),
+                    ("data/parent".to_string(), EntryKind::Symlink),
+                    ("data/root".to_string(), EntryKind::Symlink),
+                ],
+                entries,
+                "{archive_type}"
+            );
+        }
+
+        // Requested links are stored as links as well
+        let created = destination.path().join("parent.tar");
+        archive_create_files(
+            vec!["data/parent".into()],
+            source.path().to_path_buf(),
+            ArchiveType::Tar,
+            created.clone(),
+        )
+        .unwrap();
+
+        let entries = archive_list(created).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(EntryKind::Symlink, entries[0].kind);
+        assert_eq!(Some(PathBuf::from("..")), entries[0].link);
+    }
+
+    #[test]
+    fn zip_date_time_test() {
+        let modified = UNIX_EPOCH + std::time::Duration::from_secs(1_696_064_020);
+        let expected = DateTime::from_date_and_time(2023, 9, 30, 8, 53, 40).unwrap();
+
+        let converted = zip_date_time(modified);
+
+        assert_eq!(expected.datepart(), converted.datepart());
+        assert_eq!(expected.timepart(), converted.timepart());
+        assert_eq!(
+            DateTime::default().datepart(),
+            zip_date_time(UNIX_EPOCH).datepart()
+        );
+        assert_eq!(Some(1_696_064_020), zip_unix_time(converted));
+    }
+
+    #[test]
+    fn archive_extract_matching_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let targz = paths.search_glob("test_archives.tar.gz")[0].clone();
+        let zip = paths.search_glob("test_archives.zip")[0].clone();
+
+        let destination = tempfile::tempdir().unwrap();
+
+        // Keep the directory structure of the archive
+        let preserved = destination.path().join("preserved");
+        let mut extracted = archive_extract_matching(
+            targz.clone(),
+            EntrySelector::glob("*.jpg").unwrap(),
+            preserved.clone(),
+            false,
+        )
+        .unwrap();
+        extracted.sort();
+
+        assert_eq!(
+            vec![
+                preserved.join("test_archives/happy_cloud.jpg"),
+                preserved.join("test_archives/sad_cloud.jpg"),
+            ],
+            extracted
+        );
+        assert!(!preserved.join("test_archives/test.txt").exists());
+
+        // Drop the parent directories
+        let flattened = destination.path().join("flattened");
+        let extracted = archive_extract_matching(
+            targz,
+            EntrySelector::regex(r"^test_archives/.*\.txt$").unwrap(),
+            flattened.clone(),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(vec![flattened.join("test.txt")], extracted);
+        assert!(flattened.join("test.txt").is_file());
+
+        let extracted = archive_extract_matching(
+            zip,
+            EntrySelector::glob("*_cloud.*").unwrap(),
+            destination.path().join("zip"),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(2, extracted.len());
+        assert!(extracted.iter().all(|path| path.exists()));
+    }
+
+    #[test]
+    fn archive_list_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let tar = archive_list(paths.search_glob("test_archives.tar")[0].clone()).unwrap();
+
+        assert_eq!(2, tar.len());
+        assert_eq!(PathBuf::from("happy_cloud.jpg"), tar[0].path);
+        assert_eq!(65789, tar[0].size);
+        assert_eq!(Some(0o777), tar[0].mode);
+        assert_eq!(EntryKind::File, tar[0].kind);
+        assert_eq!(None, tar[0].crc32);
+
+        let targz = archive_list(paths.search_glob("test_archives.tar.gz")[0].clone()).unwrap();
+
+        assert_eq!(4, targz.len());
+        assert_eq!(PathBuf::from("test_archives/"), targz[0].path);
+        assert_eq!(EntryKind::Directory, targz[0].kind);
+        assert_eq!(PathBuf::from("test_archives/test.txt"), targz[3].path);
+        assert_eq!(37, targz[3].size);
+
+        let zip = archive_list(paths.search_glob("test_archives.zip")[0].clone()).unwrap();
+
+        assert_eq!(3, zip.len());
+        assert_eq!(PathBuf::from("lorem.txt"), zip[1].path);
+        assert_eq!(3821, zip[1].size);
+        assert_eq!(Some(1363), zip[1].compressed_size);
+        assert_eq!(Some(0xbc438c00), zip[1].crc32);
+        assert_eq!(EntryKind::File, zip[1].kind);
+        assert!(zip[1].mtime.is_some());
+    }
+
+    #[test]
+    fn archive_extract_all_with_progress_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let dir = tempfile::tempdir().unwrap();
+
+        for name in ["test_archives.tar.gz", "test_archives.zip"] {
+            let archive = paths.search_glob(name)[0].clone();
+            let size = std::fs::metadata(&archive).unwrap().len();
+
+            let mut updates: Vec<ExtractProgress> = vec![];
+            let report = archive_extract_all_with_progress(
+                archive,
+                dir.path().join(name),
+                &ExtractPolicy::default(),
+                |progress| updates.push(progress.clone()),
+                &CancelToken::new(),
+            )
+            .unwrap();
+
+            let last = updates.last().unwrap();
+            assert_eq!(report.extracted.len() as u64, last.entries);
+            assert_eq!(Some(size), last.bytes_total);
+            assert!(last.bytes > 0 && last.bytes <= size);
+            assert!(updates.windows(2).all(|w| w[0].bytes <= w[1].bytes));
+        }
+    }
+
+    #[test]
+    fn archive_extract_all_cancel_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let archive = paths.search_glob("test_archives.tar")[0].clone();
+        let dir = tempfile::tempdir().unwrap();
+
+        // Cancel in the middle of the first entry, the new destination is removed completely
+        let destination = dir.path().join("new");
+        let cancel = CancelToken::new();
+        let error = archive_extract_all_with_progress(
+            archive.clone(),
+            destination.clone(),
+            &ExtractPolicy::default(),
+            |progress| {
+                if progress.bytes > 20000 {
+                    cancel.cancel();
+                }
+            },
+            &cancel,
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().contains("cancelled"));
+        assert!(!destination.exists());
+
+        // Cancel after the first entry, files that were already in the destination are kept
+        let destination = dir.path().join("existing");
+        std::fs::create_dir(&destination).unwrap();
+        std::fs::write(destination.join("keep.txt"), "keep").unwrap();
+
+        let cancel = CancelToken::new();
+        let result = archive_extract_all_with_progress(
+            archive,
+            destination.clone(),
+            &ExtractPolicy::default(),
+            |progress| {
+                if progress.entries == 1 {
+                    cancel.cancel();
+                }
+            },
+            &cancel,
+        );
+
+        assert!(result.is_err());
+        assert!(destination.join("keep.txt").exists());
+        assert!(!destination.join("happy_cloud.jpg").exists());
+        assert!(!destination.join("sad_cloud.jpg").exists());
+    }
+
+    #[test]
+    fn archive_encrypted_zip_test() {
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+
+        std::fs::write(source.path().join("firmware.hex"), ":00000001FF").unwrap();
+
+        let archive = destination.path().join("package.zip");
+        archive_create_all_with_password(
+            source.path().to_path_buf(),
+            ArchiveType::Zip,
+            archive.clone(),
+            "s3cr3t",
+        )
+        .unwrap();
+
+        // AES encrypted entries
+        let report = archive_extract_all_with_password(
+            archive.clone(),
+            destination.path().join("aes"),
+            "s3cr3t",
+        )
+        .unwrap();
+        assert_eq!(1, report.extracted.len());
+        assert_eq!(
+            ":00000001FF",
+            std::fs::read_to_string(destination.path().join("aes/firmware.hex")).unwrap()
+        );
+
+        let error = archive_extract_all_with_password(
+            archive.clone(),
+            destination.path().join("wrong"),
+            "guess",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("Wrong password"));
+
+        let error =
+            archive_extract_all(archive.clone(), destination.path().join("none")).unwrap_err();
+        assert!(error.to_string().contains("password is required"));
+
+        // Other archive types cannot be encrypted
+        assert!(
+            archive_create_all_with_password(
+                source.path().to_path_buf(),
+                ArchiveType::Gz,
+                destination.path().join("package.tar.gz"),
+                "s3cr3t",
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn archive_extract_matching_encrypted_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Plain documentation next to an encrypted service configuration
+        let archive = dir.path().join("package.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("docs/readme.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"read me").unwrap();
+        zip.start_file(
+            "service/service.ini",
+            SimpleFileOptions::default().with_aes_encryption(AesMode::Aes256, "s3cr3t"),
+        )
+        .unwrap();
+        zip.write_all(b"station_id=42").unwrap();
+        zip.finish().unwrap();
+
+        // Encrypted entries that are not selected are never opened
+        let extracted = archive_extract_matching(
+            archive.clone(),
+            EntrySelector::glob("*.txt").unwrap(),
+            dir.path().join("plain"),
+            true,
+        )
+        .unwrap();
+        assert_eq!(vec![dir.path().join("plain/readme.txt")], extracted);
+
+        assert!(matches!(
+            archive_extract_matching(
+                archive.clone(),
+                EntrySelector::glob("*.ini").unwrap(),
+                dir.path().join("missing"),
+                true,
+            ),
+            Err(ArchiveError::Password(_))
+        ));
+
+        let extracted = archive_extract_matching_with_password(
+            archive,
+            EntrySelector::glob("service/*").unwrap(),
+            dir.path().join("service"),
+            false,
+            "s3cr3t",
+        )
+        .unwrap();
+        assert_eq!(
+            vec![dir.path().join("service/service/service.ini")],
+            extracted
+        );
+        assert_eq!(
+            "station_id=42",
+            std::fs::read_to_string(&extracted[0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn extract_all_selected_encrypted_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let archive = dir.path().join("bundle.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("app.bin", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"v2").unwrap();
+        zip.start_file(
+            "secret.ini",
+            SimpleFileOptions::default().with_aes_encryption(AesMode::Aes256, "s3cr3t"),
+        )
+        .unwrap();
+        zip.write_all(b"key=1").unwrap();
+        zip.finish().unwrap();
+
+        // The excluded encrypted entry is never opened, but still counted
+        let mut entries = 0;
+        let report = extract_all_with_progress(
+            archive,
+            ArchiveType::Zip,
+            dir.path().join("out"),
+            &ExtractPolicy::default(),
+            None,
+            &mut |progress| entries = progress.entries,
+            &CancelToken::new(),
+            &|name| name != Path::new("secret.ini"),
+        )
+        .unwrap();
+
+        assert_eq!(vec![PathBuf::from("app.bin")], report.extracted);
+        assert_eq!(2, entries);
+        assert!(!dir.path().join("out/secret.ini").exists());
+    }
+
+    #[test]
+    fn archive_zipcrypto_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let archive = paths.search_glob("test_encrypted.zip")[0].clone();
+        let destination = tempfile::tempdir().unwrap();
+
+        archive_extract_file_with_password(
+            archive.clone(),
+            "service.ini".into(),
+            destination.path().to_path_buf(),
+            "field-service",
+        )
+        .unwrap();
+        assert_eq!(
+            "station_id=42\nservice_mode=true\n",
+            std::fs::read_to_string(destination.path().join("service.ini")).unwrap()
+        );
+
+        // ZipCrypto can only detect most wrong passwords up front, the rest fail on the CRC32
+        assert!(
+            archive_extract_file_with_password(
+                archive,
+                "service.ini".into(),
+                destination.path().join("wrong"),
+                "not-the-password",
+            )
+            .is_err()
+        );
+    }
 }
@@ -0,0 +1,228 @@
+//! # Async archives utilities
+//! Awaitable variants of the archive utilities for tokio based applications (requires the `tokio` feature).
+//! The blocking work runs on the blocking thread pool of the runtime, so the reactor threads are never stalled.
+//! Dropping an extraction future (e.g. when it loses a `tokio::select!` against a shutdown signal)
+//! cancels the extraction and removes its partial output.
+
+use anyhow::anyhow;
+use std::path::PathBuf;
+
+use crate::archives::{
+    ArchiveEntry, archive_extract_file, archive_list, detect_archive_type,
+    extract_all_with_progress,
+};
+use crate::error::ArchiveError;
+use crate::policy::{ExtractPolicy, ExtractReport};
+use crate::progress::{CancelToken, ExtractProgress};
+use crate::readers::archive_read_file;
+
+/// Entry Point:
+/// Extract the selected file from the provided archive into the selected destination directory
+/// Async variant of `archive_extract_file`
+pub async fn archive_extract_file_async(
+    archive: PathBuf,
+    filename: PathBuf,
+    destination: PathBuf,
+) -> Result<(), ArchiveError> {
+    blocking("archive_extract_file_async", move || {
+        archive_extract_file(archive, filename, destination)
+    })
+    .await
+}
+
+/// Entry Point:
+/// Extract all contents from the provided archive into the selected destination directory
+/// Async variant of `archive_extract_all`
+pub async fn archive_extract_all_async(
+    archive: PathBuf,
+    destination: PathBuf,
+) -> Result<(), ArchiveError> {
+    archive_extract_all_with_policy_async(archive, destination, ExtractPolicy::default()).await?;
+
+    Ok(())
+}
+
+/// Entry Point:
+/// Extract all contents from the provided archive into the selected destination directory
+/// applying the provided `ExtractPolicy`
+/// Async variant of `archive_extract_all_with_policy`
+pub async fn archive_extract_all_with_policy_async(
+    archive: PathBuf,
+    destination: PathBuf,
+    policy: ExtractPolicy,
+) -> Result<ExtractReport, ArchiveError> {
+    archive_extract_all_with_progress_async(
+        archive,
+        destination,
+        policy,
+        |_| {},
+        CancelToken::new(),
+    )
+    .await
+}
+
+/// Entry Point:
+/// Extract all contents from the provided archive into the selected destination directory
+/// applying the provided `ExtractPolicy` and reporting the progress to the callback
+/// Dropping the future before it completes cancels the provided token
+/// Async variant of `archive_extract_all_with_progress`
+pub async fn archive_extract_all_with_progress_async(
+    archive: PathBuf,
+    destination: PathBuf,
+    policy: ExtractPolicy,
+    mut progress: impl FnMut(&ExtractProgress) + Send + 'static,
+    cancel: CancelToken,
+) -> Result<ExtractReport, ArchiveError> {
+    let guard = CancelOnDrop(Some(cancel.clone()));
+
+    let report = blocking("archive_extract_all_with_progress_async", move || {
+        let archive_type =
+            detect_archive_type(&archive, "archive_extract_all_with_progress_async")?;
+
+        Ok(extract_all_with_progress(
+            archive,
+            archive_type,
+            destination,
+            &policy,
+            None,
+            &mut progress,
+            &cancel,
+            &|_| true,
+        )?)
+    })
+    .await;
+
+    guard.disarm();
+
+    report
+}
+
+/// Entry Point:
+/// List the entries of the provided archive without extracting anything on disk
+/// Async variant of `archive_list`
+pub async fn archive_list_async(archive: PathBuf) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+    blocking("archive_list_async", move || archive_list(archive)).await
+}
+
+/// Entry Point:
+/// Read the selected file from the provided archive into a byte buffer
+/// Async variant of `archive_read_file`
+pub async fn archive_read_file_async(
+    archive: PathBuf,
+    filename: PathBuf,
+) -> Result<Vec<u8>, ArchiveError> {
+    blocking("archive_read_file_async", move || {
+        archive_read_file(archive, filename)
+    })
+    .await
+}
+
+/// Run the blocking task on the blocking thread pool of the runtime and wait for its result
+async fn blocking<T: Send + 'static>(
+    caller: &str,
+    task: impl FnOnce() -> Result<T, ArchiveError> + Send + 'static,
+) -> Result<T, ArchiveError> {
+    match tokio::task::spawn_blocking(task).await {
+        Ok(result) => result,
+        Err(e) => Err(ArchiveError::Other(anyhow!(
+            "[{}] Blocking task failed: {}",
+            caller,
+            e
+        ))),
+    }
+}
+
+/// Cancels the extraction if the future is dropped before it completes
+struct CancelOnDrop(Option<CancelToken>);
+
+impl CancelOnDrop {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancel) = &self.0 {
+            cancel.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_archives::*;
+    use std::time::Duration;
+    use utils_box_pathfinder::paths::IncludePathsBuilder;
+
+    #[tokio::test]
+    async fn archive_extract_all_async_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let archive = paths.search_glob("test_archives.tar.gz")[0].clone();
+        let destination = tempfile::tempdir().unwrap();
+
+        archive_extract_all_async(archive.clone(), destination.path().to_path_buf())
+            .await
+            .unwrap();
+        assert!(destination.path().join("test_archives/test.txt").exists());
+
+        let entries = archive_list_async(archive.clone()).await.unwrap();
+        assert_eq!(4, entries.len());
+
+        let text = archive_read_file_async(archive.clone(), "test_archives/test.txt".into())
+            .await
+            .unwrap();
+        assert_eq!(37, text.len());
+
+        let unknown = destination.path().join("test_archives/test.txt");
+        assert!(matches!(
+            archive_extract_all_async(unknown, destination.path().join("out")).await,
+            Err(ArchiveError::UnsupportedFormat(_))
+        ));
+
+        let missing = archive_read_file_async(archive, "missing.txt".into()).await;
+        assert!(matches!(missing, Err(ArchiveError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn archive_extract_all_async_dropped_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let archive = paths.search_glob("test_archives.tar")[0].clone();
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("dropped");
+        let cancel = CancelToken::new();
+
+        // Slow down the extraction so that the timeout always wins the race
+        let extraction = archive_extract_all_with_progress_async(
+            archive,
+            destination.clone(),
+            ExtractPolicy::default(),
+            |_| std::thread::sleep(Duration::from_millis(20)),
+            cancel.clone(),
+        );
+
+        tokio::select! {
+            _ = extraction => panic!("The extraction should not complete!"),
+            _ = tokio::time::sleep(Duration::from_millis(50)) => {}
+        }
+
+        assert!(cancel.is_cancelled());
+
+        // The blocking task removes the partial output once it notices the cancellation
+        for _ in 0..100 {
+            if !destination.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!destination.exists());
+    }
+}
@@ -0,0 +1,366 @@
+//! # Atomic extraction utilities
+//! Extract archives without ever leaving a half-populated destination behind.
+//! The archive is extracted in a sibling staging directory, synced to disk and then moved into place,
+//! while the previous contents are kept in a rollback slot that can be restored with one call.
+//! On Linux the contents are swapped with a single `renameat2(RENAME_EXCHANGE)`, elsewhere (or on filesystems
+//! without support for it) with two renames. The parent directory is synced after every rename, and a swap
+//! interrupted between the two renames is recovered by the next extraction.
+
+use anyhow::{Result, bail};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use utils_box_logger::{log_error, log_info, log_warn};
+
+use crate::archives::archive_extract_all_with_policy;
+use crate::error::ArchiveError;
+use crate::policy::{ExtractPolicy, ExtractReport};
+
+/// Entry Point:
+/// Extract all contents from the provided archive into a sibling staging directory of the destination
+/// and swap it into place once everything is written and synced to disk
+/// On failure the destination is left untouched. The previous contents are kept for `archive_rollback`.
+pub fn archive_extract_all_atomic(
+    archive: PathBuf,
+    destination: PathBuf,
+    policy: &ExtractPolicy,
+) -> Result<ExtractReport, ArchiveError> {
+    let staging = sibling(&destination, "staging")?;
+    let rollback = sibling(&destination, "rollback")?;
+
+    recover(&destination, &rollback)?;
+
+    // Leftovers of an interrupted extraction
+    let _ = std::fs::remove_dir_all(&staging);
+
+    let report = match archive_extract_all_with_policy(archive, staging.clone(), policy)
+        .map_err(anyhow::Error::from)
+        .and_then(|report| sync_all(&staging).map(|_| report))
+    {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e.into());
+        }
+    };
+
+    // Only the latest previous contents are kept for rollback
+    let _ = std::fs::remove_dir_all(&rollback);
+
+    if let Err(e) = swap_into_place(&staging, &destination, &rollback) {
+        let _ = std::fs::remove_dir_all(&staging);
+
+        log_error!(
+            "[archive_extract_all_atomic] Failed to move the extracted contents into [{}]: {}",
+            destination.display(),
+            e
+        );
+        return Err(e.into());
+    }
+
+    log_info!(
+        "[archive_extract_all_atomic] Extracted [{}] entries into [{}]",
+        report.extracted.len(),
+        destination.display()
+    );
+
+    Ok(report)
+}
+
+/// Entry Point:
+/// Restore the contents the destination had before the last `archive_extract_all_atomic`
+/// The current contents are discarded. Fails if there is nothing to roll back to.
+pub fn archive_rollback(destination: PathBuf) -> Result<(), ArchiveError> {
+    let rollback = sibling(&destination, "rollback")?;
+    let discarded = sibling(&destination, "discarded")?;
+
+    if !rollback.is_dir() {
+        return Err(ArchiveError::NotFound(format!(
+            "[archive_rollback] No previous contents available for [{}]!",
+            destination.display()
+        )));
+    }
+
+    let _ = std::fs::remove_dir_all(&discarded);
+
+    if let Err(e) = swap_into_place(&rollback, &destination, &discarded) {
+        log_error!(
+            "[archive_rollback] Failed to restore the previous contents of [{}]: {}",
+            destination.display(),
+            e
+        );
+        return Err(e.into());
+    }
+
+    if let Err(e) = std::fs::remove_dir_all(&discarded)
+        && discarded.exists()
+    {
+        log_warn!(
+            "[archive_rollback] Failed to remove [{}]: {}",
+            discarded.display(),
+            e
+        );
+    }
+
+    log_info!(
+        "[archive_rollback] Restored the previous contents of [{}]",
+        destination.display()
+    );
+
+    Ok(())
+}
+
+/// Restore the previous contents if an interrupted swap left only the rollback slot behind
+fn recover(destination: &Path, rollback: &Path) -> Result<()> {
+    if destination.exists() || !rollback.is_dir() {
+        return Ok(());
+    }
+
+    log_warn!(
+        "[atomic] Restoring the previous contents of [{}] left behind by an interrupted swap",
+        destination.display()
+    );
+
+    std::fs::rename(rollback, destination)?;
+    sync_parent(destination)
+}
+
+/// Move `source` into place at `destination`, the current contents of the destination (if any) end up at `previous`
+/// If the second rename fails, the current contents are moved back to the destination.
+fn swap_into_place(source: &Path, destination: &Path, previous: &Path) -> Result<()> {
+    if !destination.exists() {
+        std::fs::rename(source, destination)?;
+        return sync_parent(destination);
+    }
+
+    #[cfg(target_os = "linux")]
+    if exchange(source, destination)? {
+        sync_parent(destination)?;
+
+        // The destination is already updated, the previous contents are just not kept if this fails
+        if let Err(e) = std::fs::rename(source, previous) {
+            log_warn!(
+                "[atomic] Failed to keep the previous contents of [{}] in [{}]: {}",
+                destination.display(),
+                previous.display(),
+                e
+            );
+            return Ok(());
+        }
+
+        return sync_parent(previous);
+    }
+
+    std::fs::rename(destination, previous)?;
+    sync_parent(previous)?;
+
+    if let Err(e) = std::fs::rename(source, destination) {
+        std::fs::rename(previous, destination)?;
+        sync_parent(destination)?;
+        return Err(e.into());
+    }
+
+    sync_parent(destination)
+}
+
+/// Swap the two paths with a single `renameat2(RENAME_EXCHANGE)`
+/// Returns `false` if the kernel or the filesystem does not support it
+#[cfg(target_os = "linux")]
+fn exchange(a: &Path, b: &Path) -> Result<bool> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+
+    // SAFETY: Both paths are valid NUL terminated strings that outlive the call
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+
+    if result == 0 {
+        return Ok(true);
+    }
+
+    let e = std::io::Error::last_os_error();
+
+    match e.raw_os_error() {
+        Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP) => Ok(false),
+        _ => Err(e.into()),
+    }
+}
+
+/// Hidden sibling directory of the destination (e.g. `.firmware.staging` next to `firmware`)
+/// Siblings are on the same filesystem, so renaming between them is atomic
+fn sibling(destination: &Path, slot: &str) -> Result<PathBuf> {
+    match (destination.parent(), destination.file_name()) {
+        (Some(parent), Some(name)) => {
+            Ok(parent.join(format!(".{}.{}", name.to_string_lossy(), slot)))
+        }
+        _ => bail!(ArchiveError::InvalidInput(format!(
+            "[atomic] Invalid destination directory [{}]!",
+            destination.display()
+        ))),
+    }
+}
+
+/// Flush all files and directories below the provided path to disk
+fn sync_all(path: &Path) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(path)?;
+
+    if metadata.is_dir() {
+        for dir_entry in std::fs::read_dir(path)? {
+            sync_all(&dir_entry?.path())?;
+        }
+    } else if !metadata.is_file() {
+        // Links and special files have no contents to flush
+        return Ok(());
+    }
+
+    sync_path(path)
+}
+
+/// Flush the directory entry of the renamed path
+fn sync_parent(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_path(parent),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn sync_path(path: &Path) -> Result<()> {
+    File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+/// Directories cannot be opened as files on other platforms, only file contents are flushed
+#[cfg(not(unix))]
+fn sync_path(path: &Path) -> Result<()> {
+    if path.is_file() {
+        File::open(path)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::atomic::*;
+
+    #[test]
+    fn archive_extract_all_atomic_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let release = dir.path().join("release");
+        std::fs::create_dir_all(release.join("bin")).unwrap();
+        std::fs::write(release.join("bin/app"), "v2").unwrap();
+
+        let archive = dir.path().join("release.tar.gz");
+        archive_create_all(release, ArchiveType::Gz, archive.clone()).unwrap();
+
+        // Nothing to roll back to on the first installation
+        let destination = dir.path().join("installation");
+        archive_extract_all_atomic(
+            archive.clone(),
+            destination.clone(),
+            &ExtractPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            "v2",
+            std::fs::read_to_string(destination.join("bin/app")).unwrap()
+        );
+        assert!(matches!(
+            archive_rollback(destination.clone()),
+            Err(ArchiveError::NotFound(_))
+        ));
+
+        // The previous contents are replaced as a whole and kept for rollback
+        std::fs::remove_dir_all(&destination).unwrap();
+        std::fs::create_dir(&destination).unwrap();
+        std::fs::write(destination.join("v1.txt"), "v1").unwrap();
+
+        archive_extract_all_atomic(archive, destination.clone(), &ExtractPolicy::default())
+            .unwrap();
+        assert!(!destination.join("v1.txt").exists());
+        assert!(destination.join("bin/app").exists());
+        assert!(!dir.path().join(".installation.staging").exists());
+
+        archive_rollback(destination.clone()).unwrap();
+        assert_eq!(
+            "v1",
+            std::fs::read_to_string(destination.join("v1.txt")).unwrap()
+        );
+        assert!(!destination.join("bin").exists());
+        assert!(!dir.path().join(".installation.rollback").exists());
+    }
+
+    #[test]
+    fn archive_extract_all_atomic_recovery_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let release = dir.path().join("release");
+        std::fs::create_dir(&release).unwrap();
+        std::fs::write(release.join("v2.txt"), "v2").unwrap();
+
+        let archive = dir.path().join("release.tar");
+        archive_create_all(release, ArchiveType::Tar, archive.clone()).unwrap();
+
+        // Interrupted between the two renames of a swap: only the previous contents are left
+        let destination = dir.path().join("installation");
+        let rollback = dir.path().join(".installation.rollback");
+        std::fs::create_dir(&rollback).unwrap();
+        std::fs::write(rollback.join("v1.txt"), "v1").unwrap();
+
+        archive_extract_all_atomic(archive, destination.clone(), &ExtractPolicy::default())
+            .unwrap();
+        assert!(destination.join("v2.txt").exists());
+        assert!(!destination.join("v1.txt").exists());
+
+        // The previous contents survived the recovery
+        archive_rollback(destination.clone()).unwrap();
+        assert_eq!(
+            "v1",
+            std::fs::read_to_string(destination.join("v1.txt")).unwrap()
+        );
+        assert!(!destination.join("v2.txt").exists());
+    }
+
+    #[test]
+    fn archive_extract_all_atomic_failure_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let release = dir.path().join("release");
+        std::fs::create_dir(&release).unwrap();
+        std::fs::write(release.join("data.bin"), vec![0x5A; 64 * 1024]).unwrap();
+
+        // Truncated archives fail in the middle of the extraction
+        let archive = dir.path().join("release.tar");
+        archive_create_all(release, ArchiveType::Tar, archive.clone()).unwrap();
+        let bytes = std::fs::read(&archive).unwrap();
+        std::fs::write(&archive, &bytes[..bytes.len() / 2]).unwrap();
+
+        let destination = dir.path().join("installation");
+        std::fs::create_dir(&destination).unwrap();
+        std::fs::write(destination.join("v1.txt"), "v1").unwrap();
+
+        assert!(matches!(
+            archive_extract_all_atomic(archive, destination.clone(), &ExtractPolicy::default()),
+            Err(ArchiveError::Corrupt(_))
+        ));
+        assert_eq!(
+            "v1",
+            std::fs::read_to_string(destination.join("v1.txt")).unwrap()
+        );
+        assert!(!destination.join("data.bin").exists());
+        assert!(!dir.path().join(".installation.staging").exists());
+    }
+}
@@ -0,0 +1,647 @@
+//! # Delta utilities
+//! Compare archives and ship only what changed between two releases.
+//! Useful for incremental firmware updates where a full archive would carry mostly unchanged files.
+
+use anyhow::{Result, bail};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use utils_box_logger::{log_info, log_warn};
+
+use crate::archives::{
+    ArchiveType, EntryKind, archive_create_all, archive_extract_all_with_policy, archive_list,
+    detect_archive_type, extract_all_with_progress,
+};
+use crate::error::ArchiveError;
+use crate::policy::{ExtractPolicy, normalize};
+use crate::progress::CancelToken;
+use crate::readers::{archive_entries, archive_read_file};
+use crate::verify::sha256_hex;
+
+/// Name of the entry of a delta bundle that lists the files to delete, one path per line
+pub static DELTA_DELETED: &str = ".delta-deleted";
+
+/// Differences between the files and links of two archives
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveCompare {
+    pub updated: Vec<PathBuf>,
+    pub added: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+}
+
+impl ArchiveCompare {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the two archives hold the same files and links
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.added.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// Entry Point:
+/// Compare the files and links of two archives by their type, permissions, link target and SHA-256 digest
+/// and return the differences against the first one. Directories are not compared.
+/// Archives of different types can be compared (e.g. a tar.gz release against a zip one)
+pub fn archive_compare(a: PathBuf, b: PathBuf) -> Result<ArchiveCompare, ArchiveError> {
+    let a_states = archive_states(a)?;
+    let b_states = archive_states(b)?;
+
+    let mut results = ArchiveCompare::new();
+
+    for (path, a_state) in a_states.iter() {
+        match b_states.get(path) {
+            Some(b_state) if b_state.differs(a_state) => results.updated.push(path.clone()),
+            Some(_) => {}
+            None => results.deleted.push(path.clone()),
+        }
+    }
+
+    results.added = b_states
+        .into_keys()
+        .filter(|path| !a_states.contains_key(path))
+        .collect();
+
+    log_info!(
+        "[archive_compare] Updated [{}], added [{}], deleted [{}] files",
+        results.updated.len(),
+        results.added.len(),
+        results.deleted.len()
+    );
+
+    Ok(results)
+}
+
+/// Entry Point:
+/// Create a delta bundle of the selected type that turns the contents of the `old` archive into the `new` one
+/// The bundle holds the updated and added files and links of `new` together with a `.delta-deleted` list of the removed ones
+/// Entries of `new` that the default `ExtractPolicy` skips (e.g. links outside of the archive) cannot be shipped
+/// Returns the differences that were packaged
+pub fn archive_create_delta(
+    old: PathBuf,
+    new: PathBuf,
+    archive_type: ArchiveType,
+    delta: PathBuf,
+) -> Result<ArchiveCompare, ArchiveError> {
+    let mut results = archive_compare(old, new.clone())?;
+
+    // The changed files are staged next to the bundle, so that their permissions and times are kept
+    let staging = staging_dir(&delta)?;
+    let _ = std::fs::remove_dir_all(&staging);
+
+    let created = stage_delta(&new, &mut results, &staging)
+        .and_then(|_| Ok(archive_create_all(staging.clone(), archive_type, delta)?));
+
+    let _ = std::fs::remove_dir_all(&staging);
+    created?;
+
+    Ok(results)
+}
+
+/// Entry Point:
+/// Apply a delta bundle on a directory holding the extracted contents of the old archive
+/// The `.delta-deleted` list is read and validated before anything is written, then the other entries of the bundle
+/// are extracted with the default `ExtractPolicy` and the listed files are deleted
+/// Returns the changes applied on the directory, entries skipped by the policy are not part of them
+pub fn archive_apply_delta(
+    delta: PathBuf,
+    destination: PathBuf,
+) -> Result<ArchiveCompare, ArchiveError> {
+    let mut results = ArchiveCompare::new();
+    let mut deleted_list: Option<PathBuf> = None;
+
+    // Whether each file or link of the bundle replaces something already in the destination
+    let mut existing: BTreeMap<PathBuf, bool> = BTreeMap::new();
+
+    for entry in archive_list(delta.clone())? {
+        let path = normalize(&entry.path);
+
+        if path == Path::new(DELTA_DELETED) {
+            deleted_list = Some(entry.path);
+            continue;
+        }
+
+        if entry.kind == EntryKind::Directory {
+            continue;
+        }
+
+        let exists = destination.join(&path).symlink_metadata().is_ok();
+        existing.insert(path, exists);
+    }
+
+    let deleted_list = match deleted_list {
+        Some(deleted_list) => deleted_list,
+        None => {
+            return Err(ArchiveError::InvalidInput(format!(
+                "[archive_apply_delta] Delta bundle has no [{}] list!",
+                DELTA_DELETED
+            )));
+        }
+    };
+    let deleted = match String::from_utf8(archive_read_file(delta.clone(), deleted_list)?) {
+        Ok(deleted) => deleted,
+        Err(_) => {
+            return Err(ArchiveError::Corrupt(format!(
+                "[archive_apply_delta] The [{}] list of the delta bundle is not valid UTF-8!",
+                DELTA_DELETED
+            )));
+        }
+    };
+
+    // The list itself is never written in the destination
+    let archive_type = detect_archive_type(&delta, "archive_apply_delta")?;
+    let report = extract_all_with_progress(
+        delta,
+        archive_type,
+        destination.clone(),
+        &ExtractPolicy::default(),
+        None,
+        &mut |_| {},
+        &CancelToken::new(),
+        &|name| normalize(name) != Path::new(DELTA_DELETED),
+    )?;
+
+    for (entry, hazard) in report.skipped.iter() {
+        log_warn!(
+            "[archive_apply_delta] Entry [{}] of the delta bundle was not applied due to [{}]",
+            entry.display(),
+            hazard
+        );
+    }
+
+    for path in report.extracted {
+        match existing.get(&path) {
+            Some(true) => results.updated.push(path),
+            Some(false) => results.added.push(path),
+            None => {}
+        }
+    }
+
+    let canonical_destination = destination.canonicalize()?;
+
+    for line in deleted.lines().filter(|line| !line.trim().is_empty()) {
+        // Only paths inside the destination can be deleted
+        let path = normalize(Path::new(line));
+        if path.as_os_str().is_empty() {
+            continue;
+        }
+
+        let target = destination.join(&path);
+
+        // Symbolic links in the parent directories could point the deletion outside of the destination
+        let inside = target
+            .parent()
+            .and_then(|parent| parent.canonicalize().ok())
+            .is_some_and(|parent| parent.starts_with(&canonical_destination));
+
+        if !inside {
+            log_warn!(
+                "[archive_apply_delta] Skipping deletion of [{}] outside of [{}]",
+                path.display(),
+                destination.display()
+            );
+            continue;
+        }
+
+        if target.is_file() || target.is_symlink() {
+            std::fs::remove_file(&target)?;
+            remove_empty_parents(&destination, &path);
+            results.deleted.push(path);
+        }
+    }
+
+    log_info!(
+        "[archive_apply_delta] Updated [{}], added [{}], deleted [{}] files in [{}]",
+        results.updated.len(),
+        results.added.len(),
+        results.deleted.len(),
+        destination.display()
+    );
+
+    Ok(results)
+}
+
+/// State of a file or link of an archive as compared by `archive_compare`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct EntryState {
+    kind: EntryKind,
+    mode: Option<u32>,
+    link: Option<PathBuf>,
+    digest: Option<String>,
+}
+
+impl EntryState {
+    /// Permissions are only compared when both archives store them (e.g. zip archives created on Windows do not)
+    fn differs(&self, other: &EntryState) -> bool {
+        let mode_differs = match (self.mode, other.mode) {
+            (Some(mode), Some(other_mode)) => mode != other_mode,
+            _ => false,
+        };
+
+        self.kind != other.kind
+            || self.link != other.link
+            || self.digest != other.digest
+            || mode_differs
+    }
+}
+
+/// States of the files and links of an archive by normalized path
+fn archive_states(archive: PathBuf) -> Result<BTreeMap<PathBuf, EntryState>> {
+    let mut states: BTreeMap<PathBuf, EntryState> = BTreeMap::new();
+
+    for entry in archive_list(archive.clone())? {
+        if entry.kind == EntryKind::Directory {
+            continue;
+        }
+
+        states.insert(
+            normalize(&entry.path),
+            EntryState {
+                kind: entry.kind,
+                mode: entry.mode,
+                link: entry.link,
+                digest: None,
+            },
+        );
+    }
+
+    for entry in archive_entries(archive)? {
+        let (path, mut reader) = entry?;
+
+        let state = match states.get_mut(&normalize(&path)) {
+            Some(state) => state,
+            None => continue,
+        };
+
+        // Zip archives store the target of symbolic links as the contents of the entry
+        if state.kind == EntryKind::Symlink {
+            let mut target = String::new();
+            reader.read_to_string(&mut target)?;
+            state.link = Some(PathBuf::from(target));
+        } else {
+            state.digest = Some(sha256_hex(&mut reader)?);
+        }
+    }
+
+    Ok(states)
+}
+
+/// Directory used to stage the contents of a delta bundle before packaging it
+fn staging_dir(delta: &Path) -> Result<PathBuf> {
+    match (delta.parent(), delta.file_name()) {
+        (Some(parent), Some(name)) => {
+            Ok(parent.join(format!(".{}.staging", name.to_string_lossy())))
+        }
+        _ => bail!(ArchiveError::InvalidInput(format!(
+            "[archive_create_delta] Invalid delta bundle path [{}]!",
+            delta.display()
+        ))),
+    }
+}
+
+/// Extract the new archive in the staging directory and keep only the changed files, links and the deletion list
+/// Changes that the default `ExtractPolicy` skips are dropped from the results, since they cannot be shipped
+fn stage_delta(new: &Path, results: &mut ArchiveCompare, staging: &Path) -> Result<()> {
+    let report = archive_extract_all_with_policy(
+        new.to_path_buf(),
+        staging.to_path_buf(),
+        &ExtractPolicy::default(),
+    )?;
+
+    for (entry, hazard) in report.skipped.iter() {
+        let path = normalize(entry);
+
+        if results.updated.contains(&path) || results.added.contains(&path) {
+            log_warn!(
+                "[archive_create_delta] Entry [{}] cannot be shipped in the delta bundle due to [{}]",
+                entry.display(),
+                hazard
+            );
+        }
+
+        results.updated.retain(|changed| *changed != path);
+        results.added.retain(|changed| *changed != path);
+    }
+
+    let changed: BTreeSet<&PathBuf> = results.updated.iter().chain(results.added.iter()).collect();
+    prune_unchanged(staging, staging, &changed)?;
+
+    let deleted: String = results
+        .deleted
+        .iter()
+        .map(|path| format!("{}\n", path.display()))
+        .collect();
+    std::fs::write(staging.join(DELTA_DELETED), deleted)?;
+
+    Ok(())
+}
+
+/// Remove every file that is not part of the changed set together with the directories left empty
+fn prune_unchanged(base: &Path, dir: &Path, changed: &BTreeSet<&PathBuf>) -> Result<()> {
+    for dir_entry in std::fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        let file_type = std::fs::symlink_metadata(&path)?.file_type();
+
+        if file_type.is_dir() {
+            prune_unchanged(base, &path, changed)?;
+
+            if std::fs::read_dir(&path)?.next().is_none() {
+                std::fs::remove_dir(&path)?;
+            }
+        } else if !changed.contains(&path.strip_prefix(base)?.to_path_buf()) {
+            std::fs::remove_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the parent directories of a deleted file while they are empty
+fn remove_empty_parents(destination: &Path, path: &Path) {
+    for parent in path.ancestors().skip(1) {
+        if parent.as_os_str().is_empty() || std::fs::remove_dir(destination.join(parent)).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::delta::*;
+
+    fn release(dir: &Path, files: &[(&str, &str)]) {
+        let _ = std::fs::remove_dir_all(dir);
+
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn archive_compare_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        release(
+            &dir.path().join("v1"),
+            &[
+                ("app.bin", "v1"),
+                ("conf/app.ini", "a=1"),
+                ("old.txt", "old"),
+            ],
+        );
+        release(
+            &dir.path().join("v2"),
+            &[
+                ("app.bin", "v2"),
+                ("conf/app.ini", "a=1"),
+                ("new.txt", "new"),
+            ],
+        );
+
+        let v1 = dir.path().join("v1.tar.gz");
+        let v2 = dir.path().join("v2.zip");
+        archive_create_all(dir.path().join("v1"), ArchiveType::Gz, v1.clone()).unwrap();
+        archive_create_all(dir.path().join("v2"), ArchiveType::Zip, v2.clone()).unwrap();
+
+        let results = archive_compare(v1.clone(), v2).unwrap();
+
+        assert_eq!(vec![PathBuf::from("app.bin")], results.updated);
+        assert_eq!(vec![PathBuf::from("new.txt")], results.added);
+        assert_eq!(vec![PathBuf::from("old.txt")], results.deleted);
+
+        assert!(archive_compare(v1.clone(), v1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn archive_delta_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let v1_files = [
+            ("app.bin", "v1"),
+            ("conf/app.ini", "a=1"),
+            ("docs/old.txt", "old"),
+        ];
+        let v2_files = [
+            ("app.bin", "v2"),
+            ("conf/app.ini", "a=1"),
+            ("conf/extra.ini", "b=2"),
+        ];
+
+        release(&dir.path().join("v1"), &v1_files);
+        release(&dir.path().join("v2"), &v2_files);
+
+        let v1 = dir.path().join("v1.tar");
+        let v2 = dir.path().join("v2.tar");
+        archive_create_all(dir.path().join("v1"), ArchiveType::Tar, v1.clone()).unwrap();
+        archive_create_all(dir.path().join("v2"), ArchiveType::Tar, v2.clone()).unwrap();
+
+        let delta = dir.path().join("v1_v2.tar.gz");
+        archive_create_delta(v1.clone(), v2, ArchiveType::Gz, delta.clone()).unwrap();
+
+        // Only the changed files and the deletion list are shipped
+        let mut shipped: Vec<PathBuf> = archive_list(delta.clone())
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.kind == EntryKind::File)
+            .map(|entry| entry.path)
+            .collect();
+        shipped.sort();
+        assert_eq!(
+            vec![
+                PathBuf::from(DELTA_DELETED),
+                PathBuf::from("app.bin"),
+                PathBuf::from("conf/extra.ini")
+            ],
+            shipped
+        );
+
+        // Applying the delta on the old release results in the new one
+        let installation = dir.path().join("installation");
+        archive_extract_all(v1, installation.clone()).unwrap();
+
+        // A file of the installation that happens to share the name of the list is left alone
+        std::fs::write(installation.join(DELTA_DELETED), "keep").unwrap();
+
+        let results = archive_apply_delta(delta, installation.clone()).unwrap();
+
+        assert_eq!(vec![PathBuf::from("app.bin")], results.updated);
+        assert_eq!(vec![PathBuf::from("conf/extra.ini")], results.added);
+        assert_eq!(vec![PathBuf::from("docs/old.txt")], results.deleted);
+
+        for (name, contents) in v2_files {
+            assert_eq!(
+                contents,
+                std::fs::read_to_string(installation.join(name)).unwrap()
+            );
+        }
+        assert!(!installation.join("docs").exists());
+        assert_eq!(
+            "keep",
+            std::fs::read_to_string(installation.join(DELTA_DELETED)).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn archive_delta_file_to_symlink_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        release(
+            &dir.path().join("v1"),
+            &[("lib/libfoo.so", "foo"), ("lib/libfoo.so.1", "foo.1")],
+        );
+        release(&dir.path().join("v2"), &[("lib/libfoo.so.1", "foo.1")]);
+        std::os::unix::fs::symlink("libfoo.so.1", dir.path().join("v2/lib/libfoo.so")).unwrap();
+
+        let v1 = dir.path().join("v1.tar");
+        let v2 = dir.path().join("v2.tar");
+        archive_create_all(dir.path().join("v1"), ArchiveType::Tar, v1.clone()).unwrap();
+        archive_create_all(dir.path().join("v2"), ArchiveType::Tar, v2.clone()).unwrap();
+
+        let delta = dir.path().join("v1_v2.tar");
+        let shipped =
+            archive_create_delta(v1.clone(), v2, ArchiveType::Tar, delta.clone()).unwrap();
+
+        // The file is replaced by the link, not deleted
+        assert_eq!(vec![PathBuf::from("lib/libfoo.so")], shipped.updated);
+        assert!(shipped.deleted.is_empty());
+
+        let installation = dir.path().join("installation");
+        archive_extract_all(v1, installation.clone()).unwrap();
+
+        let results = archive_apply_delta(delta, installation.clone()).unwrap();
+        assert_eq!(shipped, results);
+
+        let link = installation.join("lib/libfoo.so");
+        assert_eq!(
+            PathBuf::from("libfoo.so.1"),
+            std::fs::read_link(&link).unwrap()
+        );
+        assert_eq!("foo.1", std::fs::read_to_string(link).unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn archive_delta_mode_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+
+        let files = [("bin/start.sh", "#!/bin/sh\n"), ("conf/app.ini", "a=1")];
+        release(&dir.path().join("v1"), &files);
+        release(&dir.path().join("v2"), &files);
+
+        // Only the script is made executable
+        std::fs::set_permissions(
+            dir.path().join("v2/bin/start.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+
+        let v1 = dir.path().join("v1.tar.gz");
+        let v2 = dir.path().join("v2.zip");
+        archive_create_all(dir.path().join("v1"), ArchiveType::Gz, v1.clone()).unwrap();
+        archive_create_all(dir.path().join("v2"), ArchiveType::Zip, v2.clone()).unwrap();
+
+        let delta = dir.path().join("v1_v2.tar.gz");
+        let shipped = archive_create_delta(v1.clone(), v2, ArchiveType::Gz, delta.clone()).unwrap();
+        assert_eq!(vec![PathBuf::from("bin/start.sh")], shipped.updated);
+        assert!(shipped.added.is_empty() && shipped.deleted.is_empty());
+
+        let installation = dir.path().join("installation");
+        archive_extract_all(v1, installation.clone()).unwrap();
+
+        let results = archive_apply_delta(delta, installation.clone()).unwrap();
+        assert_eq!(shipped, results);
+
+        let metadata = std::fs::metadata(installation.join("bin/start.sh")).unwrap();
+        assert_eq!(0o755, metadata.permissions().mode() & 0o777);
+    }
+
+    #[test]
+    fn archive_apply_delta_skipped_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let installation = dir.path().join("installation");
+        release(&installation, &[("app.bin", "v1")]);
+
+        // A hand-made bundle with an entry that the default policy skips
+        let delta = dir.path().join("delta.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(&delta).unwrap());
+        for (name, contents) in [
+            ("app.bin", &b"v2"[..]),
+            ("../escape.txt", &b"escape"[..]),
+            (DELTA_DELETED, &b""[..]),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, contents).unwrap();
+        }
+        builder.into_inner().unwrap();
+
+        let results = archive_apply_delta(delta, installation.clone()).unwrap();
+
+        assert_eq!(vec![PathBuf::from("app.bin")], results.updated);
+        assert!(results.added.is_empty());
+        assert!(!dir.path().join("escape.txt").exists());
+        assert!(!installation.join("escape.txt").exists());
+    }
+
+    #[test]
+    fn archive_apply_delta_malformed_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let installation = dir.path().join("installation");
+        release(&installation, &[("app.bin", "v1")]);
+
+        // Bundle without a deletion list
+        let bundle = dir.path().join("bundle");
+        release(&bundle, &[("app.bin", "v2")]);
+
+        let delta = dir.path().join("delta.tar");
+        archive_create_all(bundle, ArchiveType::Tar, delta.clone()).unwrap();
+
+        assert!(matches!(
+            archive_apply_delta(delta, installation.clone()),
+            Err(ArchiveError::InvalidInput(_))
+        ));
+        assert_eq!(
+            "v1",
+            std::fs::read_to_string(installation.join("app.bin")).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn archive_apply_delta_symlink_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // The old release left a `lib` link pointing outside of the installation
+        let installation = dir.path().join("installation");
+        release(&installation, &[("old.txt", "old")]);
+        release(&dir.path().join("outside"), &[("libfoo.so", "foo")]);
+        std::os::unix::fs::symlink("../outside", installation.join("lib")).unwrap();
+
+        let bundle = dir.path().join("bundle");
+        release(&bundle, &[(DELTA_DELETED, "lib/libfoo.so\nold.txt\n")]);
+
+        let delta = dir.path().join("delta.tar");
+        archive_create_all(bundle, ArchiveType::Tar, delta.clone()).unwrap();
+
+        let results = archive_apply_delta(delta, installation.clone()).unwrap();
+
+        assert_eq!(vec![PathBuf::from("old.txt")], results.deleted);
+        assert!(dir.path().join("outside/libfoo.so").exists());
+        assert!(!installation.join("old.txt").exists());
+    }
+}
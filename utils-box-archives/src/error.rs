@@ -0,0 +1,238 @@
+//! # Archive errors
+//! Typed errors of the archive utilities, so that callers can tell a missing entry from a corrupted archive
+//! or an I/O failure without matching strings.
+//! `ArchiveError` implements `std::error::Error`, so it converts into `anyhow::Error` with `?`
+//! and can be recovered from it with `ArchiveError::from` (or `downcast_ref::<ArchiveError>()`).
+
+use std::{fmt, io, path::PathBuf};
+use zip::result::ZipError;
+
+use crate::limits::LimitExceeded;
+use crate::policy::Hazard;
+
+/// Failures of the archive utilities
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// The requested entry or source file does not exist
+    NotFound(String),
+    /// Unknown archive format, a format that requires a disabled feature or an operation the format does not support
+    UnsupportedFormat(String),
+    /// The archive or one of its entries is corrupted or truncated
+    Corrupt(String),
+    /// The entry is encrypted and the password is missing or wrong
+    Password(String),
+    /// An entry was rejected by the `ExtractPolicy`, or a requested entry was skipped by it
+    PolicyViolation {
+        entry: PathBuf,
+        hazard: Hazard,
+    },
+    /// One of the `ExtractLimits` of the policy was exceeded
+    LimitExceeded(LimitExceeded),
+    /// The extraction was cancelled through its `CancelToken`
+    Cancelled,
+    /// The provided arguments cannot be used (e.g. the source is not a directory)
+    InvalidInput(String),
+    Io(io::Error),
+    /// Any other failure
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::NotFound(message)
+            | ArchiveError::UnsupportedFormat(message)
+            | ArchiveError::Corrupt(message)
+            | ArchiveError::Password(message)
+            | ArchiveError::InvalidInput(message) => write!(f, "{message}"),
+            ArchiveError::PolicyViolation { entry, hazard } => write!(
+                f,
+                "[extract_policy] Entry [{}] rejected due to [{}]!",
+                entry.display(),
+                hazard
+            ),
+            ArchiveError::LimitExceeded(exceeded) => write!(f, "{exceeded}"),
+            ArchiveError::Cancelled => write!(f, "[extract_all] Extraction cancelled!"),
+            ArchiveError::Io(e) => write!(f, "[archives] I/O error: {e}"),
+            ArchiveError::Other(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ArchiveError::LimitExceeded(exceeded) => Some(exceeded),
+            ArchiveError::Io(e) => Some(e),
+            ArchiveError::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        // Streaming readers report exceeded limits through the I/O error they return
+        if let Some(exceeded) = e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<LimitExceeded>())
+        {
+            return ArchiveError::LimitExceeded(exceeded.clone());
+        }
+
+        // Decoders and the tar reader report broken streams through these kinds
+        match e.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                ArchiveError::Corrupt(format!("[archives] Corrupted archive: {e}"))
+            }
+            _ => ArchiveError::Io(e),
+        }
+    }
+}
+
+impl From<ZipError> for ArchiveError {
+    fn from(e: ZipError) -> Self {
+        match e {
+            ZipError::Io(e) => e.into(),
+            ZipError::InvalidArchive(reason) => {
+                ArchiveError::Corrupt(format!("[archives][zip] Corrupted archive: {reason}"))
+            }
+            ZipError::UnsupportedArchive(reason) if reason == ZipError::PASSWORD_REQUIRED => {
+                ArchiveError::Password(format!("[archives][zip] {reason}"))
+            }
+            ZipError::UnsupportedArchive(reason) => {
+                ArchiveError::UnsupportedFormat(format!("[archives][zip] {reason}"))
+            }
+            ZipError::FileNotFound => {
+                ArchiveError::NotFound("[archives][zip] Failed to find requested file!".to_string())
+            }
+            ZipError::InvalidPassword => {
+                ArchiveError::Password("[archives][zip] Wrong password!".to_string())
+            }
+            e => ArchiveError::Other(e.into()),
+        }
+    }
+}
+
+impl From<LimitExceeded> for ArchiveError {
+    fn from(e: LimitExceeded) -> Self {
+        ArchiveError::LimitExceeded(e)
+    }
+}
+
+/// Recover the typed error from the errors of the internal `anyhow` based helpers
+impl From<anyhow::Error> for ArchiveError {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<ArchiveError>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        let e = match e.downcast::<LimitExceeded>() {
+            Ok(e) => return e.into(),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ZipError>() {
+            Ok(e) => return e.into(),
+            Err(e) => e,
+        };
+
+        match e.downcast::<io::Error>() {
+            Ok(e) => e.into(),
+            Err(e) => ArchiveError::Other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::error::*;
+    use crate::policy::{ExtractPolicy, PolicyAction};
+
+    #[test]
+    fn archive_error_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let source = dir.path().join("source");
+        std::fs::create_dir(&source).unwrap();
+        std::fs::write(source.join("data.txt"), "data").unwrap();
+
+        let archive = dir.path().join("bundle.tar");
+        archive_create_all(source.clone(), ArchiveType::Tar, archive.clone()).unwrap();
+
+        let error = archive_extract_file(
+            archive.clone(),
+            "missing.txt".into(),
+            dir.path().join("out"),
+        )
+        .unwrap_err();
+        assert!(matches!(error, ArchiveError::NotFound(_)));
+
+        let error = archive_list(source.join("data.txt")).unwrap_err();
+        assert!(matches!(error, ArchiveError::UnsupportedFormat(_)));
+
+        let error = archive_list(dir.path().join("missing.tar")).unwrap_err();
+        assert!(matches!(error, ArchiveError::Io(ref e) if e.kind() == io::ErrorKind::NotFound));
+
+        // Truncated in the middle of the data of the entry
+        let bytes = std::fs::read(&archive).unwrap();
+        std::fs::write(&archive, &bytes[..600]).unwrap();
+        let error = archive_extract_all(archive, dir.path().join("out")).unwrap_err();
+        assert!(matches!(error, ArchiveError::Corrupt(_)), "{error:?}");
+
+        // The typed error survives a round trip through anyhow
+        let error: anyhow::Error = archive_create_all(
+            source.join("data.txt"),
+            ArchiveType::Tar,
+            dir.path().join("bad.tar"),
+        )
+        .unwrap_err()
+        .into();
+        assert!(matches!(
+            error.downcast_ref::<ArchiveError>(),
+            Some(ArchiveError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            ArchiveError::from(error),
+            ArchiveError::InvalidInput(_)
+        ));
+
+        // A plain .gz file cannot hold a whole directory
+        std::fs::write(source.join("more.txt"), "more").unwrap();
+        let error =
+            archive_create_all(source, ArchiveType::GzFile, dir.path().join("bad.gz")).unwrap_err();
+        assert!(matches!(error, ArchiveError::InvalidInput(_)), "{error:?}");
+    }
+
+    #[test]
+    fn archive_error_policy_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Absolute paths cannot be added through the builder, so the header is written by hand
+        let archive = dir.path().join("absolute.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..12].copy_from_slice(b"/etc/app.ini");
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"a=1\n"[..]).unwrap();
+        builder.into_inner().unwrap();
+
+        let policy = ExtractPolicy {
+            absolute_paths: PolicyAction::Reject,
+            ..Default::default()
+        };
+
+        let error =
+            archive_extract_all_with_policy(archive, dir.path().join("out"), &policy).unwrap_err();
+
+        match error {
+            ArchiveError::PolicyViolation { entry, hazard } => {
+                assert_eq!(PathBuf::from("/etc/app.ini"), entry);
+                assert_eq!(crate::policy::Hazard::AbsolutePath, hazard);
+            }
+            e => panic!("Unexpected error: {e}"),
+        }
+    }
+}
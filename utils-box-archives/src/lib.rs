@@ -3,7 +3,7 @@
 //!
 //! # Utilities provided:
 //! ## Archives
-//! Extract files from Tar, Gz and Zip Files
+//! Extract files from Tar, Gz and Zip Files and create new ones
 //!
 //! Mininal Example:
 //! ```ignore
@@ -24,5 +24,243 @@
 //!
 //! ```
 //!
+//! Package a directory into a new archive:
+//! ```ignore
+//! let logs: PathBuf = "/var/log/my_app".into();
+//!
+//! archives::archive_create_all(logs, ArchiveType::Gz, "/tmp/my_app_logs.tar.gz".into()).unwrap();
+//!
+//! ```
+//!
+//! ## Extraction Policy
+//! Harden extraction of archives from semi-trusted sources against absolute paths, `..` traversal,
+//! links pointing outside of the destination, device nodes and setuid/setgid bits.
+//! Each hazard can be rejected, skipped, sanitized or allowed and a report is returned.
+//!
+//! Mininal Example:
+//! ```ignore
+//! let policy = ExtractPolicy {
+//!     parent_traversal: PolicyAction::Reject,
+//!     ..Default::default()
+//! };
+//!
+//! let report = archives::archive_extract_all_with_policy(archive, destination, &policy).unwrap();
+//!
+//! println!("Skipped entries: {:?}", report.skipped);
+//!
+//! ```
+//!
+//! ## Listing
+//! Inspect the entries of an archive (path, size, permissions, modification time, CRC) without extracting it
+//!
+//! Mininal Example:
+//! ```ignore
+//! for entry in archives::archive_list(archive).unwrap() {
+//!     println!("{} [{} bytes]", entry.path.display(), entry.size);
+//! }
+//!
+//! ```
+//!
+//! ## Compression formats
+//! Tar, tar.gz and Zip archives are always supported, together with plain `.gz` files holding a single file.
+//! The `xz`, `bzip2` and `zstd` features add support for tar.xz, tar.bz2 and tar.zst archives.
+//! Files that match no known signature are rejected with an "unknown archive format" error.
+//!
+//! ```toml
+//! utils-box-archives = { version = "1", features = ["xz", "zstd"] }
+//! ```
+//!
+//! ## Partial extraction
+//! Extract every entry matching a glob or regex in one pass, keeping or flattening the directory structure
+//!
+//! Mininal Example:
+//! ```ignore
+//! let hex_files = archives::archive_extract_matching(
+//!     archive,
+//!     EntrySelector::glob("*.hex").unwrap(),
+//!     destination,
+//!     true,
+//! )
+//! .unwrap();
+//!
+//! ```
+//!
+//! ## In-memory reading
+//! Read a file stored inside an archive into a byte buffer or a reader, or iterate over all files as `(path, reader)` pairs, without touching the disk
+//! The `_with_limits` variants enforce `ExtractLimits` on the decompressed contents, so that a decompression bomb cannot exhaust the memory
+//!
+//! Mininal Example:
+//! ```ignore
+//! let config = readers::archive_read_file(archive.clone(), "config/app.ini".into()).unwrap();
+//!
+//! for entry in readers::archive_entries(archive).unwrap() {
+//!     let (path, reader) = entry.unwrap();
+//!     println!("{} [{} bytes]", path.display(), reader.get_ref().len());
+//! }
+//!
+//! ```
+//!
+//! ## Progress and cancellation
+//! Follow the progress of long extractions (entries and bytes processed vs. the total when known) and abort them from another thread.
+//! Everything written by a cancelled extraction is removed.
+//!
+//! Mininal Example:
+//! ```ignore
+//! let cancel = CancelToken::new();
+//!
+//! // e.g. cancel from a signal handler
+//! let handler_token = cancel.clone();
+//!
+//! let report = archives::archive_extract_all_with_progress(
+//!     archive,
+//!     destination,
+//!     &ExtractPolicy::default(),
+//!     |progress| println!("{} / {:?} bytes", progress.bytes, progress.bytes_total),
+//!     &cancel,
+//! );
+//!
+//! ```
+//!
+//! ## Verification
+//! Read back every entry of an archive checking zip CRC32s, gzip trailers and tar header checksums,
+//! optionally comparing each file with the SHA-256 digests of a manifest (supplied or read from a `SHA256SUMS` entry).
+//! The result is reported per entry.
+//!
+//! Mininal Example:
+//! ```ignore
+//! let report = verify::archive_verify(bundle, &Manifest::Sha256Sums).unwrap();
+//!
+//! if !report.is_ok() {
+//!     for entry in report.failed() {
+//!         println!("{}: {:?}", entry.path.display(), entry.status);
+//!     }
+//! }
+//!
+//! ```
+//!
+//! ## Encrypted zip archives
+//! Extract password protected zip archives (ZipCrypto and AES) and create AES-256 encrypted ones.
+//! Wrong or missing passwords are reported separately from corrupted data.
+//!
+//! Mininal Example:
+//! ```ignore
+//! archives::archive_extract_all_with_password(package, destination, "field-service").unwrap();
+//!
+//! archives::archive_extract_matching_with_password(package, EntrySelector::glob("*.ini").unwrap(), config_dir, true, "field-service").unwrap();
+//!
+//! archives::archive_create_all_with_password(logs, ArchiveType::Zip, "/tmp/logs.zip".into(), "s3cr3t").unwrap();
+//!
+//! ```
+//!
+//! ## Async extraction
+//! The `tokio` feature adds awaitable variants of the extraction, listing and reading utilities that run on the blocking thread pool.
+//! Dropping an extraction future cancels it and removes its partial output, so it can be raced against shutdown signals.
+//!
+//! Mininal Example:
+//! ```ignore
+//! tokio::select! {
+//!     result = async_archives::archive_extract_all_async(archive, destination) => result.unwrap(),
+//!     _ = shutdown.recv() => println!("Extraction aborted"),
+//! }
+//!
+//! ```
+//!
+//! ## Delta bundles
+//! Compare two archives by the SHA-256 digests of their files and ship only the changes between them.
+//! A delta bundle holds the updated and added files plus a `.delta-deleted` list and is applied on the extracted old release.
+//!
+//! Mininal Example:
+//! ```ignore
+//! let changes = delta::archive_compare("/tmp/v1.tar.gz".into(), "/tmp/v2.tar.gz".into()).unwrap();
+//! println!("Updated: {:?}", changes.updated);
+//!
+//! delta::archive_create_delta("/tmp/v1.tar.gz".into(), "/tmp/v2.tar.gz".into(), ArchiveType::Gz, "/tmp/v1_v2.tar.gz".into()).unwrap();
+//!
+//! // On the device
+//! delta::archive_apply_delta("/tmp/v1_v2.tar.gz".into(), "/opt/my_app".into()).unwrap();
+//!
+//! ```
+//!
+//! ## Atomic extraction
+//! Extract into a hidden staging directory next to the destination and swap it into place only once everything is synced to disk.
+//! A failed extraction leaves the destination untouched, and the previous contents can be restored with `archive_rollback`.
+//!
+//! Mininal Example:
+//! ```ignore
+//! atomic::archive_extract_all_atomic("/tmp/firmware_v2.tar.gz".into(), "/opt/firmware".into(), &ExtractPolicy::default()).unwrap();
+//!
+//! // The new firmware failed its health check
+//! atomic::archive_rollback("/opt/firmware".into()).unwrap();
+//!
+//! ```
+//!
+//! ## Nested archives
+//! Extract deliveries that hold more archives (e.g. a zip of tar.gz bundles) in one go.
+//! Inner archives are detected by their magic numbers and extracted into sibling directories up to `max_depth` levels,
+//! while the total size and entry count limits stop decompression bombs.
+//! Inner archives that are corrupted or in an unsupported format are left as-is and listed in the `invalid` entries of the report.
+//!
+//! Mininal Example:
+//! ```ignore
+//! let options = nested::NestedOptions {
+//!     max_depth: 2,
+//!     max_total_bytes: 512 * 1024 * 1024,
+//!     ..Default::default()
+//! };
+//!
+//! let report = nested::archive_extract_nested("/tmp/delivery.zip".into(), "/tmp/delivery".into(), &options).unwrap();
+//! println!("Inner archives: {:?}", report.archives);
+//!
+//! ```
+//!
+//! ## Extraction limits
+//! Cap the extracted bytes (in total and per entry), the number of entries and the compression ratio through the `ExtractLimits` of the policy.
+//! The limits are enforced while streaming tar, gz and zip entries and the `ArchiveError::LimitExceeded` error names the limit and the entry that hit it.
+//!
+//! Mininal Example:
+//! ```ignore
+//! let policy = ExtractPolicy {
+//!     limits: limits::ExtractLimits {
+//!         max_total_bytes: Some(64 * 1024 * 1024),
+//!         max_ratio: Some(100),
+//!         ..Default::default()
+//!     },
+//!     ..Default::default()
+//! };
+//!
+//! if let Err(ArchiveError::LimitExceeded(exceeded)) =
+//!     archives::archive_extract_all_with_policy(archive, destination, &policy)
+//! {
+//!     println!("Limit [{}] hit by [{}]", exceeded.limit, exceeded.entry.display());
+//! }
+//!
+//! ```
+//!
+//! ## Error handling
+//! The functions of `archives`, `readers`, `nested`, `verify`, `delta`, `atomic` and `async_archives` fail with an `ArchiveError` (`NotFound`, `UnsupportedFormat`, `Corrupt`, `Password`, `PolicyViolation`, `LimitExceeded`, `Cancelled`, `Io`, ...).
+//! It implements `std::error::Error`, so `?` still works in `anyhow` based code and `ArchiveError::from` recovers it from an `anyhow::Error`.
+//!
+//! Mininal Example:
+//! ```ignore
+//! match archives::archive_extract_file(archive, "config.ini".into(), destination) {
+//!     Ok(()) => println!("Extracted!"),
+//!     Err(ArchiveError::NotFound(_)) => println!("No configuration in this bundle"),
+//!     Err(ArchiveError::Corrupt(reason)) => println!("Broken bundle: {reason}"),
+//!     Err(e) => return Err(e.into()),
+//! }
+//!
+//! ```
+//!
 
 pub mod archives;
+#[cfg(feature = "tokio")]
+pub mod async_archives;
+pub mod atomic;
+pub mod delta;
+pub mod error;
+pub mod limits;
+pub mod nested;
+pub mod policy;
+pub mod progress;
+pub mod readers;
+pub mod verify;
@@ -0,0 +1,389 @@
+//! # Extraction limits utilities
+//! Resource limits enforced while streaming the entries of an archive to disk.
+//! Useful for protecting devices with small flash against decompression bombs,
+//! by capping the extracted bytes, the number of entries and the compression ratio.
+
+use anyhow::Result;
+use std::{
+    fmt,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Resource limits of a single extraction, `None` leaves the resource unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractLimits {
+    /// Maximum size in bytes of all extracted entries
+    pub max_total_bytes: Option<u64>,
+    /// Maximum size in bytes of a single extracted entry
+    pub max_entry_bytes: Option<u64>,
+    /// Maximum number of entries in the archive (including skipped ones)
+    pub max_entries: Option<u64>,
+    /// Maximum ratio of extracted to compressed bytes.
+    /// Checked per entry for zip archives and against the size of the archive file for all types.
+    pub max_ratio: Option<u64>,
+}
+
+/// Resource limits that can be exceeded during an extraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    TotalBytes,
+    EntryBytes,
+    Entries,
+    Ratio,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Limit::TotalBytes => "total bytes",
+            Limit::EntryBytes => "entry bytes",
+            Limit::Entries => "entries",
+            Limit::Ratio => "compression ratio",
+        };
+
+        write!(f, "{description}")
+    }
+}
+
+/// Error returned (as `ArchiveError::LimitExceeded`) when an extraction exceeds one of its `ExtractLimits`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    /// Entry (as named in the archive) that was being extracted when the limit was hit
+    pub entry: PathBuf,
+    /// Value that was reached (bytes, entries or ratio)
+    pub value: u64,
+    /// Configured maximum
+    pub max: u64,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[extract_limits] Entry [{}] exceeds the [{}] limit ([{}] > [{}])!",
+            self.entry.display(),
+            self.limit,
+            self.value,
+            self.max
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Usage of the limits during a single extraction
+pub(crate) struct Budget {
+    limits: ExtractLimits,
+    /// Size of the archive file, the compressed size of all entries
+    archive_size: u64,
+    total_bytes: u64,
+    entries: u64,
+    entry_bytes: u64,
+    /// Compressed size of the current entry (only known for zip archives)
+    entry_compressed: Option<u64>,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: &ExtractLimits, archive_size: u64) -> Self {
+        Self {
+            limits: limits.clone(),
+            archive_size,
+            total_bytes: 0,
+            entries: 0,
+            entry_bytes: 0,
+            entry_compressed: None,
+        }
+    }
+
+    /// Count a new entry and check its declared size (if known) before anything is written
+    pub(crate) fn start_entry(
+        &mut self,
+        name: &Path,
+        size: Option<u64>,
+        compressed_size: Option<u64>,
+    ) -> Result<()> {
+        self.entries += 1;
+        self.entry_bytes = 0;
+        self.entry_compressed = compressed_size;
+
+        exceeds(name, Limit::Entries, self.entries, self.limits.max_entries)?;
+
+        match size {
+            Some(size) => self.check(name, size),
+            None => Ok(()),
+        }
+    }
+
+    /// Count bytes written for the current entry
+    pub(crate) fn add_bytes(&mut self, name: &Path, bytes: u64) -> Result<()> {
+        self.check(name, bytes)?;
+
+        self.entry_bytes += bytes;
+        self.total_bytes += bytes;
+
+        Ok(())
+    }
+
+    /// Copy the contents of the current entry, failing as soon as a limit is exceeded
+    pub(crate) fn copy(
+        &mut self,
+        name: &Path,
+        reader: &mut impl Read,
+        writer: &mut impl Write,
+    ) -> Result<u64> {
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut copied: u64 = 0;
+
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => return Ok(copied),
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            self.add_bytes(name, read as u64)?;
+            writer.write_all(&buffer[..read])?;
+            copied += read as u64;
+        }
+    }
+
+    /// Check whether writing `bytes` more for the current entry stays within the limits
+    fn check(&self, name: &Path, bytes: u64) -> Result<()> {
+        let entry_bytes = self.entry_bytes.saturating_add(bytes);
+        let total_bytes = self.total_bytes.saturating_add(bytes);
+
+        exceeds(
+            name,
+            Limit::EntryBytes,
+            entry_bytes,
+            self.limits.max_entry_bytes,
+        )?;
+        exceeds(
+            name,
+            Limit::TotalBytes,
+            total_bytes,
+            self.limits.max_total_bytes,
+        )?;
+
+        if let Some(max_ratio) = self.limits.max_ratio {
+            for (extracted, compressed) in [
+                (entry_bytes, self.entry_compressed),
+                (total_bytes, Some(self.archive_size)),
+            ] {
+                let compressed = match compressed {
+                    Some(compressed) => compressed.max(1),
+                    None => continue,
+                };
+
+                if extracted > compressed.saturating_mul(max_ratio) {
+                    exceeds(name, Limit::Ratio, extracted / compressed, Some(max_ratio))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn exceeds(name: &Path, limit: Limit, value: u64, max: Option<u64>) -> Result<()> {
+    match max {
+        Some(max) if value > max => Err(LimitExceeded {
+            limit,
+            entry: name.to_path_buf(),
+            value,
+            max,
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::error::ArchiveError;
+    use crate::limits::*;
+    use crate::policy::{ExtractPolicy, ExtractReport};
+    use std::fs::File;
+
+    fn limited(limits: ExtractLimits) -> ExtractPolicy {
+        ExtractPolicy {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    fn limit_exceeded(result: Result<ExtractReport, ArchiveError>) -> LimitExceeded {
+        match result {
+            Err(ArchiveError::LimitExceeded(exceeded)) => exceeded,
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn extract_limits_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let source = dir.path().join("source");
+        std::fs::create_dir(&source).unwrap();
+        std::fs::write(source.join("small.txt"), "small").unwrap();
+        std::fs::write(source.join("zeros.bin"), vec![0u8; 1024 * 1024]).unwrap();
+
+        for (archive_type, name) in [
+            (ArchiveType::Tar, "bundle.tar"),
+            (ArchiveType::Gz, "bundle.tar.gz"),
+            (ArchiveType::Zip, "bundle.zip"),
+        ] {
+            let archive = dir.path().join(name);
+            archive_create_all(source.clone(), archive_type, archive.clone()).unwrap();
+
+            let destination = dir.path().join(format!("{name}.out"));
+
+            let exceeded = limit_exceeded(archive_extract_all_with_policy(
+                archive.clone(),
+                destination.clone(),
+                &limited(ExtractLimits {
+                    max_entry_bytes: Some(64 * 1024),
+                    ..Default::default()
+                }),
+            ));
+            assert_eq!(Limit::EntryBytes, exceeded.limit);
+            assert_eq!(PathBuf::from("zeros.bin"), exceeded.entry);
+            assert!(!destination.join("zeros.bin").exists());
+
+            let exceeded = limit_exceeded(archive_extract_all_with_policy(
+                archive.clone(),
+                destination.clone(),
+                &limited(ExtractLimits {
+                    max_entries: Some(1),
+                    ..Default::default()
+                }),
+            ));
+            assert_eq!(Limit::Entries, exceeded.limit);
+
+            // Plenty of room for everything
+            archive_extract_all_with_policy(
+                archive,
+                destination.clone(),
+                &limited(ExtractLimits {
+                    max_total_bytes: Some(2 * 1024 * 1024),
+                    max_entries: Some(2),
+                    ..Default::default()
+                }),
+            )
+            .unwrap();
+            assert!(destination.join("zeros.bin").exists());
+        }
+    }
+
+    #[test]
+    fn extract_limits_sparse_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // GNU sparse entry: 512 stored bytes at the end of a 4 MiB hole
+        let archive = dir.path().join("sparse.tar");
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::GNUSparse);
+        header.set_path("sparse.bin").unwrap();
+        header.set_size(512);
+        header.set_mode(0o644);
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.sparse[0].set_offset(4 * 1024 * 1024);
+        gnu.sparse[0].set_length(512);
+        gnu.set_real_size(4 * 1024 * 1024 + 512);
+        header.set_cksum();
+        builder.append(&header, &[0xAA; 512][..]).unwrap();
+        builder.into_inner().unwrap();
+
+        let destination = dir.path().join("out");
+        let exceeded = limit_exceeded(archive_extract_all_with_policy(
+            archive.clone(),
+            destination.clone(),
+            &limited(ExtractLimits {
+                max_entry_bytes: Some(1024 * 1024),
+                ..Default::default()
+            }),
+        ));
+        assert_eq!(Limit::EntryBytes, exceeded.limit);
+        assert!(!destination.join("sparse.bin").exists());
+
+        let exceeded = limit_exceeded(archive_extract_all_with_policy(
+            archive.clone(),
+            destination.clone(),
+            &limited(ExtractLimits {
+                max_ratio: Some(100),
+                ..Default::default()
+            }),
+        ));
+        assert_eq!(Limit::Ratio, exceeded.limit);
+        assert!(!destination.join("sparse.bin").exists());
+
+        // The expanded contents are written and counted when within the limits
+        let report = archive_extract_all_with_policy(
+            archive,
+            destination.clone(),
+            &limited(ExtractLimits {
+                max_total_bytes: Some(5 * 1024 * 1024),
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(vec![PathBuf::from("sparse.bin")], report.extracted);
+        let contents = std::fs::read(destination.join("sparse.bin")).unwrap();
+        assert_eq!(4 * 1024 * 1024 + 512, contents.len());
+        assert_eq!(&[0xAA; 512][..], &contents[4 * 1024 * 1024..]);
+    }
+
+    #[test]
+    fn extract_limits_ratio_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let source = dir.path().join("source");
+        std::fs::create_dir(&source).unwrap();
+        std::fs::write(source.join("zeros.bin"), vec![0u8; 4 * 1024 * 1024]).unwrap();
+
+        let limits = ExtractLimits {
+            max_ratio: Some(100),
+            ..Default::default()
+        };
+
+        for (archive_type, name) in [
+            (ArchiveType::Gz, "bomb.tar.gz"),
+            (ArchiveType::Zip, "bomb.zip"),
+        ] {
+            let archive = dir.path().join(name);
+            archive_create_all(source.clone(), archive_type, archive.clone()).unwrap();
+
+            let exceeded = limit_exceeded(archive_extract_all_with_policy(
+                archive,
+                dir.path().join("out"),
+                &limited(limits.clone()),
+            ));
+            assert_eq!(Limit::Ratio, exceeded.limit);
+            assert_eq!(100, exceeded.max);
+        }
+
+        // Plain .gz files are only checked while streaming
+        let archive = dir.path().join("zeros.bin.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::best(),
+        );
+        std::io::Write::write_all(&mut encoder, &vec![0u8; 4 * 1024 * 1024]).unwrap();
+        encoder.finish().unwrap();
+
+        let destination = dir.path().join("gz");
+        let exceeded = limit_exceeded(archive_extract_all_with_policy(
+            archive,
+            destination.clone(),
+            &limited(limits),
+        ));
+        assert_eq!(Limit::Ratio, exceeded.limit);
+        assert_eq!(PathBuf::from("zeros.bin"), exceeded.entry);
+        assert!(!destination.join("zeros.bin").exists());
+    }
+}
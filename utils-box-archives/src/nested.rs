@@ -0,0 +1,431 @@
+//! # Nested archives utilities
+//! Recursively extract archives that contain more archives (e.g. a zip delivery holding tar.gz bundles).
+//! Inner archives are detected by their magic numbers and extracted into sibling directories,
+//! while depth, size and entry count limits guard against decompression bombs.
+
+use anyhow::{Result, bail};
+use std::path::{Path, PathBuf};
+
+use utils_box_logger::{log_info, log_warn};
+
+use crate::archives::{
+    EntryKind, archive_extract_all_with_policy, archive_list, sniff_archive_type,
+};
+use crate::error::ArchiveError;
+use crate::limits::{Limit, LimitExceeded};
+use crate::policy::ExtractPolicy;
+
+/// Limits applied to a recursive extraction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NestedOptions {
+    /// Levels of inner archives to extract (`0` extracts only the outer archive)
+    pub max_depth: usize,
+    /// Maximum size in bytes of all files extracted from all levels
+    pub max_total_bytes: u64,
+    /// Maximum number of entries extracted from all levels
+    pub max_entries: u64,
+    /// Policy applied to the entries of every archive
+    pub policy: ExtractPolicy,
+}
+
+impl Default for NestedOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_total_bytes: 1024 * 1024 * 1024,
+            max_entries: 100_000,
+            policy: ExtractPolicy::default(),
+        }
+    }
+}
+
+/// Summary of a recursive extraction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NestedReport {
+    /// Paths (relative to the destination) that were written, including the contents of inner archives
+    pub extracted: Vec<PathBuf>,
+    /// Inner archives (relative to the destination) and the directories they were extracted into
+    pub archives: Vec<(PathBuf, PathBuf)>,
+    /// Inner archives (relative to the destination) left as-is because `max_depth` was reached
+    pub depth_exceeded: Vec<PathBuf>,
+    /// Files that look like inner archives (relative to the destination) left as-is because
+    /// their format is not supported (e.g. a plain .xz file or a disabled feature) or they are corrupted
+    pub invalid: Vec<PathBuf>,
+    /// Size in bytes of all extracted files
+    pub total_bytes: u64,
+    /// Number of extracted entries
+    pub entries: u64,
+}
+
+impl NestedReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Entry Point:
+/// Extract all contents from the provided archive into the selected destination directory
+/// and then every inner archive into a sibling directory named after it (e.g. `bundle.tar.gz` into `bundle/`)
+/// The limits of `NestedOptions` are checked against the entry headers before each archive is extracted
+/// and enforced while streaming its entries through the `ExtractLimits` of the policy
+pub fn archive_extract_nested(
+    archive: PathBuf,
+    destination: PathBuf,
+    options: &NestedOptions,
+) -> Result<NestedReport, ArchiveError> {
+    let mut report = NestedReport::new();
+
+    extract_level(
+        &archive,
+        &destination,
+        Path::new(""),
+        0,
+        options,
+        &mut report,
+    )?;
+
+    log_info!(
+        "[archive_extract_nested] Extracted [{}] entries ([{}] bytes) from [{}] inner archives into [{}]",
+        report.entries,
+        report.total_bytes,
+        report.archives.len(),
+        destination.display()
+    );
+
+    Ok(report)
+}
+
+/// Extract a single archive into `destination.join(base)` and recurse into the inner archives found in it
+fn extract_level(
+    archive: &Path,
+    destination: &Path,
+    base: &Path,
+    depth: usize,
+    options: &NestedOptions,
+    report: &mut NestedReport,
+) -> Result<()> {
+    // The headers give the sizes up front, so a bomb is rejected before anything is written
+    let entries = archive_list(archive.to_path_buf())?;
+
+    let declared_bytes: u64 = entries
+        .iter()
+        .filter(|entry| entry.kind == EntryKind::File)
+        .map(|entry| entry.size)
+        .sum();
+
+    check_limits(
+        archive,
+        report.entries + entries.len() as u64,
+        report.total_bytes.saturating_add(declared_bytes),
+        options,
+    )?;
+
+    // What is left of the budget is enforced while streaming, as the headers may lie (e.g. zip entries)
+    let mut policy = options.policy.clone();
+    policy.limits.max_total_bytes = Some(
+        policy
+            .limits
+            .max_total_bytes
+            .unwrap_or(u64::MAX)
+            .min(options.max_total_bytes.saturating_sub(report.total_bytes)),
+    );
+    policy.limits.max_entries = Some(
+        policy
+            .limits
+            .max_entries
+            .unwrap_or(u64::MAX)
+            .min(options.max_entries.saturating_sub(report.entries)),
+    );
+
+    let level =
+        archive_extract_all_with_policy(archive.to_path_buf(), destination.join(base), &policy)?;
+
+    let mut inner: Vec<PathBuf> = vec![];
+
+    for path in level.extracted {
+        let full_path = destination.join(base).join(&path);
+
+        let metadata = std::fs::symlink_metadata(&full_path)?;
+        if metadata.is_file() {
+            report.total_bytes += metadata.len();
+
+            if sniff_archive_type(&full_path).unwrap_or(None).is_some() {
+                inner.push(base.join(&path));
+            }
+        }
+
+        report.entries += 1;
+        report.extracted.push(base.join(path));
+    }
+
+    // Hard links are not limited while streaming, but count the size of their source again
+    check_limits(archive, report.entries, report.total_bytes, options)?;
+
+    for inner_archive in inner {
+        if depth >= options.max_depth {
+            log_warn!(
+                "[archive_extract_nested] Inner archive [{}] left as-is, maximum depth [{}] reached",
+                inner_archive.display(),
+                options.max_depth
+            );
+            report.depth_exceeded.push(inner_archive);
+            continue;
+        }
+
+        let inner_base = sibling_dir(destination, &inner_archive);
+        let existed = destination.join(&inner_base).exists();
+
+        let extracted = extract_level(
+            &destination.join(&inner_archive),
+            destination,
+            &inner_base,
+            depth + 1,
+            options,
+            report,
+        );
+
+        // Only the failures of the inner archive itself are tolerated, limits and I/O errors abort the extraction
+        if let Err(e) = extracted {
+            let e = ArchiveError::from(e);
+
+            if !matches!(
+                e,
+                ArchiveError::UnsupportedFormat(_) | ArchiveError::Corrupt(_)
+            ) {
+                bail!(e);
+            }
+
+            log_warn!(
+                "[archive_extract_nested] Inner archive [{}] left as-is: {}",
+                inner_archive.display(),
+                e
+            );
+
+            if !existed {
+                let _ = std::fs::remove_dir_all(destination.join(&inner_base));
+            }
+
+            report.invalid.push(inner_archive);
+            continue;
+        }
+
+        report.archives.push((inner_archive, inner_base));
+    }
+
+    Ok(())
+}
+
+/// Limits of all levels, reported as `ArchiveError::LimitExceeded` for the archive being extracted
+fn check_limits(archive: &Path, entries: u64, bytes: u64, options: &NestedOptions) -> Result<()> {
+    if entries > options.max_entries {
+        bail!(ArchiveError::LimitExceeded(LimitExceeded {
+            limit: Limit::Entries,
+            entry: archive.to_path_buf(),
+            value: entries,
+            max: options.max_entries,
+        }));
+    }
+
+    if bytes > options.max_total_bytes {
+        bail!(ArchiveError::LimitExceeded(LimitExceeded {
+            limit: Limit::TotalBytes,
+            entry: archive.to_path_buf(),
+            value: bytes,
+            max: options.max_total_bytes,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Directory (relative to the destination) next to the inner archive named after it without the archive extensions
+/// Falls back to `<name>.extracted` if the name has no archive extension or the path is already taken
+fn sibling_dir(destination: &Path, inner_archive: &Path) -> PathBuf {
+    let name = inner_archive
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let stem = [
+        ".tar.gz", ".tgz", ".tar.xz", ".txz", ".tar.bz2", ".tbz2", ".tar.zst", ".tzst", ".tar",
+        ".zip", ".gz",
+    ]
+    .iter()
+    .find_map(|extension| name.strip_suffix(extension))
+    .filter(|stem| !stem.is_empty());
+
+    let dir = inner_archive.with_file_name(stem.unwrap_or(&name));
+
+    if dir == inner_archive || destination.join(&dir).exists() {
+        inner_archive.with_file_name(format!("{name}.extracted"))
+    } else {
+        dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::nested::*;
+
+    /// Supplier delivery: a zip holding a tar.gz bundle that holds a tar of documents
+    fn delivery(dir: &Path) -> PathBuf {
+        let docs = dir.join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("manual.txt"), "read me").unwrap();
+
+        let bundle = dir.join("bundle");
+        std::fs::create_dir_all(bundle.join("bin")).unwrap();
+        std::fs::write(bundle.join("bin/app"), "app").unwrap();
+        archive_create_all(docs, ArchiveType::Tar, bundle.join("docs.tar")).unwrap();
+
+        let outer = dir.join("outer");
+        std::fs::create_dir_all(&outer).unwrap();
+        std::fs::write(outer.join("notes.txt"), "delivery notes").unwrap();
+        archive_create_all(bundle, ArchiveType::Gz, outer.join("bundle.tar.gz")).unwrap();
+
+        let archive = dir.join("delivery.zip");
+        archive_create_all(outer, ArchiveType::Zip, archive.clone()).unwrap();
+
+        archive
+    }
+
+    #[test]
+    fn archive_extract_nested_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = delivery(dir.path());
+
+        let destination = dir.path().join("all");
+        let report = archive_extract_nested(
+            archive.clone(),
+            destination.clone(),
+            &NestedOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "read me",
+            std::fs::read_to_string(destination.join("bundle/docs/manual.txt")).unwrap()
+        );
+        assert_eq!(
+            "app",
+            std::fs::read_to_string(destination.join("bundle/bin/app")).unwrap()
+        );
+        assert_eq!(
+            vec![
+                (
+                    PathBuf::from("bundle/docs.tar"),
+                    PathBuf::from("bundle/docs")
+                ),
+                (PathBuf::from("bundle.tar.gz"), PathBuf::from("bundle"))
+            ],
+            report.archives
+        );
+        assert!(report.depth_exceeded.is_empty());
+
+        // Only the first level of inner archives
+        let destination = dir.path().join("shallow");
+        let options = NestedOptions {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let report = archive_extract_nested(archive, destination.clone(), &options).unwrap();
+
+        assert!(destination.join("bundle/docs.tar").exists());
+        assert!(!destination.join("bundle/docs").exists());
+        assert_eq!(
+            vec![PathBuf::from("bundle/docs.tar")],
+            report.depth_exceeded
+        );
+    }
+
+    #[test]
+    fn archive_extract_nested_invalid_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let docs = dir.path().join("docs");
+        std::fs::create_dir(&docs).unwrap();
+        std::fs::write(docs.join("manual.txt"), "read me".repeat(1000)).unwrap();
+
+        let outer = dir.path().join("outer");
+        std::fs::create_dir(&outer).unwrap();
+        archive_create_all(docs.clone(), ArchiveType::Tar, outer.join("docs.tar")).unwrap();
+
+        // Inner archive truncated in the middle of the contents of its entry
+        archive_create_all(docs, ArchiveType::Tar, outer.join("broken.tar")).unwrap();
+        let bytes = std::fs::read(outer.join("broken.tar")).unwrap();
+        std::fs::write(outer.join("broken.tar"), &bytes[..2048]).unwrap();
+
+        let archive = dir.path().join("delivery.tar");
+        archive_create_all(outer, ArchiveType::Tar, archive.clone()).unwrap();
+
+        let destination = dir.path().join("all");
+        let report =
+            archive_extract_nested(archive, destination.clone(), &NestedOptions::default())
+                .unwrap();
+
+        assert_eq!(vec![PathBuf::from("broken.tar")], report.invalid);
+        assert_eq!(
+            vec![(PathBuf::from("docs.tar"), PathBuf::from("docs"))],
+            report.archives
+        );
+
+        // The corrupted archive is kept, without a half extracted directory next to it
+        assert!(destination.join("broken.tar").exists());
+        assert!(!destination.join("broken").exists());
+        assert!(destination.join("docs/manual.txt").exists());
+    }
+
+    #[test]
+    fn archive_extract_nested_limits_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Highly compressible payload inside a tar.gz inside a tar.gz
+        let payload = dir.path().join("payload");
+        std::fs::create_dir(&payload).unwrap();
+        std::fs::write(payload.join("zeros.bin"), vec![0u8; 4 * 1024 * 1024]).unwrap();
+
+        let inner = dir.path().join("inner");
+        std::fs::create_dir(&inner).unwrap();
+        archive_create_all(payload, ArchiveType::Gz, inner.join("payload.tar.gz")).unwrap();
+
+        let archive = dir.path().join("bomb.tar.gz");
+        archive_create_all(inner, ArchiveType::Gz, archive.clone()).unwrap();
+
+        let options = NestedOptions {
+            max_total_bytes: 1024 * 1024,
+            ..Default::default()
+        };
+        let destination = dir.path().join("bytes");
+        let error =
+            archive_extract_nested(archive.clone(), destination.clone(), &options).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                ArchiveError::LimitExceeded(LimitExceeded {
+                    limit: Limit::TotalBytes,
+                    ..
+                })
+            ),
+            "{error:?}"
+        );
+        assert!(!destination.join("payload/zeros.bin").exists());
+
+        let options = NestedOptions {
+            max_entries: 1,
+            ..Default::default()
+        };
+        let error =
+            archive_extract_nested(archive, dir.path().join("entries"), &options).unwrap_err();
+        assert!(
+            matches!(
+                error,
+                ArchiveError::LimitExceeded(LimitExceeded {
+                    limit: Limit::Entries,
+                    ..
+                })
+            ),
+            "{error:?}"
+        );
+    }
+}
@@ -0,0 +1,747 @@
+//! # Extraction policy utilities
+//! Rules applied to every entry while extracting archives from semi-trusted sources.
+//! Useful for protecting the destination directory against path traversal (zip-slip),
+//! links pointing outside of it, device nodes and setuid/setgid binaries.
+
+use anyhow::{Result, bail};
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use utils_box_logger::log_trace;
+
+use crate::archives::EntryKind;
+use crate::error::ArchiveError;
+use crate::limits::ExtractLimits;
+
+/// Action taken when an archive entry triggers one of the policy hazards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyAction {
+    /// Abort the whole extraction with an error
+    Reject,
+    /// Do not extract the entry and continue with the rest of the archive
+    Skip,
+    /// Fix the entry (e.g. strip the offending path components or permission bits) and extract it.
+    /// Hazards that cannot be fixed (links and device nodes) are skipped instead.
+    Sanitize,
+    /// Extract the entry as-is
+    Allow,
+}
+
+/// Classes of dangerous archive entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hazard {
+    /// Entry path starts from the filesystem root (e.g. `/etc/passwd`)
+    AbsolutePath,
+    /// Entry path contains `..` components (e.g. `../../etc/passwd`)
+    ParentTraversal,
+    /// Symbolic or hard link pointing outside of the destination directory
+    ExternalLink,
+    /// Character/block device or FIFO
+    DeviceNode,
+    /// Entry permissions include the setuid or setgid bits
+    SetuidBits,
+}
+
+impl fmt::Display for Hazard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Hazard::AbsolutePath => "absolute path",
+            Hazard::ParentTraversal => "parent directory traversal",
+            Hazard::ExternalLink => "link outside of destination",
+            Hazard::DeviceNode => "device node",
+            Hazard::SetuidBits => "setuid/setgid permissions",
+        };
+
+        write!(f, "{description}")
+    }
+}
+
+/// Policy applied to each entry during extraction.
+/// The default policy is the safe choice for archives from semi-trusted sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractPolicy {
+    pub absolute_paths: PolicyAction,
+    pub parent_traversal: PolicyAction,
+    pub external_links: PolicyAction,
+    pub device_nodes: PolicyAction,
+    pub setuid_bits: PolicyAction,
+    /// Resource limits enforced while extracting (unlimited by default)
+    pub limits: ExtractLimits,
+}
+
+impl Default for ExtractPolicy {
+    fn default() -> Self {
+        Self {
+            absolute_paths: PolicyAction::Sanitize,
+            parent_traversal: PolicyAction::Skip,
+            external_links: PolicyAction::Skip,
+            device_nodes: PolicyAction::Skip,
+            setuid_bits: PolicyAction::Sanitize,
+            limits: ExtractLimits::default(),
+        }
+    }
+}
+
+/// Summary of an extraction performed under an `ExtractPolicy`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtractReport {
+    /// Paths (relative to the destination) that were written
+    pub extracted: Vec<PathBuf>,
+    /// Entries (as named in the archive) that were not extracted and the reason why
+    pub skipped: Vec<(PathBuf, Hazard)>,
+    /// Entries (as named in the archive) that were modified before being extracted
+    pub sanitized: Vec<(PathBuf, Hazard)>,
+}
+
+impl ExtractReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Entry that passed the policy checks, ready to be written in the destination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CheckedEntry {
+    /// Normalized path relative to the destination directory
+    pub path: PathBuf,
+    /// Permissions to apply (with setuid/setgid stripped if sanitized)
+    pub mode: Option<u32>,
+    /// Whether the setuid/setgid bits may be kept
+    pub preserve_special_bits: bool,
+}
+
+impl ExtractPolicy {
+    /// Create a new policy that rejects every hazard
+    pub fn strict() -> Self {
+        Self {
+            absolute_paths: PolicyAction::Reject,
+            parent_traversal: PolicyAction::Reject,
+            external_links: PolicyAction::Reject,
+            device_nodes: PolicyAction::Reject,
+            setuid_bits: PolicyAction::Reject,
+            limits: ExtractLimits::default(),
+        }
+    }
+
+    /// Create a new policy that extracts everything as-is.
+    /// Only use it with archives from fully trusted sources!
+    pub fn permissive() -> Self {
+        Self {
+            absolute_paths: PolicyAction::Allow,
+            parent_traversal: PolicyAction::Allow,
+            external_links: PolicyAction::Allow,
+            device_nodes: PolicyAction::Allow,
+            setuid_bits: PolicyAction::Allow,
+            limits: ExtractLimits::default(),
+        }
+    }
+
+    pub fn action(&self, hazard: Hazard) -> PolicyAction {
+        match hazard {
+            Hazard::AbsolutePath => self.absolute_paths,
+            Hazard::ParentTraversal => self.parent_traversal,
+            Hazard::ExternalLink => self.external_links,
+            Hazard::DeviceNode => self.device_nodes,
+            Hazard::SetuidBits => self.setuid_bits,
+        }
+    }
+
+    /// Apply the policy on a single archive entry.
+    /// Returns `Ok(None)` if the entry must be skipped and an error if it must be rejected.
+    /// `link` is the target of symbolic links and the (archive) source path of hard links.
+    pub(crate) fn check(
+        &self,
+        name: &Path,
+        kind: EntryKind,
+        mode: Option<u32>,
+        link: Option<&Path>,
+        report: &mut ExtractReport,
+    ) -> Result<Option<CheckedEntry>> {
+        let mut hazards: Vec<Hazard> = vec![];
+
+        if name.has_root() {
+            hazards.push(Hazard::AbsolutePath);
+        }
+        if name.components().any(|c| c == Component::ParentDir) {
+            hazards.push(Hazard::ParentTraversal);
+        }
+
+        let path = normalize(name);
+
+        // Symbolic links resolve from their own directory, hard links from the archive root
+        let external_link = match (kind, link) {
+            (EntryKind::Symlink, Some(target)) => {
+                let base = path.parent().unwrap_or(Path::new(""));
+                target.has_root() || resolve(base, target).is_none()
+            }
+            (EntryKind::HardLink, Some(source)) => {
+                source.has_root() || resolve(Path::new(""), source).is_none()
+            }
+            _ => false,
+        };
+
+        if external_link {
+            hazards.push(Hazard::ExternalLink);
+        }
+        if kind.is_device() {
+            hazards.push(Hazard::DeviceNode);
+        }
+        if mode.is_some_and(|m| m & 0o6000 != 0) {
+            hazards.push(Hazard::SetuidBits);
+        }
+
+        let mut checked = CheckedEntry {
+            path: if hazards.contains(&Hazard::AbsolutePath)
+                || hazards.contains(&Hazard::ParentTraversal)
+            {
+                // Keep the raw path for `Allow`, it will be sanitized below if requested
+                name.to_path_buf()
+            } else {
+                path.clone()
+            },
+            mode,
+            preserve_special_bits: true,
+        };
+
+        // Rejections take precedence, then skips, then sanitizations
+        if let Some(hazard) = hazards
+            .iter()
+            .find(|&&h| self.action(h) == PolicyAction::Reject)
+        {
+            bail!(ArchiveError::PolicyViolation {
+                entry: name.to_path_buf(),
+                hazard: *hazard,
+            });
+        }
+
+        let cannot_sanitize = |h: Hazard| matches!(h, Hazard::ExternalLink | Hazard::DeviceNode);
+
+        if let Some(&hazard) = hazards.iter().find(|&&h| {
+            self.action(h) == PolicyAction::Skip
+                || (self.action(h) == PolicyAction::Sanitize && cannot_sanitize(h))
+        }) {
+            log_trace!(
+                "[extract_policy] Entry [{}] skipped due to [{}]",
+                name.display(),
+                hazard
+            );
+            report.skipped.push((name.to_path_buf(), hazard));
+            return Ok(None);
+        }
+
+        for hazard in hazards
+            .into_iter()
+            .filter(|&h| self.action(h) == PolicyAction::Sanitize)
+        {
+            match hazard {
+                Hazard::AbsolutePath | Hazard::ParentTraversal => checked.path = path.clone(),
+                Hazard::SetuidBits => {
+                    checked.mode = mode.map(|m| m & !0o6000);
+                    checked.preserve_special_bits = false;
+                }
+                Hazard::ExternalLink | Hazard::DeviceNode => unreachable!(),
+            }
+
+            log_trace!(
+                "[extract_policy] Entry [{}] sanitized due to [{}]",
+                name.display(),
+                hazard
+            );
+            report.sanitized.push((name.to_path_buf(), hazard));
+        }
+
+        Ok(Some(checked))
+    }
+}
+
+/// Lexically normalize an archive path into a plain relative path.
+/// Root, prefix and `.` components are dropped and `..` never climbs above the root.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+
+    normalized
+}
+
+/// Lexically resolve a relative `target` from the `base` directory.
+/// Returns `None` if the result would escape above the root.
+fn resolve(base: &Path, target: &Path) -> Option<PathBuf> {
+    let mut resolved = normalize(base);
+
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => {}
+            Component::Prefix(_) | Component::RootDir => return None,
+        }
+    }
+
+    Some(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::policy::*;
+    use std::io::Write;
+    use tar::{Builder, EntryType, Header};
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    /// Write a raw tar header so that names rejected by `tar::Builder` (`..`, `/`) can be stored
+    fn append_raw(
+        builder: &mut Builder<std::fs::File>,
+        name: &str,
+        kind: EntryType,
+        mode: u32,
+        link: Option<&str>,
+        data: &[u8],
+    ) {
+        let mut header = Header::new_gnu();
+        let raw_name = &mut header.as_old_mut().name;
+        raw_name[..name.len()].copy_from_slice(name.as_bytes());
+
+        if let Some(link) = link {
+            let raw_link = &mut header.as_old_mut().linkname;
+            raw_link[..link.len()].copy_from_slice(link.as_bytes());
+        }
+
+        header.set_entry_type(kind);
+        header.set_mode(mode);
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+
+        builder.append(&header, data).unwrap();
+    }
+
+    fn malicious_tar(path: &Path) {
+        let mut builder = Builder::new(std::fs::File::create(path).unwrap());
+
+        append_raw(
+            &mut builder,
+            "safe.txt",
+            EntryType::Regular,
+            0o644,
+            None,
+            b"safe",
+        );
+        append_raw(
+            &mut builder,
+            "../evil.txt",
+            EntryType::Regular,
+            0o644,
+            None,
+            b"evil",
+        );
+        append_raw(
+            &mut builder,
+            "/abs.txt",
+            EntryType::Regular,
+            0o644,
+            None,
+            b"abs",
+        );
+        append_raw(
+            &mut builder,
+            "suid",
+            EntryType::Regular,
+            0o4755,
+            None,
+            b"#!/bin/sh",
+        );
+        append_raw(
+            &mut builder,
+            "passwd",
+            EntryType::Symlink,
+            0o777,
+            Some("/etc/passwd"),
+            b"",
+        );
+        append_raw(
+            &mut builder,
+            "up",
+            EntryType::Symlink,
+            0o777,
+            Some("../.."),
+            b"",
+        );
+        append_raw(
+            &mut builder,
+            "inside",
+            EntryType::Symlink,
+            0o777,
+            Some("safe.txt"),
+            b"",
+        );
+        append_raw(
+            &mut builder,
+            "hard",
+            EntryType::Link,
+            0o644,
+            Some("../../etc/shadow"),
+            b"",
+        );
+        append_raw(&mut builder, "tty", EntryType::Char, 0o666, None, b"");
+
+        builder.into_inner().unwrap().flush().unwrap();
+    }
+
+    fn malicious_zip(path: &Path) {
+        let mut zip = ZipWriter::new(std::fs::File::create(path).unwrap());
+
+        zip.start_file("safe.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"safe").unwrap();
+        zip.start_file("../evil.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.start_file("/abs.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"abs").unwrap();
+        zip.start_file(
+            "suid",
+            SimpleFileOptions::default().unix_permissions(0o4755),
+        )
+        .unwrap();
+        zip.write_all(b"#!/bin/sh").unwrap();
+        zip.add_symlink("passwd", "/etc/passwd", SimpleFileOptions::default())
+            .unwrap();
+
+        zip.finish().unwrap();
+    }
+
+    /// Zip with a chain of symbolic links that only escapes the destination when resolved on disk
+    fn symlink_chain_zip(path: &Path, second_link: &str, file: &str) {
+        let mut zip = ZipWriter::new(std::fs::File::create(path).unwrap());
+
+        zip.add_symlink("a/b", "..", SimpleFileOptions::default())
+            .unwrap();
+        zip.add_symlink("a/b/c", second_link, SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file(file, SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"escaped").unwrap();
+
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_chain_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // `a/b/c` points to `../escaped.txt` from the destination itself
+        let archive = dir.path().join("chain_file.zip");
+        let destination = dir.path().join("out_file");
+        symlink_chain_zip(&archive, "../escaped.txt", "c");
+
+        archive_extract_all(archive.clone(), destination.clone()).unwrap();
+
+        assert!(!dir.path().join("escaped.txt").exists());
+        assert!(destination.join("c").symlink_metadata().unwrap().is_file());
+        assert_eq!(
+            "escaped",
+            std::fs::read_to_string(destination.join("c")).unwrap()
+        );
+
+        let report =
+            archive_extract_all_with_policy(archive, destination, &ExtractPolicy::default())
+                .unwrap();
+        assert_eq!(
+            vec![(PathBuf::from("a/b/c"), Hazard::ExternalLink)],
+            report.skipped
+        );
+
+        // `a/b/c` points to the parent of the destination
+        let archive = dir.path().join("chain_dir.zip");
+        let destination = dir.path().join("out_dir");
+        symlink_chain_zip(&archive, "..", "c/newdir/f");
+
+        archive_extract_all(archive, destination.clone()).unwrap();
+
+        assert!(!dir.path().join("newdir").exists());
+        assert!(destination.join("c/newdir/f").is_file());
+
+        // Links already present in the destination are never written or created through
+        let archive = dir.path().join("planted.zip");
+        let destination = dir.path().join("out_planted");
+        std::fs::create_dir(&destination).unwrap();
+        std::os::unix::fs::symlink("../planted.txt", destination.join("c")).unwrap();
+        std::os::unix::fs::symlink("..", destination.join("up")).unwrap();
+        std::fs::write(dir.path().join("planted.txt"), "outside").unwrap();
+
+        let mut zip = ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        zip.start_file("c", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"inside").unwrap();
+        zip.start_file("up/newdir/f", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"escaped").unwrap();
+        zip.finish().unwrap();
+
+        let report = archive_extract_all_with_policy(
+            archive,
+            destination.clone(),
+            &ExtractPolicy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "outside",
+            std::fs::read_to_string(dir.path().join("planted.txt")).unwrap()
+        );
+        assert_eq!(
+            "inside",
+            std::fs::read_to_string(destination.join("c")).unwrap()
+        );
+        assert!(!dir.path().join("newdir").exists());
+        assert_eq!(
+            vec![(PathBuf::from("up/newdir/f"), Hazard::ExternalLink)],
+            report.skipped
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn hard_link_through_symlink_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("hard.tar");
+        let destination = dir.path().join("out");
+
+        std::fs::create_dir_all(dir.path().join("outside")).unwrap();
+        std::fs::write(dir.path().join("outside/secret"), "secret").unwrap();
+        std::fs::create_dir(&destination).unwrap();
+        std::os::unix::fs::symlink("../outside", destination.join("link")).unwrap();
+
+        let mut builder = Builder::new(std::fs::File::create(&archive).unwrap());
+        append_raw(
+            &mut builder,
+            "safe.txt",
+            EntryType::Regular,
+            0o644,
+            None,
+            b"safe",
+        );
+        append_raw(
+            &mut builder,
+            "copy",
+            EntryType::Link,
+            0o644,
+            Some("safe.txt"),
+            b"",
+        );
+        append_raw(
+            &mut builder,
+            "hard",
+            EntryType::Link,
+            0o644,
+            Some("link/secret"),
+            b"",
+        );
+        builder.into_inner().unwrap().flush().unwrap();
+
+        let report = archive_extract_all_with_policy(
+            archive,
+            destination.clone(),
+            &ExtractPolicy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "safe",
+            std::fs::read_to_string(destination.join("copy")).unwrap()
+        );
+        assert!(destination.join("hard").symlink_metadata().is_err());
+        assert_eq!(
+            vec![(PathBuf::from("hard"), Hazard::ExternalLink)],
+            report.skipped
+        );
+    }
+
+    #[test]
+    fn normalize_test() {
+        assert_eq!(
+            PathBuf::from("etc/passwd"),
+            normalize(Path::new("/etc/passwd"))
+        );
+        assert_eq!(
+            PathBuf::from("evil.txt"),
+            normalize(Path::new("../../evil.txt"))
+        );
+        assert_eq!(PathBuf::from("a/c"), normalize(Path::new("./a/b/../c")));
+        assert_eq!(
+            Some(PathBuf::from("a/c")),
+            resolve(Path::new("a/b"), Path::new("../c"))
+        );
+        assert_eq!(None, resolve(Path::new("a"), Path::new("../../c")));
+    }
+
+    #[test]
+    fn default_policy_tar_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("malicious.tar");
+        let destination = dir.path().join("out");
+
+        malicious_tar(&archive);
+
+        let report = archive_extract_all_with_policy(
+            archive,
+            destination.clone(),
+            &ExtractPolicy::default(),
+        )
+        .unwrap();
+
+        assert!(destination.join("safe.txt").exists());
+        assert!(destination.join("abs.txt").exists());
+        assert!(destination.join("inside").exists());
+        assert!(!dir.path().join("evil.txt").exists());
+        assert!(!destination.join("evil.txt").exists());
+        assert!(destination.join("passwd").symlink_metadata().is_err());
+        assert!(destination.join("up").symlink_metadata().is_err());
+        assert!(!destination.join("hard").exists());
+        assert!(!destination.join("tty").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(destination.join("suid"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(0o755, mode & 0o7777);
+        }
+
+        assert_eq!(
+            vec![
+                (PathBuf::from("../evil.txt"), Hazard::ParentTraversal),
+                (PathBuf::from("passwd"), Hazard::ExternalLink),
+                (PathBuf::from("up"), Hazard::ExternalLink),
+                (PathBuf::from("hard"), Hazard::ExternalLink),
+                (PathBuf::from("tty"), Hazard::DeviceNode),
+            ],
+            report.skipped
+        );
+        assert_eq!(
+            vec![
+                (PathBuf::from("/abs.txt"), Hazard::AbsolutePath),
+                (PathBuf::from("suid"), Hazard::SetuidBits),
+            ],
+            report.sanitized
+        );
+    }
+
+    #[test]
+    fn sanitize_policy_tar_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("malicious.tar");
+        let destination = dir.path().join("out");
+
+        malicious_tar(&archive);
+
+        let policy = ExtractPolicy {
+            parent_traversal: PolicyAction::Sanitize,
+            ..Default::default()
+        };
+
+        let report =
+            archive_extract_all_with_policy(archive, destination.clone(), &policy).unwrap();
+
+        // The `..` component is stripped and the file lands inside the destination
+        assert_eq!(
+            "evil",
+            std::fs::read_to_string(destination.join("evil.txt")).unwrap()
+        );
+        assert!(!dir.path().join("evil.txt").exists());
+        assert!(
+            report
+                .sanitized
+                .contains(&(PathBuf::from("../evil.txt"), Hazard::ParentTraversal))
+        );
+    }
+
+    #[test]
+    fn strict_policy_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let tar_archive = dir.path().join("malicious.tar");
+        malicious_tar(&tar_archive);
+
+        let zip_archive = dir.path().join("malicious.zip");
+        malicious_zip(&zip_archive);
+
+        for archive in [tar_archive, zip_archive] {
+            assert!(
+                archive_extract_all_with_policy(
+                    archive,
+                    dir.path().join("strict"),
+                    &ExtractPolicy::strict(),
+                )
+                .is_err()
+            );
+            assert!(!dir.path().join("evil.txt").exists());
+        }
+    }
+
+    #[test]
+    fn default_policy_zip_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("malicious.zip");
+        let destination = dir.path().join("out");
+
+        malicious_zip(&archive);
+
+        let report = archive_extract_all_with_policy(
+            archive,
+            destination.clone(),
+            &ExtractPolicy::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            "safe",
+            std::fs::read_to_string(destination.join("safe.txt")).unwrap()
+        );
+        assert!(destination.join("abs.txt").exists());
+        assert!(!dir.path().join("evil.txt").exists());
+        assert!(destination.join("passwd").symlink_metadata().is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(destination.join("suid"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(0o755, mode & 0o7777);
+        }
+
+        assert_eq!(
+            vec![
+                (PathBuf::from("../evil.txt"), Hazard::ParentTraversal),
+                (PathBuf::from("passwd"), Hazard::ExternalLink),
+            ],
+            report.skipped
+        );
+        assert_eq!(
+            vec![
+                PathBuf::from("safe.txt"),
+                PathBuf::from("abs.txt"),
+                PathBuf::from("suid"),
+            ],
+            report.extracted
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! # Extraction progress utilities
+//! Progress reporting and cancellation of long running extractions.
+//! Useful for showing feedback while unpacking large bundles and for aborting them gracefully
+//! (e.g. from a signal handler or a UI thread).
+
+use std::{
+    cell::RefCell,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// Token used to cancel an extraction from another thread.
+/// Clones share the same state, so one clone can be handed to the extraction and another one kept for cancelling it.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the cancellation of every extraction using this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Snapshot of the progress of an extraction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtractProgress {
+    /// Entries of the archive processed so far
+    pub entries: u64,
+    /// Number of entries in the archive (only known up front for zip and plain .gz files)
+    pub entries_total: Option<u64>,
+    /// Bytes read from the archive file so far
+    pub bytes: u64,
+    /// Size of the archive file
+    pub bytes_total: Option<u64>,
+    /// Path of the entry currently being processed, as named in the archive
+    pub entry: PathBuf,
+}
+
+/// Shared state of an extraction, updated both by the extraction loop and by the archive reader
+pub(crate) struct Tracker<'a> {
+    progress: ExtractProgress,
+    callback: &'a mut dyn FnMut(&ExtractProgress),
+    cancel: CancelToken,
+    /// Entry that is being written and has not completed yet
+    partial: Option<PathBuf>,
+}
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new(
+        bytes_total: Option<u64>,
+        callback: &'a mut dyn FnMut(&ExtractProgress),
+        cancel: CancelToken,
+    ) -> Self {
+        Self {
+            progress: ExtractProgress {
+                bytes_total,
+                ..Default::default()
+            },
+            callback,
+            cancel,
+            partial: None,
+        }
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub(crate) fn set_entries_total(&mut self, entries_total: u64) {
+        self.progress.entries_total = Some(entries_total);
+    }
+
+    /// Entry (as named in the archive) that was interrupted while being written
+    pub(crate) fn partial(&self) -> Option<&PathBuf> {
+        self.partial.as_ref()
+    }
+
+    pub(crate) fn start_entry(&mut self, entry: PathBuf) {
+        self.partial = Some(entry.clone());
+        self.progress.entry = entry;
+    }
+
+    pub(crate) fn finish_entry(&mut self) {
+        self.partial = None;
+        self.progress.entries += 1;
+        (self.callback)(&self.progress);
+    }
+
+    fn add_bytes(&mut self, bytes: usize) {
+        self.progress.bytes += bytes as u64;
+
+        // Seeking inside zip archives may read some parts twice
+        if let Some(total) = self.progress.bytes_total {
+            self.progress.bytes = self.progress.bytes.min(total);
+        }
+
+        (self.callback)(&self.progress);
+    }
+}
+
+/// Reader over the archive file that reports every chunk read and fails once the extraction is cancelled
+pub(crate) struct TrackedReader<'t, 'a, R> {
+    inner: R,
+    tracker: &'t RefCell<Tracker<'a>>,
+}
+
+impl<'t, 'a, R> TrackedReader<'t, 'a, R> {
+    pub(crate) fn new(inner: R, tracker: &'t RefCell<Tracker<'a>>) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+impl<R: Read> Read for TrackedReader<'_, '_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.tracker.borrow().is_cancelled() {
+            return Err(io::Error::other("[extract_all] Extraction cancelled!"));
+        }
+
+        let read = self.inner.read(buf)?;
+        self.tracker.borrow_mut().add_bytes(read);
+
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for TrackedReader<'_, '_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
@@ -0,0 +1,770 @@
+//! # Readers utilities
+//! Read files stored inside archives directly into memory, without extracting anything on disk.
+//! Useful for parsing configuration or hex files that are shipped inside an archive.
+
+use anyhow::{Result, bail};
+use flate2::read::GzDecoder;
+use std::{
+    fs::File,
+    io::{self, Cursor, Read},
+    path::{Path, PathBuf},
+};
+use tar::Archive;
+use zip::ZipArchive;
+
+use crate::archives::{
+    ArchiveType, EntryKind, decompressed_stream, detect_archive_type, gz_file_name, is_zip_dir,
+};
+use crate::error::ArchiveError;
+use crate::limits::{Budget, ExtractLimits, LimitExceeded};
+
+/// Size of a single tar block, entries are padded to a multiple of it
+const TAR_BLOCK_SIZE: u64 = 512;
+
+/// Largest buffer allocated up front from the size declared in an entry header
+const MAX_PREALLOCATION: u64 = 64 * 1024;
+
+/// Entry Point:
+/// Read the selected file from the provided archive into a byte buffer
+/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
+pub fn archive_read_file(archive: PathBuf, filename: PathBuf) -> Result<Vec<u8>, ArchiveError> {
+    archive_read_file_with_limits(archive, filename, &ExtractLimits::default())
+}
+
+/// Entry Point:
+/// Read the selected file from the provided archive into a byte buffer
+/// enforcing the provided `ExtractLimits` while the contents are decompressed
+pub fn archive_read_file_with_limits(
+    archive: PathBuf,
+    filename: PathBuf,
+    limits: &ExtractLimits,
+) -> Result<Vec<u8>, ArchiveError> {
+    let mut contents: Vec<u8> = vec![];
+    archive_open_file_with_limits(archive, filename, limits)?.read_to_end(&mut contents)?;
+
+    Ok(contents)
+}
+
+/// Entry Point:
+/// Open the selected file from the provided archive and return a reader over its contents
+/// Tar and gz contents are streamed while they are read, zip entries are decompressed in memory
+/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
+pub fn archive_open_file(
+    archive: PathBuf,
+    filename: PathBuf,
+) -> Result<Box<dyn Read>, ArchiveError> {
+    archive_open_file_with_limits(archive, filename, &ExtractLimits::default())
+}
+
+/// Entry Point:
+/// Open the selected file from the provided archive and return a reader over its contents
+/// enforcing the provided `ExtractLimits`. Zip entries fail before they are fully decompressed in memory,
+/// streamed tar and gz contents fail with an `io::Error` wrapping `LimitExceeded` once a limit is hit
+pub fn archive_open_file_with_limits(
+    archive: PathBuf,
+    filename: PathBuf,
+    limits: &ExtractLimits,
+) -> Result<Box<dyn Read>, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_open_file_with_limits")?;
+
+    Ok(open_file(archive, archive_type, filename, limits)?)
+}
+
+/// Entry Point:
+/// Iterate over all regular files of the provided archive as `(path, reader)` pairs
+/// Each file is read into memory when the iterator reaches it
+/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
+pub fn archive_entries(archive: PathBuf) -> Result<ArchiveEntries, ArchiveError> {
+    archive_entries_with_limits(archive, &ExtractLimits::default())
+}
+
+/// Entry Point:
+/// Iterate over all regular files of the provided archive as `(path, reader)` pairs
+/// enforcing the provided `ExtractLimits` over all files read into memory
+/// The iteration stops with `ArchiveError::LimitExceeded` once a limit is hit
+pub fn archive_entries_with_limits(
+    archive: PathBuf,
+    limits: &ExtractLimits,
+) -> Result<ArchiveEntries, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_entries_with_limits")?;
+
+    Ok(ArchiveEntries::new(archive, archive_type, limits)?)
+}
+
+/// Open the selected file from the provided archive and return a reader over its contents
+fn open_file(
+    archive: PathBuf,
+    archive_type: ArchiveType,
+    filename: PathBuf,
+    limits: &ExtractLimits,
+) -> Result<Box<dyn Read>> {
+    let mut budget = Budget::new(limits, std::fs::metadata(&archive)?.len());
+
+    match archive_type {
+        ArchiveType::Zip => {
+            let file = File::open(archive)?;
+            let mut ar = ZipArchive::new(file)?;
+
+            for i in 0..ar.len() {
+                let name = ar.name_for_index(i).unwrap_or_default();
+                let path = Path::new(name);
+
+                // Match either the full path or only the file name like `archive_extract_file`
+                // Only the requested entry is opened, so other entries may be encrypted
+                if !is_zip_dir(name)
+                    && (path == filename || path.file_name() == Some(filename.as_os_str()))
+                {
+                    let mut in_file = ar.by_index(i)?;
+                    return Ok(Box::new(read_zip_file(&mut in_file, &mut budget)?));
+                }
+            }
+
+            bail!(ArchiveError::NotFound(
+                "[open_file][zip] Failed to find requested file!".to_string()
+            ));
+        }
+        ArchiveType::GzFile => {
+            let (name, reader) = open_gz_file(&archive)?;
+
+            if name == filename {
+                budget.start_entry(&name, None, None)?;
+
+                return Ok(Box::new(LimitedReader {
+                    inner: reader,
+                    budget,
+                    name,
+                }));
+            }
+
+            bail!(ArchiveError::NotFound(
+                "[open_file][gz] Failed to find requested file!".to_string()
+            ));
+        }
+        _ => {
+            let mut stream = TarStream::new(decompressed_stream(&archive, &archive_type)?);
+
+            while let Some(header) = stream.next_header(&mut budget)? {
+                if header.kind == EntryKind::File && header.path == filename {
+                    if let Some(contents) = header.contents {
+                        return Ok(Box::new(Cursor::new(contents)));
+                    }
+
+                    budget.start_entry(&header.path, Some(header.size), None)?;
+
+                    // The stream is positioned at the start of the contents of the entry
+                    return Ok(Box::new(LimitedReader {
+                        inner: ExactReader {
+                            inner: stream.inner,
+                            remaining: header.size,
+                        },
+                        budget,
+                        name: header.path,
+                    }));
+                }
+
+                stream.skip_data(header.size)?;
+            }
+
+            bail!(ArchiveError::NotFound(format!(
+                "[open_file][{}] Failed to find requested file!",
+                archive_type
+            )));
+        }
+    }
+}
+
+/// Open a plain .gz file and return the name of the compressed file together with a reader over its contents
+fn open_gz_file(archive: &Path) -> Result<(PathBuf, Box<dyn Read>)> {
+    let mut decoder = GzDecoder::new(File::open(archive)?);
+
+    // The header is parsed with the first read
+    let mut buffer: Vec<u8> = vec![];
+    (&mut decoder).take(1).read_to_end(&mut buffer)?;
+
+    let name = gz_file_name(archive, decoder.header());
+
+    Ok((name, Box::new(Cursor::new(buffer).chain(decoder))))
+}
+
+/// Decompress a zip entry in memory, failing as soon as one of the limits is exceeded
+fn read_zip_file(in_file: &mut zip::read::ZipFile, budget: &mut Budget) -> Result<Cursor<Vec<u8>>> {
+    let name = PathBuf::from(in_file.name());
+
+    // The declared sizes are checked up front and the actual ones while decompressing
+    budget.start_entry(&name, Some(in_file.size()), Some(in_file.compressed_size()))?;
+
+    let mut contents: Vec<u8> = buffer_for(in_file.size());
+    budget.copy(&name, in_file, &mut contents)?;
+
+    Ok(Cursor::new(contents))
+}
+
+/// Iterator over the regular files of an archive as `(path, reader)` pairs
+/// Directories, links and special files are not returned
+pub struct ArchiveEntries {
+    source: EntriesSource,
+    budget: Budget,
+}
+
+enum EntriesSource {
+    Tar(TarStream),
+    Zip {
+        ar: ZipArchive<File>,
+        index: usize,
+    },
+    GzFile(Option<(PathBuf, Box<dyn Read>)>),
+    /// Iteration stopped after an error, the position in the stream is unknown
+    Failed,
+}
+
+impl ArchiveEntries {
+    fn new(archive: PathBuf, archive_type: ArchiveType, limits: &ExtractLimits) -> Result<Self> {
+        let budget = Budget::new(limits, std::fs::metadata(&archive)?.len());
+
+        let source = match archive_type {
+            ArchiveType::Zip => EntriesSource::Zip {
+                ar: ZipArchive::new(File::open(archive)?)?,
+                index: 0,
+            },
+            ArchiveType::GzFile => EntriesSource::GzFile(Some(open_gz_file(&archive)?)),
+            _ => EntriesSource::Tar(TarStream::new(decompressed_stream(
+                &archive,
+                &archive_type,
+            )?)),
+        };
+
+        Ok(Self { source, budget })
+    }
+
+    fn next_entry(&mut self) -> Result<Option<(PathBuf, Cursor<Vec<u8>>)>> {
+        let budget = &mut self.budget;
+
+        match &mut self.source {
+            EntriesSource::Tar(stream) => {
+                while let Some(header) = stream.next_header(budget)? {
+                    if header.kind != EntryKind::File {
+                        stream.skip_data(header.size)?;
+                        continue;
+                    }
+
+                    let contents = match header.contents {
+                        Some(contents) => contents,
+                        None => {
+                            // The declared size bounds the buffer, so it is checked before reading
+                            budget.start_entry(&header.path, Some(header.size), None)?;
+                            let contents = stream.read_data(header.size)?;
+                            budget.add_bytes(&header.path, contents.len() as u64)?;
+                            contents
+                        }
+                    };
+
+                    return Ok(Some((header.path, Cursor::new(contents))));
+                }
+
+                Ok(None)
+            }
+            EntriesSource::Zip { ar, index } => {
+                while *index < ar.len() {
+                    let i = *index;
+                    *index += 1;
+
+                    // Directories are skipped without opening them
+                    if is_zip_dir(ar.name_for_index(i).unwrap_or_default()) {
+                        continue;
+                    }
+
+                    let mut in_file = ar.by_index(i)?;
+
+                    let contents = read_zip_file(&mut in_file, budget)?;
+
+                    return Ok(Some((PathBuf::from(in_file.name()), contents)));
+                }
+
+                Ok(None)
+            }
+            EntriesSource::GzFile(file) => match file.take() {
+                Some((name, mut reader)) => {
+                    budget.start_entry(&name, None, None)?;
+
+                    let mut contents: Vec<u8> = vec![];
+                    budget.copy(&name, &mut reader, &mut contents)?;
+
+                    Ok(Some((name, Cursor::new(contents))))
+                }
+                None => Ok(None),
+            },
+            EntriesSource::Failed => Ok(None),
+        }
+    }
+}
+
+impl Iterator for ArchiveEntries {
+    type Item = Result<(PathBuf, Cursor<Vec<u8>>), ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_entry() {
+            Ok(entry) => entry.map(Ok),
+            Err(e) => {
+                self.source = EntriesSource::Failed;
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
+
+/// Header of a tar entry as needed by the readers
+struct TarHeader {
+    path: PathBuf,
+    size: u64,
+    kind: EntryKind,
+    /// Contents that were already read while parsing the header (GNU sparse entries)
+    contents: Option<Vec<u8>>,
+}
+
+/// Tar stream that is parsed one entry at a time.
+/// A new `tar::Archive` is used for every header, so that the contents of an entry
+/// can be handed out as a reader that owns the underlying stream.
+struct TarStream {
+    inner: CountingReader,
+}
+
+impl TarStream {
+    fn new(stream: Box<dyn Read>) -> Self {
+        Self {
+            inner: CountingReader {
+                inner: stream,
+                position: 0,
+            },
+        }
+    }
+
+    /// Read the next header (including GNU long names and PAX extensions)
+    /// and leave the stream at the start of the contents of the entry.
+    /// GNU sparse entries are read completely since the `tar` crate has to fill in their holes,
+    /// their holes can expand far beyond the header size so they are read through the `budget`.
+    fn next_header(&mut self, budget: &mut Budget) -> Result<Option<TarHeader>> {
+        let mut ar = Archive::new(&mut self.inner);
+
+        let mut header = match ar.entries()?.next() {
+            Some(entry) => {
+                let mut entry = entry?;
+
+                let mut header = TarHeader {
+                    path: entry.path()?.to_path_buf(),
+                    size: entry.size(),
+                    kind: EntryKind::from(entry.header().entry_type()),
+                    contents: None,
+                };
+
+                if entry.header().entry_type().is_gnu_sparse() {
+                    budget.start_entry(&header.path, None, None)?;
+
+                    let mut contents: Vec<u8> = vec![];
+                    budget.copy(&header.path, &mut entry, &mut contents)?;
+                    header.contents = Some(contents);
+                }
+
+                header
+            }
+            None => return Ok(None),
+        };
+
+        if header.contents.is_some() {
+            header.size = 0;
+            self.skip_padding()?;
+        }
+
+        Ok(Some(header))
+    }
+
+    /// Read the contents of the current entry and move to the next header
+    fn read_data(&mut self, size: u64) -> Result<Vec<u8>> {
+        let mut contents: Vec<u8> = buffer_for(size);
+        (&mut self.inner).take(size).read_to_end(&mut contents)?;
+
+        if (contents.len() as u64) < size {
+            bail!(ArchiveError::Corrupt(
+                "[read_data] Unexpected end of tar archive!".to_string()
+            ));
+        }
+
+        self.skip_padding()?;
+
+        Ok(contents)
+    }
+
+    /// Skip the contents of the current entry and move to the next header
+    fn skip_data(&mut self, size: u64) -> Result<()> {
+        io::copy(&mut (&mut self.inner).take(size), &mut io::sink())?;
+        self.skip_padding()
+    }
+
+    /// Entries are padded with zeros up to the next tar block
+    fn skip_padding(&mut self) -> Result<()> {
+        let padding = (TAR_BLOCK_SIZE - self.inner.position % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+        io::copy(&mut (&mut self.inner).take(padding), &mut io::sink())?;
+
+        Ok(())
+    }
+}
+
+/// Reader that keeps track of how many bytes were consumed from the tar stream
+struct CountingReader {
+    inner: Box<dyn Read>,
+    position: u64,
+}
+
+impl Read for CountingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+
+        Ok(read)
+    }
+}
+
+/// Reader over the contents of a tar entry that fails if the stream ends before the size declared in its header
+struct ExactReader<R: Read> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: Read> Read for ExactReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let max = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..max])?;
+
+        if read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "[open_file] Unexpected end of tar archive!",
+            ));
+        }
+
+        self.remaining -= read as u64;
+
+        Ok(read)
+    }
+}
+
+/// Reader over streamed contents that counts every byte against the limits of the extraction
+/// Exceeding a limit fails the read with an `io::Error` wrapping `LimitExceeded`
+struct LimitedReader<R: Read> {
+    inner: R,
+    budget: Budget,
+    /// Entry (as named in the archive) being read
+    name: PathBuf,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if let Err(e) = self.budget.add_bytes(&self.name, read as u64) {
+            return Err(match e.downcast::<LimitExceeded>() {
+                Ok(exceeded) => io::Error::other(exceeded),
+                Err(e) => io::Error::other(e),
+            });
+        }
+
+        Ok(read)
+    }
+}
+
+/// Buffer for the contents of an entry. The declared size comes from an untrusted header,
+/// so only a small part of it is allocated up front and `read_to_end` grows the rest.
+fn buffer_for(size: u64) -> Vec<u8> {
+    Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::readers::*;
+    use std::io::Write;
+    use utils_box_pathfinder::paths::IncludePathsBuilder;
+
+    #[test]
+    fn archive_read_file_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        let zip = paths.search_glob("test_archives.zip");
+        let lorem = archive_read_file(zip[0].clone(), "lorem.txt".into()).unwrap();
+        assert_eq!(3821, lorem.len());
+        assert!(lorem.starts_with(b"Lorem ipsum"));
+
+        let targz = paths.search_glob("test_archives.tar.gz");
+        let text = archive_read_file(targz[0].clone(), "test_archives/test.txt".into()).unwrap();
+        assert_eq!(37, text.len());
+
+        let tar = paths.search_glob("test_archives.tar");
+        let mut reader = archive_open_file(tar[0].clone(), "sad_cloud.jpg".into()).unwrap();
+        let mut cloud: Vec<u8> = vec![];
+        reader.read_to_end(&mut cloud).unwrap();
+        assert_eq!(32920, cloud.len());
+
+        assert!(matches!(
+            archive_read_file(tar[0].clone(), "missing.txt".into()),
+            Err(ArchiveError::NotFound(_))
+        ));
+        assert!(matches!(
+            archive_read_file(zip[0].clone(), "missing.txt".into()),
+            Err(ArchiveError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn archive_entries_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        for name in [
+            "test_archives.tar",
+            "test_archives.tar.gz",
+            "test_archives.zip",
+        ] {
+            let archive = paths.search_glob(name)[0].clone();
+
+            let listed: Vec<ArchiveEntry> = archive_list(archive.clone())
+                .unwrap()
+                .into_iter()
+                .filter(|entry| entry.kind == EntryKind::File)
+                .collect();
+
+            let entries: Vec<(PathBuf, Cursor<Vec<u8>>)> = archive_entries(archive)
+                .unwrap()
+                .collect::<Result<_, ArchiveError>>()
+                .unwrap();
+
+            assert_eq!(listed.len(), entries.len());
+            for (entry, (path, reader)) in listed.iter().zip(entries.iter()) {
+                assert_eq!(entry.path, *path);
+                assert_eq!(entry.size, reader.get_ref().len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn archive_read_file_encrypted_zip_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let archive = dir.path().join("package.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file(
+            "secret.ini",
+            zip::write::SimpleFileOptions::default()
+                .with_aes_encryption(zip::AesMode::Aes256, "s3cr3t"),
+        )
+        .unwrap();
+        zip.write_all(b"key=1").unwrap();
+        zip.add_directory("docs/", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file("docs/readme.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"read me").unwrap();
+        zip.finish().unwrap();
+
+        // The encrypted entry in front of the requested one is not opened
+        assert_eq!(
+            b"read me".to_vec(),
+            archive_read_file(archive.clone(), "readme.txt".into()).unwrap()
+        );
+        assert!(matches!(
+            archive_read_file(archive, "secret.ini".into()),
+            Err(ArchiveError::Password(_))
+        ));
+    }
+
+    #[test]
+    fn archive_entries_long_names_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("long_names.tar");
+
+        // Paths over 100 characters are stored with GNU long name entries
+        let long_name = format!("{}/config.ini", "nested".repeat(20));
+
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        for (name, contents) in [
+            ("first.hex", &b":00000001FF"[..]),
+            (long_name.as_str(), &b"[main]\nvalue=1\n"[..]),
+            ("last.txt", &b"done"[..]),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents).unwrap();
+        }
+        builder.into_inner().unwrap().flush().unwrap();
+
+        let entries: Vec<(PathBuf, Cursor<Vec<u8>>)> = archive_entries(archive.clone())
+            .unwrap()
+            .collect::<Result<_, ArchiveError>>()
+            .unwrap();
+
+        assert_eq!(3, entries.len());
+        assert_eq!(PathBuf::from(&long_name), entries[1].0);
+        assert_eq!(b"[main]\nvalue=1\n", entries[1].1.get_ref().as_slice());
+        assert_eq!(b"done", entries[2].1.get_ref().as_slice());
+
+        assert_eq!(
+            b"done".to_vec(),
+            archive_read_file(archive.clone(), "last.txt".into()).unwrap()
+        );
+        assert_eq!(
+            b"[main]\nvalue=1\n".to_vec(),
+            archive_read_file(archive, long_name.into()).unwrap()
+        );
+    }
+
+    #[test]
+    fn archive_entries_huge_size_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("huge.tar");
+
+        // Header declaring an exabyte of contents that are not there
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_path("huge.bin").unwrap();
+        header.set_size(1 << 60);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"tiny"[..]).unwrap();
+        builder.into_inner().unwrap().flush().unwrap();
+
+        let entries: Vec<_> = archive_entries(archive).unwrap().collect();
+        assert_eq!(1, entries.len());
+        assert!(matches!(entries[0], Err(ArchiveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn archive_read_file_limits_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let source = dir.path().join("source");
+        std::fs::create_dir(&source).unwrap();
+        std::fs::write(source.join("config.ini"), "a=1").unwrap();
+        std::fs::write(source.join("zeros.bin"), vec![0u8; 1024 * 1024]).unwrap();
+
+        let limits = ExtractLimits {
+            max_entry_bytes: Some(64 * 1024),
+            ..Default::default()
+        };
+
+        for (archive_type, name) in [
+            (ArchiveType::Tar, "bundle.tar"),
+            (ArchiveType::Gz, "bundle.tar.gz"),
+            (ArchiveType::Zip, "bundle.zip"),
+        ] {
+            let archive = dir.path().join(name);
+            archive_create_all(source.clone(), archive_type, archive.clone()).unwrap();
+
+            assert_eq!(
+                b"a=1".to_vec(),
+                archive_read_file_with_limits(archive.clone(), "config.ini".into(), &limits)
+                    .unwrap()
+            );
+
+            match archive_read_file_with_limits(archive.clone(), "zeros.bin".into(), &limits) {
+                Err(ArchiveError::LimitExceeded(exceeded)) => {
+                    assert_eq!(crate::limits::Limit::EntryBytes, exceeded.limit);
+                    assert_eq!(PathBuf::from("zeros.bin"), exceeded.entry);
+                }
+                other => panic!("Unexpected result: {other:?}"),
+            }
+
+            let entries: Vec<_> = archive_entries_with_limits(archive, &limits)
+                .unwrap()
+                .collect();
+            assert!(
+                entries
+                    .iter()
+                    .any(|entry| matches!(entry, Err(ArchiveError::LimitExceeded(_))))
+            );
+        }
+
+        // Plain .gz files are streamed without a declared size
+        let archive = dir.path().join("zeros.bin.gz");
+        archive_create_files(
+            vec![source.join("zeros.bin")],
+            source.clone(),
+            ArchiveType::GzFile,
+            archive.clone(),
+        )
+        .unwrap();
+
+        let mut reader =
+            archive_open_file_with_limits(archive.clone(), "zeros.bin".into(), &limits).unwrap();
+        let error = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert!(matches!(
+            ArchiveError::from(error),
+            ArchiveError::LimitExceeded(_)
+        ));
+
+        let entries: Vec<_> = archive_entries_with_limits(archive, &limits)
+            .unwrap()
+            .collect();
+        assert!(matches!(entries[0], Err(ArchiveError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn archive_open_file_truncated_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("truncated.tar");
+
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_path("app.hex").unwrap();
+        header.set_size(2048);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &[b'F'; 2048][..]).unwrap();
+        builder.into_inner().unwrap().flush().unwrap();
+
+        // Cut in the middle of the contents of the entry
+        let bytes = std::fs::read(&archive).unwrap();
+        std::fs::write(&archive, &bytes[..1024]).unwrap();
+
+        let mut reader = archive_open_file(archive.clone(), "app.hex".into()).unwrap();
+        let mut contents: Vec<u8> = vec![];
+        let error = reader.read_to_end(&mut contents).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+
+        assert!(matches!(
+            archive_read_file(archive, "app.hex".into()),
+            Err(ArchiveError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn archive_read_gz_file_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("version.txt.gz");
+
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"1.2.3").unwrap();
+        encoder.finish().unwrap();
+
+        assert_eq!(
+            b"1.2.3".to_vec(),
+            archive_read_file(archive.clone(), "version.txt".into()).unwrap()
+        );
+
+        let entries: Vec<_> = archive_entries(archive).unwrap().collect();
+        assert_eq!(1, entries.len());
+    }
+}
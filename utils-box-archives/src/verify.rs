@@ -0,0 +1,542 @@
+//! # Verification utilities
+//! Check the integrity of archives and of extracted files before using them.
+//! Useful for confirming that a bundle is intact before flashing it, using the checksums
+//! of the archive formats and optional SHA-256 manifests (`SHA256SUMS`).
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use tar::Archive;
+use zip::ZipArchive;
+
+use utils_box_logger::{log_info, log_warn};
+
+use crate::archives::{
+    ArchiveType, EntryKind, archive_list, decompressed_stream, detect_archive_type, gz_file_name,
+};
+use crate::error::ArchiveError;
+use crate::policy::normalize;
+use crate::readers::archive_read_file;
+
+/// Name of the manifest entry holding the SHA-256 digests of the other entries
+pub static SHA256SUMS: &str = "SHA256SUMS";
+
+/// SHA-256 digests to check the entries against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Manifest {
+    /// Only check the integrity of the archive itself
+    #[default]
+    None,
+    /// Hex encoded SHA-256 digests by path inside the archive
+    Digests(BTreeMap<PathBuf, String>),
+    /// Read the digests from the `SHA256SUMS` entry of the archive (or of the extracted directory)
+    Sha256Sums,
+}
+
+/// Outcome of the verification of a single entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckStatus {
+    Passed,
+    /// The entry could not be read back (CRC32 mismatch, truncated or corrupted data)
+    Corrupted(String),
+    /// The contents do not match the digest of the manifest
+    DigestMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// The entry is listed in the manifest but is not available
+    Missing,
+}
+
+/// Verification result of a single entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryCheck {
+    /// Path of the entry as stored in the archive (or relative to the extracted directory)
+    pub path: PathBuf,
+    pub status: CheckStatus,
+}
+
+/// Per-entry verification results
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub entries: Vec<EntryCheck>,
+    /// Errors of the archive container itself (tar header checksums, gzip trailers)
+    /// Entries after a corrupted tar header cannot be reached and are not reported
+    pub archive_errors: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether every entry passed and the archive itself is intact
+    pub fn is_ok(&self) -> bool {
+        self.archive_errors.is_empty()
+            && self
+                .entries
+                .iter()
+                .all(|entry| entry.status == CheckStatus::Passed)
+    }
+
+    /// Entries that did not pass the verification
+    pub fn failed(&self) -> Vec<&EntryCheck> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.status != CheckStatus::Passed)
+            .collect()
+    }
+}
+
+/// Entry Point:
+/// Read every entry of the provided archive checking the zip CRC32s, the gzip trailers and the tar header checksums
+/// and optionally compare the SHA-256 digest of each file with the provided manifest
+/// It detects if it is a compressed archive using the magic numbers from: <https://www.garykessler.net/library/file_sigs.html>
+pub fn archive_verify(archive: PathBuf, manifest: &Manifest) -> Result<VerifyReport, ArchiveError> {
+    let archive_type = detect_archive_type(&archive, "archive_verify")?;
+
+    let digests = match manifest {
+        Manifest::None => BTreeMap::new(),
+        Manifest::Digests(digests) => normalize_digests(digests),
+        Manifest::Sha256Sums => {
+            let sums = archive_list(archive.clone())?.into_iter().find(|entry| {
+                entry.kind == EntryKind::File && entry.path.file_name() == Some(SHA256SUMS.as_ref())
+            });
+
+            let sums = match sums {
+                Some(sums) => sums.path,
+                None => {
+                    return Err(ArchiveError::NotFound(format!(
+                        "[archive_verify] No [{}] entry found in [{}]!",
+                        SHA256SUMS,
+                        archive.display()
+                    )));
+                }
+            };
+
+            let contents = archive_read_file(archive.clone(), sums.clone())?;
+            let digests = sha256sums_parse(&String::from_utf8_lossy(&contents))?;
+
+            // Paths are relative to the directory of the manifest
+            let base = normalize(sums.parent().unwrap_or(Path::new("")));
+            digests
+                .into_iter()
+                .map(|(path, digest)| (normalize(&base.join(path)), digest))
+                .collect()
+        }
+    };
+
+    let report = verify(archive, archive_type, digests)?;
+
+    log_info!(
+        "[archive_verify] Verified [{}] entries, [{}] failed, [{}] archive errors",
+        report.entries.len(),
+        report.failed().len(),
+        report.archive_errors.len()
+    );
+
+    Ok(report)
+}
+
+/// Entry Point:
+/// Compare the files of an extracted directory with the SHA-256 digests of the manifest
+/// With `Manifest::Sha256Sums` the digests are read from the `SHA256SUMS` file inside the directory
+pub fn verify_extracted(
+    destination: PathBuf,
+    manifest: &Manifest,
+) -> Result<VerifyReport, ArchiveError> {
+    let digests = match manifest {
+        Manifest::None => BTreeMap::new(),
+        Manifest::Digests(digests) => normalize_digests(digests),
+        Manifest::Sha256Sums => match std::fs::read_to_string(destination.join(SHA256SUMS)) {
+            Ok(contents) => sha256sums_parse(&contents)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(ArchiveError::NotFound(format!(
+                    "[verify_extracted] No [{}] file found in [{}]!",
+                    SHA256SUMS,
+                    destination.display()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+
+    let mut report = VerifyReport::new();
+
+    for (path, expected) in digests {
+        let status = match File::open(destination.join(&path)) {
+            Ok(mut file) => match sha256_hex(&mut file) {
+                Ok(actual) => digest_status(&expected, actual),
+                Err(e) => CheckStatus::Corrupted(e.to_string()),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => CheckStatus::Missing,
+            Err(e) => CheckStatus::Corrupted(e.to_string()),
+        };
+
+        report.entries.push(EntryCheck { path, status });
+    }
+
+    log_info!(
+        "[verify_extracted] Verified [{}] files, [{}] failed",
+        report.entries.len(),
+        report.failed().len()
+    );
+
+    Ok(report)
+}
+
+/// Parse the contents of a `SHA256SUMS` file as produced by `sha256sum`
+/// Each line holds the hex digest and the path, separated by two spaces (text mode) or ` *` (binary mode)
+pub fn sha256sums_parse(contents: &str) -> Result<BTreeMap<PathBuf, String>, ArchiveError> {
+    let mut digests: BTreeMap<PathBuf, String> = BTreeMap::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (digest, path) = match line.split_once(' ') {
+            Some((digest, path)) => (digest, path.strip_prefix([' ', '*']).unwrap_or(path)),
+            None => (line, ""),
+        };
+
+        if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) || path.is_empty() {
+            return Err(ArchiveError::Corrupt(format!(
+                "[sha256sums_parse] Invalid line [{}]: [{}]!",
+                number + 1,
+                line
+            )));
+        }
+
+        digests.insert(normalize(Path::new(path)), digest.to_lowercase());
+    }
+
+    Ok(digests)
+}
+
+/// Read every entry of the archive and check it against the digests
+fn verify(
+    archive: PathBuf,
+    archive_type: ArchiveType,
+    mut digests: BTreeMap<PathBuf, String>,
+) -> Result<VerifyReport> {
+    let mut report = VerifyReport::new();
+
+    match archive_type {
+        ArchiveType::Zip => {
+            let file = File::open(archive)?;
+            let mut ar = ZipArchive::new(file)?;
+
+            for i in 0..ar.len() {
+                let path = match ar.by_index_raw(i) {
+                    Ok(in_file) => PathBuf::from(in_file.name()),
+                    Err(e) => {
+                        report.archive_errors.push(e.to_string());
+                        continue;
+                    }
+                };
+
+                // The CRC32 is checked by the zip reader once the whole entry is read
+                let status = match ar.by_index(i) {
+                    Ok(mut in_file) => check_entry(&mut in_file, &path, &mut digests),
+                    Err(e) => CheckStatus::Corrupted(e.to_string()),
+                };
+
+                report.entries.push(EntryCheck { path, status });
+            }
+        }
+        ArchiveType::GzFile => {
+            // The CRC32 and size of the gzip trailer are checked at the end of the stream
+            let mut decoder = flate2::read::GzDecoder::new(File::open(&archive)?);
+
+            let mut buffer: Vec<u8> = vec![];
+            let status = match (&mut decoder).take(1).read_to_end(&mut buffer) {
+                Ok(_) => None,
+                Err(e) => Some(CheckStatus::Corrupted(e.to_string())),
+            };
+
+            let path = gz_file_name(&archive, decoder.header());
+            let status = status.unwrap_or_else(|| {
+                check_entry(
+                    &mut io::Cursor::new(buffer).chain(decoder),
+                    &path,
+                    &mut digests,
+                )
+            });
+
+            report.entries.push(EntryCheck { path, status });
+        }
+        _ => {
+            let mut ar = Archive::new(decompressed_stream(&archive, &archive_type)?);
+
+            // Header checksums are validated by the tar reader
+            for archived_file in ar.entries()? {
+                let mut ar_file = match archived_file {
+                    Ok(ar_file) => ar_file,
+                    Err(e) => {
+                        report.archive_errors.push(e.to_string());
+                        break;
+                    }
+                };
+
+                let path = match ar_file.path() {
+                    Ok(path) => path.to_path_buf(),
+                    Err(e) => {
+                        report.archive_errors.push(e.to_string());
+                        break;
+                    }
+                };
+
+                let status = check_entry(&mut ar_file, &path, &mut digests);
+                report.entries.push(EntryCheck { path, status });
+            }
+
+            // Read the rest of the stream so that compressed formats validate their trailers
+            if report.archive_errors.is_empty()
+                && let Err(e) = io::copy(&mut ar.into_inner(), &mut io::sink())
+            {
+                report.archive_errors.push(e.to_string());
+            }
+        }
+    }
+
+    // Whatever is left in the manifest was not found in the archive
+    for path in digests.into_keys() {
+        log_warn!(
+            "[archive_verify] Entry [{}] of the manifest is missing",
+            path.display()
+        );
+
+        report.entries.push(EntryCheck {
+            path,
+            status: CheckStatus::Missing,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Read the whole entry and compare its digest with the manifest (if listed)
+fn check_entry(
+    reader: &mut impl Read,
+    path: &Path,
+    digests: &mut BTreeMap<PathBuf, String>,
+) -> CheckStatus {
+    let actual = match sha256_hex(reader) {
+        Ok(actual) => actual,
+        Err(e) => return CheckStatus::Corrupted(e.to_string()),
+    };
+
+    match digests.remove(&normalize(path)) {
+        Some(expected) => digest_status(&expected, actual),
+        None => CheckStatus::Passed,
+    }
+}
+
+fn digest_status(expected: &str, actual: String) -> CheckStatus {
+    if expected.eq_ignore_ascii_case(&actual) {
+        CheckStatus::Passed
+    } else {
+        CheckStatus::DigestMismatch {
+            expected: expected.to_lowercase(),
+            actual,
+        }
+    }
+}
+
+/// Hex encoded SHA-256 digest of everything left in the reader
+pub(crate) fn sha256_hex(reader: &mut impl Read) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn normalize_digests(digests: &BTreeMap<PathBuf, String>) -> BTreeMap<PathBuf, String> {
+    digests
+        .iter()
+        .map(|(path, digest)| (normalize(path), digest.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archives::*;
+    use crate::verify::*;
+    use std::io::Write;
+    use utils_box_pathfinder::paths::IncludePathsBuilder;
+
+    #[test]
+    fn archive_verify_test() {
+        let paths = IncludePathsBuilder::new()
+            .include_exe_dir()
+            .include_unknown("utils-box-archives/")
+            .build();
+
+        for name in [
+            "test_archives.tar",
+            "test_archives.tar.gz",
+            "test_archives.zip",
+        ] {
+            let report =
+                archive_verify(paths.search_glob(name)[0].clone(), &Manifest::None).unwrap();
+
+            assert!(report.is_ok());
+            assert!(!report.entries.is_empty());
+        }
+    }
+
+    #[test]
+    fn archive_verify_corrupted_test() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Stored zip entries are left as-is, so flipping a byte only breaks the CRC32
+        let archive = dir.path().join("bundle.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        zip.start_file("good.txt", options).unwrap();
+        zip.write_all(b"all good here").unwrap();
+        zip.start_file("bad.txt", options).unwrap();
+        zip.write_all(b"about to be corrupted").unwrap();
+        zip.finish().unwrap();
+
+        let mut bytes = std::fs::read(&archive).unwrap();
+        let offset = bytes
+            .windows(5)
+            .position(|window| window == b"about")
+            .unwrap();
+        bytes[offset] = b'A';
+        std::fs::write(&archive, bytes).unwrap();
+
+        let report = archive_verify(archive, &Manifest::None).unwrap();
+
+        assert!(!report.is_ok());
+        assert_eq!(CheckStatus::Passed, report.entries[0].status);
+        assert!(matches!(
+            report.entries[1].status,
+            CheckStatus::Corrupted(_)
+        ));
+        assert_eq!(PathBuf::from("bad.txt"), report.failed()[0].path);
+
+        // A broken gzip trailer is reported for the archive itself
+        std::fs::write(dir.path().join("data.txt"), "some data").unwrap();
+        let archive = dir.path().join("bundle.tar.gz");
+        archive_create_files(
+            vec![dir.path().join("data.txt")],
+            dir.path().to_path_buf(),
+            ArchiveType::Gz,
+            archive.clone(),
+        )
+        .unwrap();
+
+        let mut bytes = std::fs::read(&archive).unwrap();
+        let crc = bytes.len() - 8;
+        bytes[crc] ^= 0xFF;
+        std::fs::write(&archive, bytes).unwrap();
+
+        let report = archive_verify(archive, &Manifest::None).unwrap();
+
+        assert!(!report.is_ok());
+        assert_eq!(1, report.archive_errors.len());
+    }
+
+    #[test]
+    fn archive_verify_sha256sums_test() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("fw")).unwrap();
+        std::fs::write(source.path().join("fw/image.bin"), "image").unwrap();
+        std::fs::write(source.path().join("fw/boot.bin"), "boot").unwrap();
+
+        let mut image = File::open(source.path().join("fw/image.bin")).unwrap();
+        let digest = sha256_hex(&mut image).unwrap();
+
+        std::fs::write(
+            source.path().join("SHA256SUMS"),
+            format!(
+                "{digest} *fw/image.bin\n{}  ./fw/boot.bin\n{digest}  fw/missing.bin\n",
+                "0".repeat(64)
+            ),
+        )
+        .unwrap();
+
+        let output = tempfile::tempdir().unwrap();
+        let archive = output.path().join("bundle.zip");
+        archive_create_all(
+            source.path().to_path_buf(),
+            ArchiveType::Zip,
+            archive.clone(),
+        )
+        .unwrap();
+
+        let report = archive_verify(archive, &Manifest::Sha256Sums).unwrap();
+
+        let status = |report: &VerifyReport, path: &str| {
+            report
+                .entries
+                .iter()
+                .find(|entry| normalize(&entry.path) == Path::new(path))
+                .map(|entry| entry.status.clone())
+                .unwrap()
+        };
+
+        assert_eq!(CheckStatus::Passed, status(&report, "fw/image.bin"));
+        assert!(matches!(
+            status(&report, "fw/boot.bin"),
+            CheckStatus::DigestMismatch { .. }
+        ));
+        assert_eq!(CheckStatus::Missing, status(&report, "fw/missing.bin"));
+        assert_eq!(2, report.failed().len());
+
+        // The same manifest against the files on disk
+        let report = verify_extracted(source.path().to_path_buf(), &Manifest::Sha256Sums).unwrap();
+
+        assert_eq!(3, report.entries.len());
+        assert_eq!(CheckStatus::Passed, status(&report, "fw/image.bin"));
+        assert_eq!(CheckStatus::Missing, status(&report, "fw/missing.bin"));
+
+        // No manifest to read the digests from
+        std::fs::remove_file(source.path().join("SHA256SUMS")).unwrap();
+        assert!(matches!(
+            verify_extracted(source.path().to_path_buf(), &Manifest::Sha256Sums),
+            Err(ArchiveError::NotFound(_))
+        ));
+
+        let archive = output.path().join("unsigned.tar");
+        archive_create_all(
+            source.path().to_path_buf(),
+            ArchiveType::Tar,
+            archive.clone(),
+        )
+        .unwrap();
+        assert!(matches!(
+            archive_verify(archive, &Manifest::Sha256Sums),
+            Err(ArchiveError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn sha256sums_parse_test() {
+        let digest = "a".repeat(64);
+
+        let digests =
+            sha256sums_parse(&format!("{digest}  ./a.txt\r\n\n{digest} *b/c.bin\n")).unwrap();
+
+        assert_eq!(2, digests.len());
+        assert_eq!(Some(&digest), digests.get(Path::new("a.txt")));
+        assert_eq!(Some(&digest), digests.get(Path::new("b/c.bin")));
+
+        assert!(matches!(
+            sha256sums_parse("not a digest  a.txt"),
+            Err(ArchiveError::Corrupt(_))
+        ));
+        assert!(sha256sums_parse(&digest).is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! # Summary
+//! Derive macros of the utils-box-bits crate.
+//! Do not depend on this crate directly, use the re-exports of `utils_box_bits::bitfield` instead.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Data, DeriveInput, Expr, ExprLit, ExprRange, Fields, Ident, Lit, LitStr, Path, RangeLimits,
+    Token, parse::ParseStream, parse_macro_input, spanned::Spanned,
+};
+
+/// Derive `utils_box_bits::bitfield::Bitfield` and a field-wise `Debug` for a register struct
+///
+/// Struct attribute (all optional): `#[bitfield(bytes = 4, endian = "big", check_reserved = true, crate = "utils_box_bits")]`
+/// Field attribute: `#[bits(3)]` for a single bit or `#[bits(4..=15)]` / `#[bits(4..16)]` for a range (bit 0 is the LSB)
+/// Fields covering whole bytes can override the endianness of the register: `#[bits(0..=15, endian = "little")]`
+#[proc_macro_derive(Bitfield, attributes(bitfield, bits))]
+pub fn derive_bitfield(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+struct Options {
+    bytes: Option<usize>,
+    big_endian: bool,
+    check_reserved: bool,
+    krate: Path,
+}
+
+struct Field {
+    ident: Ident,
+    ty: syn::Type,
+    lsb: u32,
+    width: u32,
+    /// The field bytes are in the opposite order of the register
+    swap: bool,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "[Bitfield] Generic structs are not supported!",
+        ));
+    }
+
+    let options = parse_options(&input)?;
+
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(named) => &named.named,
+            _ => {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "[Bitfield] Only structs with named fields are supported!",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                name.span(),
+                "[Bitfield] Only structs are supported!",
+            ));
+        }
+    };
+
+    let mut fields: Vec<Field> = vec![];
+    let mut used: u128 = 0;
+    let mut max_bit: u32 = 0;
+
+    for field in named {
+        let ident = field.ident.clone().expect("named field");
+
+        let attr = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("bits"))
+            .ok_or_else(|| {
+                syn::Error::new(
+                    ident.span(),
+                    format!("[Bitfield] Field [{ident}] is missing its #[bits(..)] range!"),
+                )
+            })?;
+
+        let (range, endian) = attr.parse_args_with(|input: ParseStream| {
+            let range: Expr = input.parse()?;
+            let mut endian: Option<bool> = None;
+
+            if input.parse::<Option<Token![,]>>()?.is_some() {
+                let key: Ident = input.parse()?;
+                if key != "endian" {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "[Bitfield] Unknown bits option!",
+                    ));
+                }
+                input.parse::<Token![=]>()?;
+                endian = Some(parse_endian(&input.parse::<LitStr>()?)?);
+            }
+
+            Ok((range, endian))
+        })?;
+
+        let (lsb, msb) = parse_bits(&range)?;
+
+        if msb < lsb || msb > 127 {
+            return Err(syn::Error::new(
+                attr.span(),
+                format!("[Bitfield] Invalid bit range [{lsb}..={msb}] of field [{ident}]!"),
+            ));
+        }
+
+        let width = msb - lsb + 1;
+        let mask = field_mask(lsb, width);
+
+        if endian.is_some() && (lsb % 8 != 0 || width % 8 != 0) {
+            return Err(syn::Error::new(
+                attr.span(),
+                format!("[Bitfield] Field [{ident}] must cover whole bytes to set its endianness!"),
+            ));
+        }
+
+        if used & mask != 0 {
+            return Err(syn::Error::new(
+                attr.span(),
+                format!("[Bitfield] Field [{ident}] overlaps with a previous field!"),
+            ));
+        }
+
+        used |= mask;
+        max_bit = max_bit.max(msb);
+
+        fields.push(Field {
+            ident,
+            ty: field.ty.clone(),
+            lsb,
+            width,
+            swap: endian.is_some_and(|big_endian| big_endian != options.big_endian),
+        });
+    }
+
+    let bytes = match options.bytes {
+        Some(bytes) => bytes,
+        None => (max_bit as usize) / 8 + 1,
+    };
+
+    if bytes == 0 || bytes > 16 {
+        return Err(syn::Error::new(
+            name.span(),
+            "[Bitfield] Registers must be 1 to 16 bytes long!",
+        ));
+    }
+
+    if max_bit as usize >= bytes * 8 {
+        return Err(syn::Error::new(
+            name.span(),
+            format!("[Bitfield] Bit [{max_bit}] does not fit in [{bytes}] bytes!"),
+        ));
+    }
+
+    let reserved = !used & field_mask(0, bytes as u32 * 8);
+
+    let krate = &options.krate;
+    let private = quote!(#krate::bitfield::__private);
+    let endian = match options.big_endian {
+        true => quote!(#krate::bitfield::Endian::Big),
+        false => quote!(#krate::bitfield::Endian::Little),
+    };
+    let check_reserved = options.check_reserved;
+
+    let width_checks = fields.iter().map(
+        |Field {
+             ident, ty, width, ..
+         }| {
+            let message = format!("[Bitfield] Field [{name}::{ident}] is wider than its type!");
+            quote! {
+                assert!(#width <= <#ty as #krate::bitfield::BitfieldValue>::BITS, #message);
+            }
+        },
+    );
+
+    let packs = fields.iter().map(
+        |Field {
+             ident,
+             lsb,
+             width,
+             swap,
+             ..
+         }| {
+            let field = ident.to_string();
+            let value =
+                quote!(#private::field_to_raw(Self::NAME, #field, self.#ident, #lsb, #width)?);
+            match swap {
+                true => quote! { raw |= #private::swap_bytes(#value, #lsb, #width); },
+                false => quote! { raw |= #value; },
+            }
+        },
+    );
+
+    let unpacks = fields.iter().map(
+        |Field {
+             ident,
+             lsb,
+             width,
+             swap,
+             ..
+         }| {
+            let raw = match swap {
+                true => quote!(#private::swap_bytes(raw, #lsb, #width)),
+                false => quote!(raw),
+            };
+            quote! {
+                #ident: #private::field_from_raw(#raw, #lsb, #width),
+            }
+        },
+    );
+
+    let debugs = fields.iter().map(
+        |Field {
+             ident, lsb, width, ..
+         }| {
+            let label = match width {
+                1 => format!("{ident}[{lsb}]"),
+                _ => format!("{ident}[{}:{lsb}]", lsb + width - 1),
+            };
+            quote! {
+                .field(#label, &self.#ident)
+            }
+        },
+    );
+
+    Ok(quote! {
+        const _: () = {
+            #(#width_checks)*
+        };
+
+        impl #krate::bitfield::Bitfield for #name {
+            const NAME: &'static str = stringify!(#name);
+            const BYTES: usize = #bytes;
+            const ENDIAN: #krate::bitfield::Endian = #endian;
+            const RESERVED_MASK: u128 = #reserved;
+            const CHECK_RESERVED: bool = #check_reserved;
+
+            fn to_raw(&self) -> #private::Result<u128> {
+                let mut raw: u128 = 0;
+                #(#packs)*
+                Ok(raw)
+            }
+
+            fn from_raw_unchecked(raw: u128) -> Self {
+                Self {
+                    #(#unpacks)*
+                }
+            }
+        }
+
+        impl ::core::fmt::Debug for #name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.debug_struct(stringify!(#name))
+                    #(#debugs)*
+                    .finish()
+            }
+        }
+    })
+}
+
+fn parse_options(input: &DeriveInput) -> syn::Result<Options> {
+    let mut options = Options {
+        bytes: None,
+        big_endian: true,
+        check_reserved: true,
+        krate: syn::parse_quote!(::utils_box_bits),
+    };
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("bitfield")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("bytes") {
+                let bytes: syn::LitInt = meta.value()?.parse()?;
+                options.bytes = Some(bytes.base10_parse()?);
+            } else if meta.path.is_ident("endian") {
+                options.big_endian = parse_endian(&meta.value()?.parse()?)?;
+            } else if meta.path.is_ident("check_reserved") {
+                let check: syn::LitBool = meta.value()?.parse()?;
+                options.check_reserved = check.value;
+            } else if meta.path.is_ident("crate") {
+                let krate: LitStr = meta.value()?.parse()?;
+                options.krate = krate.parse()?;
+            } else {
+                return Err(meta.error("[Bitfield] Unknown bitfield option!"));
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(options)
+}
+
+/// Parse `"big"` or `"little"` into whether the bytes are big endian
+fn parse_endian(endian: &LitStr) -> syn::Result<bool> {
+    match endian.value().as_str() {
+        "big" => Ok(true),
+        "little" => Ok(false),
+        _ => Err(syn::Error::new(
+            endian.span(),
+            "[Bitfield] Expected \"big\" or \"little\"!",
+        )),
+    }
+}
+
+/// Parse `3`, `4..=15` or `4..16` into the (lsb, msb) pair
+fn parse_bits(expr: &Expr) -> syn::Result<(u32, u32)> {
+    match expr {
+        Expr::Lit(_) => {
+            let bit = parse_bit(expr)?;
+            Ok((bit, bit))
+        }
+        Expr::Range(ExprRange {
+            start: Some(start),
+            end: Some(end),
+            limits,
+            ..
+        }) => {
+            let start = parse_bit(start)?;
+            let end = parse_bit(end)?;
+
+            match limits {
+                RangeLimits::Closed(_) => Ok((start, end)),
+                RangeLimits::HalfOpen(_) if end > start => Ok((start, end - 1)),
+                RangeLimits::HalfOpen(_) => {
+                    Err(syn::Error::new(expr.span(), "[Bitfield] Empty bit range!"))
+                }
+            }
+        }
+        _ => Err(syn::Error::new(
+            expr.span(),
+            "[Bitfield] Expected a bit (e.g. 3) or a bit range (e.g. 4..=15)!",
+        )),
+    }
+}
+
+fn parse_bit(expr: &Expr) -> syn::Result<u32> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(bit), ..
+        }) => bit.base10_parse(),
+        _ => Err(syn::Error::new(
+            expr.span(),
+            "[Bitfield] Expected an integer bit index!",
+        )),
+    }
+}
+
+fn field_mask(lsb: u32, width: u32) -> u128 {
+    match width {
+        128 => u128::MAX,
+        _ => ((1u128 << width) - 1) << lsb,
+    }
+}
@@ -0,0 +1,530 @@
+//! # Bit error rate utilities
+//! Compare received bit streams against the transmitted pattern: alignment search, BER, error positions
+//! and burst statistics, plus the standard PRBS test sequences with a self-synchronising checker.
+//! Streams use the one-bit-per-byte representation of `bits_to_vec` (`Vec[0]` is the first bit on the line).
+
+use anyhow::{Result, bail};
+
+/// Errors found by comparing two bit streams
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitErrors {
+    /// Number of compared bits
+    pub bits: usize,
+    /// Positions (in the reference stream) of the bits that differ
+    pub positions: Vec<usize>,
+}
+
+/// Consecutive bit errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Burst {
+    /// Position of the first errored bit
+    pub start: usize,
+    /// Bits from the first to the last errored bit
+    pub len: usize,
+    /// Errored bits in the burst
+    pub errors: usize,
+}
+
+/// Summary of the bursts of a comparison
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BurstStats {
+    pub count: usize,
+    /// Length in bits of the longest burst
+    pub longest: usize,
+    /// Mean length in bits of the bursts
+    pub mean_len: f64,
+}
+
+/// Alignment of a received stream against its reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alignment {
+    /// The received bit `i + offset` corresponds to the reference bit `i`
+    pub offset: isize,
+    pub errors: BitErrors,
+}
+
+impl BitErrors {
+    pub fn errors(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Bit error rate (`0.0` if nothing was compared)
+    pub fn ber(&self) -> f64 {
+        match self.bits {
+            0 => 0.0,
+            bits => self.positions.len() as f64 / bits as f64,
+        }
+    }
+
+    /// Group the errors in bursts, errors separated by up to `max_gap` correct bits belong to the same burst
+    pub fn bursts(&self, max_gap: usize) -> Vec<Burst> {
+        let mut bursts: Vec<Burst> = vec![];
+
+        for &position in &self.positions {
+            match bursts.last_mut() {
+                Some(burst) if position - (burst.start + burst.len) <= max_gap => {
+                    burst.len = position - burst.start + 1;
+                    burst.errors += 1;
+                }
+                _ => bursts.push(Burst {
+                    start: position,
+                    len: 1,
+                    errors: 1,
+                }),
+            }
+        }
+
+        bursts
+    }
+
+    pub fn burst_stats(&self, max_gap: usize) -> BurstStats {
+        let bursts = self.bursts(max_gap);
+
+        match bursts.len() {
+            0 => BurstStats::default(),
+            count => BurstStats {
+                count,
+                longest: bursts.iter().map(|burst| burst.len).max().unwrap_or(0),
+                mean_len: bursts.iter().map(|burst| burst.len).sum::<usize>() as f64 / count as f64,
+            },
+        }
+    }
+}
+
+/// Compare the common length of the two streams bit by bit
+pub fn compare_bits(reference: &[u8], received: &[u8]) -> BitErrors {
+    compare_at(reference, received, 0)
+}
+
+/// Find the offset (from `-max_offset` to `max_offset`) of the received stream with the lowest BER against the reference
+/// Offsets where less than half of the shorter stream overlaps are not considered.
+pub fn align_bits(reference: &[u8], received: &[u8], max_offset: usize) -> Result<Alignment> {
+    let min_overlap = reference.len().min(received.len()).div_ceil(2).max(1);
+
+    let mut best: Option<Alignment> = None;
+
+    for offset in -(max_offset as isize)..=max_offset as isize {
+        let errors = compare_at(reference, received, offset);
+
+        if errors.bits < min_overlap {
+            continue;
+        }
+
+        let better = match &best {
+            None => true,
+            Some(best) => {
+                let ber = errors.ber();
+                let best_ber = best.errors.ber();
+                ber < best_ber || (ber == best_ber && offset.abs() < best.offset.abs())
+            }
+        };
+
+        if better {
+            best = Some(Alignment { offset, errors });
+        }
+    }
+
+    match best {
+        Some(alignment) => Ok(alignment),
+        None => bail!(
+            "[align_bits] The streams do not overlap enough within [{}] bits of offset!",
+            max_offset
+        ),
+    }
+}
+
+/// Compare `reference[i]` with `received[i + offset]` where both exist
+fn compare_at(reference: &[u8], received: &[u8], offset: isize) -> BitErrors {
+    let start = (-offset).max(0) as usize;
+    let end = (received.len() as isize - offset).clamp(0, reference.len() as isize) as usize;
+
+    if start >= end {
+        return BitErrors::default();
+    }
+
+    let positions = (start..end)
+        .filter(|&i| (reference[i] != 0) != (received[(i as isize + offset) as usize] != 0))
+        .collect();
+
+    BitErrors {
+        bits: end - start,
+        positions,
+    }
+}
+
+/// Standard pseudo-random bit sequences (ITU-T O.150 polynomials)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prbs {
+    /// x^7 + x^6 + 1
+    Prbs7,
+    /// x^9 + x^5 + 1
+    Prbs9,
+    /// x^15 + x^14 + 1
+    Prbs15,
+    /// x^23 + x^18 + 1
+    Prbs23,
+    /// x^31 + x^28 + 1
+    Prbs31,
+}
+
+impl Prbs {
+    /// Degree of the polynomial (length of the shift register)
+    pub fn degree(&self) -> u32 {
+        self.taps().0
+    }
+
+    /// Length of the sequence before it repeats
+    pub fn period(&self) -> u64 {
+        (1u64 << self.degree()) - 1
+    }
+
+    /// Whether ITU-T O.150 sends the sequence inverted on the line (PRBS15/23/31)
+    pub fn inverted_on_line(&self) -> bool {
+        matches!(self, Prbs::Prbs15 | Prbs::Prbs23 | Prbs::Prbs31)
+    }
+
+    fn taps(&self) -> (u32, u32) {
+        match self {
+            Prbs::Prbs7 => (7, 6),
+            Prbs::Prbs9 => (9, 5),
+            Prbs::Prbs15 => (15, 14),
+            Prbs::Prbs23 => (23, 18),
+            Prbs::Prbs31 => (31, 28),
+        }
+    }
+
+    /// Next bit of the register, which holds the last `degree` bits (bit 0 is the most recent one)
+    fn next_bit(&self, state: u32) -> u8 {
+        let (n, m) = self.taps();
+        (((state >> (n - 1)) ^ (state >> (m - 1))) & 0x01) as u8
+    }
+
+    fn push(&self, state: u32, bit: u8) -> u32 {
+        let mask = (1u32 << self.degree()) - 1;
+        ((state << 1) | bit as u32) & mask
+    }
+}
+
+/// Endless PRBS bit generator
+/// Outputs the raw register bits unless `inverted` is set (ITU-T O.150 inverts PRBS15/23/31 on the line).
+#[derive(Debug, Clone)]
+pub struct PrbsGenerator {
+    prbs: Prbs,
+    state: u32,
+    inverted: bool,
+}
+
+impl PrbsGenerator {
+    /// Generator seeded with all ones
+    pub fn new(prbs: Prbs) -> Self {
+        Self {
+            prbs,
+            state: (1u32 << prbs.degree()) - 1,
+            inverted: false,
+        }
+    }
+
+    /// Generator of the sequence as sent on the line by ITU-T O.150 test equipment
+    pub fn o150(prbs: Prbs) -> Self {
+        Self::new(prbs).inverted(prbs.inverted_on_line())
+    }
+
+    /// Invert the output bits
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Generator seeded with the lowest `degree` bits of `seed`, which cannot all be zero
+    pub fn with_seed(prbs: Prbs, seed: u32) -> Result<Self> {
+        let state = seed & ((1u32 << prbs.degree()) - 1);
+
+        if state == 0 {
+            bail!(
+                "[PrbsGenerator::with_seed] The seed of [{:?}] cannot be zero!",
+                prbs
+            );
+        }
+
+        Ok(Self {
+            prbs,
+            state,
+            inverted: false,
+        })
+    }
+
+    /// Next `len` bits of the sequence
+    pub fn bits(&mut self, len: usize) -> Vec<u8> {
+        self.take(len).collect()
+    }
+}
+
+impl Iterator for PrbsGenerator {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let bit = self.prbs.next_bit(self.state);
+        self.state = self.prbs.push(self.state, bit);
+
+        Some(bit ^ self.inverted as u8)
+    }
+}
+
+/// Result of a PRBS check
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PrbsReport {
+    /// Bits compared while in sync
+    pub bits: usize,
+    pub errors: usize,
+    /// Whether the checker is in sync with the sequence
+    pub locked: bool,
+    /// Number of times the sync was lost
+    pub sync_losses: usize,
+}
+
+impl PrbsReport {
+    /// Bit error rate (`0.0` if nothing was compared)
+    pub fn ber(&self) -> f64 {
+        match self.bits {
+            0 => 0.0,
+            bits => self.errors as f64 / bits as f64,
+        }
+    }
+}
+
+/// PRBS checker that synchronises on its own to the phase of the received sequence
+/// The first `degree` received bits seed a local generator, whose output is then compared with the received bits
+/// (so each bit error is counted once). When more than a quarter of the bits of a 128 bit window are wrong
+/// the sync is considered lost, the window is discarded and the checker seeds itself again.
+/// Inverted sequences (e.g. PRBS15/23/31 from ITU-T O.150 equipment) never lock unless `inverted` is set.
+#[derive(Debug, Clone)]
+pub struct PrbsChecker {
+    prbs: Prbs,
+    inverted: bool,
+    state: u32,
+    /// Bits loaded in the register while synchronising
+    filled: u32,
+    locked: bool,
+    bits: usize,
+    errors: usize,
+    window_bits: usize,
+    window_errors: usize,
+    sync_losses: usize,
+}
+
+const WINDOW_BITS: usize = 128;
+const WINDOW_MAX_ERRORS: usize = WINDOW_BITS / 4;
+
+impl PrbsChecker {
+    pub fn new(prbs: Prbs) -> Self {
+        Self {
+            prbs,
+            inverted: false,
+            state: 0,
+            filled: 0,
+            locked: false,
+            bits: 0,
+            errors: 0,
+            window_bits: 0,
+            window_errors: 0,
+            sync_losses: 0,
+        }
+    }
+
+    /// Checker of the sequence as sent on the line by ITU-T O.150 test equipment
+    pub fn o150(prbs: Prbs) -> Self {
+        Self::new(prbs).inverted(prbs.inverted_on_line())
+    }
+
+    /// Expect the received bits to be inverted
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    /// Check the next received bits
+    pub fn check(&mut self, bits: &[u8]) -> PrbsReport {
+        for &bit in bits {
+            self.push((bit != 0) as u8 ^ self.inverted as u8);
+        }
+
+        self.report()
+    }
+
+    pub fn report(&self) -> PrbsReport {
+        PrbsReport {
+            bits: self.bits,
+            errors: self.errors,
+            locked: self.locked,
+            sync_losses: self.sync_losses,
+        }
+    }
+
+    fn push(&mut self, bit: u8) {
+        if !self.locked {
+            self.state = self.prbs.push(self.state, bit);
+            self.filled += 1;
+
+            // An all zero register would lock on the all zero sequence
+            if self.filled >= self.prbs.degree() && self.state != 0 {
+                self.locked = true;
+                self.window_bits = 0;
+                self.window_errors = 0;
+            }
+
+            return;
+        }
+
+        let expected = self.prbs.next_bit(self.state);
+        self.state = self.prbs.push(self.state, expected);
+
+        self.bits += 1;
+        self.window_bits += 1;
+
+        if bit != expected {
+            self.errors += 1;
+            self.window_errors += 1;
+        }
+
+        if self.window_errors > WINDOW_MAX_ERRORS {
+            self.bits -= self.window_bits;
+            self.errors -= self.window_errors;
+            self.sync_losses += 1;
+            self.locked = false;
+            self.filled = 0;
+            self.state = 0;
+        } else if self.window_bits == WINDOW_BITS {
+            self.window_bits = 0;
+            self.window_errors = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ber::*;
+
+    #[test]
+    fn compare_align_test() {
+        let reference = PrbsGenerator::new(Prbs::Prbs9).bits(500);
+
+        let mut received = vec![1, 0, 0, 1, 1];
+        received.extend_from_slice(&reference[..400]);
+        for position in [10, 11, 13, 200] {
+            received[5 + position] ^= 1;
+        }
+
+        let errors = compare_bits(&reference[..400], &received[5..]);
+        assert_eq!(400, errors.bits);
+        assert_eq!(vec![10, 11, 13, 200], errors.positions);
+        assert_eq!(0.01, errors.ber());
+
+        let bursts = errors.bursts(1);
+        assert_eq!(
+            vec![
+                Burst {
+                    start: 10,
+                    len: 4,
+                    errors: 3
+                },
+                Burst {
+                    start: 200,
+                    len: 1,
+                    errors: 1
+                }
+            ],
+            bursts
+        );
+        let stats = errors.burst_stats(0);
+        assert_eq!(3, stats.count);
+        assert_eq!(2, stats.longest);
+        assert_eq!(4.0 / 3.0, stats.mean_len);
+
+        let alignment = align_bits(&reference, &received, 16).unwrap();
+        assert_eq!(5, alignment.offset);
+        assert_eq!(errors, alignment.errors);
+
+        // Received stream that starts late
+        let alignment = align_bits(&reference, &reference[7..], 16).unwrap();
+        assert_eq!(-7, alignment.offset);
+        assert_eq!(0, alignment.errors.errors());
+        assert_eq!(493, alignment.errors.bits);
+
+        assert!(align_bits(&reference, &[], 4).is_err());
+        assert_eq!(0.0, compare_bits(&[], &reference).ber());
+    }
+
+    #[test]
+    fn prbs_test() {
+        for prbs in [Prbs::Prbs7, Prbs::Prbs9, Prbs::Prbs15] {
+            let period = prbs.period() as usize;
+            let sequence = PrbsGenerator::new(prbs).bits(2 * period);
+
+            // Maximal length sequences repeat after 2^n - 1 bits and hold 2^(n-1) ones
+            assert_eq!(sequence[..period], sequence[period..]);
+            assert_eq!(
+                1 << (prbs.degree() - 1),
+                sequence[..period].iter().filter(|&&bit| bit == 1).count()
+            );
+            assert!((1..period).all(|shift| sequence[shift..shift + 16] != sequence[..16]));
+        }
+
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 0, 1, 0],
+            PrbsGenerator::new(Prbs::Prbs7).bits(8)
+        );
+        assert!(PrbsGenerator::with_seed(Prbs::Prbs7, 0x80).is_err());
+
+        for prbs in [Prbs::Prbs15, Prbs::Prbs23, Prbs::Prbs31] {
+            // Start at an unknown phase after some noise
+            let mut received = vec![1, 1, 0, 1, 0, 0, 0, 1, 1, 1];
+            received.extend(
+                PrbsGenerator::with_seed(prbs, 0x1234_5678)
+                    .unwrap()
+                    .bits(10_000),
+            );
+            for position in [500, 501, 3000, 9000] {
+                received[position] ^= 1;
+            }
+
+            let mut checker = PrbsChecker::new(prbs);
+            let report = checker.check(&received[..5000]);
+            assert!(report.locked);
+
+            let report = checker.check(&received[5000..]);
+            assert!(report.locked);
+            assert_eq!(4, report.errors);
+            assert!(report.sync_losses <= 1);
+            assert!(report.bits > 9000);
+            assert!(report.ber() < 0.001);
+        }
+
+        // ITU-T O.150 sends PRBS15 inverted, the plain checker never locks on it
+        let line = PrbsGenerator::o150(Prbs::Prbs15).bits(5000);
+        let raw = PrbsGenerator::new(Prbs::Prbs15).bits(5000);
+        assert!(line.iter().zip(&raw).all(|(a, b)| a != b));
+        assert_eq!(
+            PrbsGenerator::new(Prbs::Prbs9).bits(100),
+            PrbsGenerator::o150(Prbs::Prbs9).bits(100)
+        );
+
+        let report = PrbsChecker::new(Prbs::Prbs15).check(&line);
+        assert!(report.sync_losses > 10);
+
+        let report = PrbsChecker::o150(Prbs::Prbs15).check(&line[100..]);
+        assert!(report.locked);
+        assert_eq!(0, report.errors);
+        assert_eq!(0, report.sync_losses);
+
+        let report = PrbsChecker::new(Prbs::Prbs9)
+            .inverted(true)
+            .check(&PrbsGenerator::new(Prbs::Prbs9).inverted(true).bits(2000));
+        assert!(report.locked);
+        assert_eq!(0, report.errors);
+
+        // A stream that is not the expected PRBS never stays in sync
+        let mut checker = PrbsChecker::new(Prbs::Prbs7);
+        let report = checker.check(&PrbsGenerator::new(Prbs::Prbs9).bits(2000));
+        assert!(report.sync_losses > 0);
+    }
+}
@@ -0,0 +1,354 @@
+//! # Bitfield utilities
+//! Declarative register maps: `#[derive(Bitfield)]` turns an annotated struct into pack/unpack functions,
+//! so that masks and shifts of hardware registers do not have to be hand-coded.
+//! Bit 0 is the LSB of the register, uncovered bits are reserved and must be zero when unpacking
+//! (unless `check_reserved = false`).
+//! Fields covering whole bytes can use their own byte order, e.g. `#[bits(0..=15, endian = "little")]`.
+//!
+//! ```ignore
+//! use utils_box_bits::bitfield::Bitfield;
+//!
+//! #[derive(Bitfield, Clone, Copy, PartialEq)]
+//! #[bitfield(bytes = 2, endian = "big")]
+//! struct Control {
+//!     #[bits(0..=2)]
+//!     mode: u8,
+//!     #[bits(3)]
+//!     enabled: bool,
+//!     #[bits(8..=15)]
+//!     offset: i8,
+//! }
+//! ```
+//! When the crate is used through `utils-box`, add `crate = "utils_box"` to the `#[bitfield(..)]` options.
+
+use anyhow::{Result, bail};
+
+pub use crate::endian::Endian;
+pub use utils_box_bits_derive::Bitfield;
+
+/// Register structs with fields at fixed bit ranges, implemented by `#[derive(Bitfield)]`
+pub trait Bitfield: Sized {
+    /// Name of the struct (used in error messages)
+    const NAME: &'static str;
+    /// Size of the packed register in bytes (up to 16)
+    const BYTES: usize;
+    const ENDIAN: Endian;
+    /// Bits of the register that are not covered by any field
+    const RESERVED_MASK: u128;
+    /// Whether unpacking fails when reserved bits are set
+    const CHECK_RESERVED: bool;
+
+    /// Pack the fields into the raw register value, fails if a field does not fit in its bit range
+    fn to_raw(&self) -> Result<u128>;
+
+    /// Unpack the fields from the raw register value, ignoring reserved bits
+    fn from_raw_unchecked(raw: u128) -> Self;
+
+    /// Unpack the fields from the raw register value, checking the reserved bits
+    fn from_raw(raw: u128) -> Result<Self> {
+        if Self::BYTES < 16 && raw >> (Self::BYTES * 8) != 0 {
+            bail!(
+                "[{}::from_raw] Value [{:#x}] does not fit in [{}] bytes!",
+                Self::NAME,
+                raw,
+                Self::BYTES
+            );
+        }
+
+        if Self::CHECK_RESERVED && raw & Self::RESERVED_MASK != 0 {
+            bail!(
+                "[{}::from_raw] Reserved bits [{:#x}] are set in [{:#x}]!",
+                Self::NAME,
+                raw & Self::RESERVED_MASK,
+                raw
+            );
+        }
+
+        Ok(Self::from_raw_unchecked(raw))
+    }
+
+    /// Pack the register into `BYTES` bytes
+    fn pack(&self) -> Result<Vec<u8>> {
+        let raw = self.to_raw()?;
+
+        let bytes = match Self::ENDIAN {
+            Endian::Big => raw.to_be_bytes()[16 - Self::BYTES..].to_vec(),
+            Endian::Little => raw.to_le_bytes()[..Self::BYTES].to_vec(),
+        };
+
+        Ok(bytes)
+    }
+
+    /// Unpack the register from exactly `BYTES` bytes
+    fn unpack(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::BYTES {
+            bail!(
+                "[{}::unpack] Expected [{}] bytes but got [{}]!",
+                Self::NAME,
+                Self::BYTES,
+                bytes.len()
+            );
+        }
+
+        let mut raw = [0u8; 16];
+
+        let raw = match Self::ENDIAN {
+            Endian::Big => {
+                raw[16 - Self::BYTES..].copy_from_slice(bytes);
+                u128::from_be_bytes(raw)
+            }
+            Endian::Little => {
+                raw[..Self::BYTES].copy_from_slice(bytes);
+                u128::from_le_bytes(raw)
+            }
+        };
+
+        Self::from_raw(raw)
+    }
+}
+
+/// Types that can be stored in a bitfield (signed integers are stored in two's complement)
+pub trait BitfieldValue: Copy {
+    /// Widest bit range the type can be stored in
+    const BITS: u32;
+
+    /// The value as the lowest `width` bits, `None` if it does not fit
+    fn to_field(self, width: u32) -> Option<u128>;
+
+    /// The value stored in the lowest `width` bits
+    fn from_field(raw: u128, width: u32) -> Self;
+}
+
+impl BitfieldValue for bool {
+    const BITS: u32 = 1;
+
+    fn to_field(self, _width: u32) -> Option<u128> {
+        Some(self as u128)
+    }
+
+    fn from_field(raw: u128, _width: u32) -> Self {
+        raw & 0x01 == 1
+    }
+}
+
+macro_rules! unsigned_bitfield_value {
+    ($($ty:ty),*) => {
+        $(
+            impl BitfieldValue for $ty {
+                const BITS: u32 = <$ty>::BITS;
+
+                fn to_field(self, width: u32) -> Option<u128> {
+                    let value = self as u128;
+
+                    match width < 128 && value >> width != 0 {
+                        true => None,
+                        false => Some(value),
+                    }
+                }
+
+                fn from_field(raw: u128, _width: u32) -> Self {
+                    raw as $ty
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! signed_bitfield_value {
+    ($($ty:ty),*) => {
+        $(
+            impl BitfieldValue for $ty {
+                const BITS: u32 = <$ty>::BITS;
+
+                fn to_field(self, width: u32) -> Option<u128> {
+                    let value = self as i128;
+
+                    if width < 128 {
+                        let min = -(1i128 << (width - 1));
+                        let max = (1i128 << (width - 1)) - 1;
+
+                        if value < min || value > max {
+                            return None;
+                        }
+                    }
+
+                    Some(value as u128 & __private::mask(width))
+                }
+
+                fn from_field(raw: u128, width: u32) -> Self {
+                    let shift = 128 - width;
+                    (((raw << shift) as i128) >> shift) as $ty
+                }
+            }
+        )*
+    };
+}
+
+unsigned_bitfield_value!(u8, u16, u32, u64, u128);
+signed_bitfield_value!(i8, i16, i32, i64, i128);
+
+/// Helpers used by the code generated by `#[derive(Bitfield)]`
+#[doc(hidden)]
+pub mod __private {
+    use super::BitfieldValue;
+
+    pub use anyhow::Result;
+
+    pub fn mask(width: u32) -> u128 {
+        match width {
+            128 => u128::MAX,
+            _ => (1u128 << width) - 1,
+        }
+    }
+
+    pub fn field_to_raw<T: BitfieldValue + std::fmt::Debug>(
+        name: &str,
+        field: &str,
+        value: T,
+        lsb: u32,
+        width: u32,
+    ) -> Result<u128> {
+        match value.to_field(width) {
+            Some(raw) => Ok(raw << lsb),
+            None => anyhow::bail!(
+                "[{}::pack] Field [{}] value [{:?}] does not fit in [{}] bits!",
+                name,
+                field,
+                value,
+                width
+            ),
+        }
+    }
+
+    pub fn field_from_raw<T: BitfieldValue>(raw: u128, lsb: u32, width: u32) -> T {
+        T::from_field((raw >> lsb) & mask(width), width)
+    }
+
+    /// Reverse the byte order of the field (whole bytes) at `lsb`, the other bits are dropped
+    pub fn swap_bytes(raw: u128, lsb: u32, width: u32) -> u128 {
+        (((raw >> lsb) & mask(width)).swap_bytes() >> (128 - width)) << lsb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitfield::*;
+    use crate::bits::*;
+
+    #[derive(Bitfield, Clone, Copy, PartialEq)]
+    #[bitfield(bytes = 4)]
+    struct Status {
+        #[bits(0..=2)]
+        mode: u8,
+        #[bits(3)]
+        enabled: bool,
+        #[bits(4..16)]
+        threshold: u16,
+        #[bits(20..=27)]
+        offset: i8,
+        #[bits(31)]
+        error: bool,
+    }
+
+    #[derive(Bitfield, Clone, Copy, PartialEq)]
+    #[bitfield(endian = "little", check_reserved = false)]
+    struct Config {
+        #[bits(0..=10)]
+        divider: u16,
+        #[bits(12..=14)]
+        gain: i8,
+    }
+
+    #[derive(Bitfield, Clone, Copy, PartialEq)]
+    #[bitfield(bytes = 4)]
+    struct Packet {
+        #[bits(16..=31)]
+        id: u16,
+        #[bits(0..=15, endian = "little")]
+        length: u16,
+    }
+
+    #[test]
+    fn bitfield_round_trip_test() {
+        let status = Status {
+            mode: 0b101,
+            enabled: true,
+            threshold: 0xABC,
+            offset: -3,
+            error: true,
+        };
+
+        let raw: u64 = 0b1000_1111_1101_0000_1010_1011_1100_1101;
+        assert_eq!(raw as u128, status.to_raw().unwrap());
+
+        // Big endian registers match the MSB-first bit vector representation
+        let bits = bits_to_vec(raw, 32);
+        let packed = status.pack().unwrap();
+        assert_eq!(bit_vec_to_byte_vec(&bits), packed);
+        assert_eq!(vec![0x8F, 0xD0, 0xAB, 0xCD], packed);
+
+        let unpacked = Status::unpack(&packed).unwrap();
+        assert!(unpacked == status);
+        assert_eq!(
+            raw,
+            vec_to_bits(&bits_to_vec(unpacked.to_raw().unwrap() as u64, 32))
+        );
+
+        assert_eq!(
+            "Status { mode[2:0]: 5, enabled[3]: true, threshold[15:4]: 2748, offset[27:20]: -3, error[31]: true }",
+            format!("{unpacked:?}")
+        );
+
+        let config = Config {
+            divider: 0x5A5,
+            gain: -4,
+        };
+        assert_eq!(2, Config::BYTES);
+        let packed = config.pack().unwrap();
+        assert_eq!(vec![0xA5, 0x45], packed);
+        assert!(Config::unpack(&packed).unwrap() == config);
+        assert_eq!(
+            bits_to_vec(0x45A5, 16),
+            bits_to_vec(config.to_raw().unwrap() as u64, 16)
+        );
+
+        // Only the field with its own endianness is byte swapped
+        let packet = Packet {
+            id: 0x1234,
+            length: 0x0102,
+        };
+        let packed = packet.pack().unwrap();
+        assert_eq!(vec![0x12, 0x34, 0x02, 0x01], packed);
+        assert!(Packet::unpack(&packed).unwrap() == packet);
+    }
+
+    #[test]
+    fn bitfield_errors_test() {
+        let status = Status {
+            mode: 0b1000,
+            enabled: false,
+            threshold: 0,
+            offset: 0,
+            error: false,
+        };
+        let error = status.pack().unwrap_err();
+        assert!(error.to_string().contains("Field [mode] value [8]"));
+
+        // Bits 16..=19 and 28..=30 are reserved
+        let error = Status::unpack(&[0x00, 0x01, 0x00, 0x00]).unwrap_err();
+        assert!(error.to_string().contains("Reserved bits [0x10000]"));
+        assert!(Status::unpack(&[0x00, 0x00, 0x00]).is_err());
+        assert!(Status::from_raw(1 << 32).is_err());
+
+        // Reserved bits are ignored when not checked
+        let config = Config::unpack(&[0xFF, 0xFF]).unwrap();
+        assert_eq!(0x7FF, config.divider);
+        assert_eq!(-1, config.gain);
+    }
+
+    #[test]
+    fn bitfield_compile_errors_test() {
+        // Overlapping fields, fields wider than their type, too many bytes and partial byte endianness
+        trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+    }
+}
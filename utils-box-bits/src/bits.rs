@@ -1,6 +1,12 @@
 //! # Bits utilities
 //! A toolbox of small utilities that manipulate bitstream data.
 //! Useful for debugging streams from embedded devices.
+//!
+//! Bit vectors hold one bit per byte (`Vec[0]` is the MSB of the stream). When a bit vector is packed into
+//! bytes or hex digits its length is padded to the next multiple of 8 or 4 by appending zero bits at the end,
+//! so the stream is left-aligned: the 21 bits `1_1010_1000_1001_1101_0110` become `D44EB0` and `[0xD4, 0x4E, 0xB0]`.
+
+use anyhow::{Result, bail};
 
 /// Convert bits stream to vector (MSB is Vec[0])
 pub fn bits_to_vec(bits: u64, out_len: u8) -> Vec<u8> {
@@ -22,6 +28,7 @@ pub fn bits_to_vec(bits: u64, out_len: u8) -> Vec<u8> {
 }
 
 /// Convert vector to bits stream (Vec[0] is MSB)
+/// The last byte is padded with zero bits at the end (e.g. `[1, 0, 1]` becomes `[0b1010_0000]`)
 pub fn bit_vec_to_byte_vec(vec: &[u8]) -> Vec<u8> {
     let mod_vec = vec.len() % 8;
     let mut vec = vec.to_owned();
@@ -29,7 +36,7 @@ pub fn bit_vec_to_byte_vec(vec: &[u8]) -> Vec<u8> {
     let mut res: Vec<u8> = vec![];
 
     if mod_vec != 0 {
-        vec.append(&mut vec![0; mod_vec]);
+        vec.append(&mut vec![0; 8 - mod_vec]);
     }
 
     for i in (0..vec.len()).step_by(8) {
@@ -41,18 +48,32 @@ pub fn bit_vec_to_byte_vec(vec: &[u8]) -> Vec<u8> {
     res
 }
 
+/// Convert vector to bits stream (Vec[0] is MSB)
+/// Bits beyond the last 64 are shifted out, use `BitVec::to_u64` for a checked conversion
 pub fn vec_to_bits(vec: &[u8]) -> u64 {
     vec.iter().fold(0b0, |acc, &x| (acc << 1) | x as u64)
 }
 
+/// Convert vector to upper case hex digits (Vec[0] is MSB)
+/// The last digit is padded with zero bits at the end (e.g. 21 bits are printed as 6 digits)
 pub fn bit_vec_to_hex_string(vec: &[u8]) -> String {
+    bit_vec_to_hex_digits(vec, false)
+}
+
+/// Convert vector to lower case hex digits (Vec[0] is MSB)
+/// The last digit is padded with zero bits at the end (e.g. 21 bits are printed as 6 digits)
+pub fn bit_vec_to_lower_hex_string(vec: &[u8]) -> String {
+    bit_vec_to_hex_digits(vec, true)
+}
+
+fn bit_vec_to_hex_digits(vec: &[u8], lower_case: bool) -> String {
     let mut hex_string = String::new();
 
     let mod_vec = vec.len() % 4;
     let mut vec = vec.to_owned();
 
     if mod_vec != 0 {
-        vec.append(&mut vec![0; mod_vec]);
+        vec.append(&mut vec![0; 4 - mod_vec]);
     }
 
     for i in (0..vec.len()).step_by(4) {
@@ -60,6 +81,7 @@ pub fn bit_vec_to_hex_string(vec: &[u8]) -> String {
 
         let char = match bits {
             0..=9 => bits + 48,
+            10..=15 if lower_case => bits + 87,
             10..=15 => bits + 55,
             _ => unreachable!(),
         } as char;
@@ -70,6 +92,95 @@ pub fn bit_vec_to_hex_string(vec: &[u8]) -> String {
     hex_string
 }
 
+/// Parse hex digits into a bit vector (MSB is Vec[0]), each digit holding 4 bits
+/// An optional `0x` prefix, underscores and whitespace are accepted (e.g. `0xD4_4E B`)
+pub fn hex_string_to_bit_vec(hex: &str) -> Result<Vec<u8>> {
+    digits_to_bit_vec("hex_string_to_bit_vec", hex, "0x", 4)
+}
+
+/// Parse binary digits into a bit vector (MSB is Vec[0]), each digit holding 1 bit
+/// An optional `0b` prefix, underscores and whitespace are accepted (e.g. `0b1101_0100`)
+pub fn bin_string_to_bit_vec(bin: &str) -> Result<Vec<u8>> {
+    digits_to_bit_vec("bin_string_to_bit_vec", bin, "0b", 1)
+}
+
+/// Parse octal digits into a bit vector (MSB is Vec[0]), each digit holding 3 bits
+/// An optional `0o` prefix, underscores and whitespace are accepted (e.g. `0o644`)
+pub fn oct_string_to_bit_vec(oct: &str) -> Result<Vec<u8>> {
+    digits_to_bit_vec("oct_string_to_bit_vec", oct, "0o", 3)
+}
+
+/// Parse hex digits into bytes, an odd number of digits is padded with zero bits at the end (`ABC` becomes `[0xAB, 0xC0]`)
+pub fn hex_string_to_byte_vec(hex: &str) -> Result<Vec<u8>> {
+    Ok(bit_vec_to_byte_vec(&hex_string_to_bit_vec(hex)?))
+}
+
+/// Parse binary digits into bytes, the last byte is padded with zero bits at the end (`0b101` becomes `[0b1010_0000]`)
+pub fn bin_string_to_byte_vec(bin: &str) -> Result<Vec<u8>> {
+    Ok(bit_vec_to_byte_vec(&bin_string_to_bit_vec(bin)?))
+}
+
+/// Parse octal digits into bytes, the last byte is padded with zero bits at the end (`0o7` becomes `[0b1110_0000]`)
+pub fn oct_string_to_byte_vec(oct: &str) -> Result<Vec<u8>> {
+    Ok(bit_vec_to_byte_vec(&oct_string_to_bit_vec(oct)?))
+}
+
+fn digits_to_bit_vec(caller: &str, input: &str, prefix: &str, digit_bits: u32) -> Result<Vec<u8>> {
+    let digits: String = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '_')
+        .collect();
+
+    let digits = match digits.get(..prefix.len()) {
+        Some(p) if p.eq_ignore_ascii_case(prefix) => &digits[prefix.len()..],
+        _ => &digits[..],
+    };
+
+    let mut bit_vec: Vec<u8> = Vec::with_capacity(digits.len() * digit_bits as usize);
+
+    for c in digits.chars() {
+        let digit = match c.to_digit(1 << digit_bits) {
+            Some(digit) => digit,
+            None => bail!("[{}] Invalid digit [{}] in [{}]!", caller, c, input),
+        };
+
+        for i in (0..digit_bits).rev() {
+            bit_vec.push(((digit >> i) & 0x01) as u8);
+        }
+    }
+
+    Ok(bit_vec)
+}
+
+/// Format bytes like `xxd`: 16 bytes per line with the offset, hex digits in groups of 2 bytes and an ASCII column
+/// (e.g. `00000000: 4865 6c6c 6f0a                           Hello.`)
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::with_capacity(40);
+
+        for (i, byte) in chunk.iter().enumerate() {
+            if i > 0 && i % 2 == 0 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{byte:02x}"));
+        }
+
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7E => byte as char,
+                _ => '.',
+            })
+            .collect();
+
+        dump.push_str(&format!("{:08x}: {hex:<39}  {ascii}\n", line * 16));
+    }
+
+    dump
+}
+
 #[cfg(test)]
 mod tests {
     use crate::bits::*;
@@ -96,5 +207,62 @@ mod tests {
         let string = bit_vec_to_hex_string(&bit_array);
 
         assert_eq!(expected_string, string);
+
+        // Padding is appended at the end of the stream
+        assert_eq!("A", bit_vec_to_hex_string(&[1, 0, 1]));
+        assert_eq!("d44eb0", bit_vec_to_lower_hex_string(&bit_array));
+        assert_eq!(vec![0xD4, 0x4E, 0xB0], bit_vec_to_byte_vec(&bit_array));
+        assert_eq!(vec![0b1010_0000], bit_vec_to_byte_vec(&[1, 0, 1]));
+        assert_eq!("", bit_vec_to_hex_string(&[]));
+    }
+
+    #[test]
+    fn string_to_bit_vec_test() {
+        let expected = bits_to_vec(0xD44E, 16);
+
+        assert_eq!(expected, hex_string_to_bit_vec("0xD44E").unwrap());
+        assert_eq!(expected, hex_string_to_bit_vec(" d4_4e\n").unwrap());
+        assert_eq!(
+            expected,
+            bin_string_to_bit_vec("0b1101_0100 0100_1110").unwrap()
+        );
+        assert_eq!(
+            bits_to_vec(0o644, 9),
+            oct_string_to_bit_vec("0o644").unwrap()
+        );
+
+        assert_eq!(vec![0xD4, 0x4E], hex_string_to_byte_vec("0XD4 4E").unwrap());
+        assert_eq!(vec![0xAB, 0xC0], hex_string_to_byte_vec("ABC").unwrap());
+        assert_eq!(vec![0b1010_0000], bin_string_to_byte_vec("101").unwrap());
+        assert_eq!(
+            vec![0b1111_1000, 0],
+            oct_string_to_byte_vec("0o760").unwrap()
+        );
+        assert!(hex_string_to_byte_vec("").unwrap().is_empty());
+
+        // Round trip through the hex representation
+        let bit_array = bits_to_vec(0b110101000100111010110, 21);
+        let hex = bit_vec_to_hex_string(&bit_array);
+        assert_eq!(
+            bit_vec_to_byte_vec(&bit_array),
+            hex_string_to_byte_vec(&hex).unwrap()
+        );
+
+        let error = hex_string_to_bit_vec("0xD4G").unwrap_err();
+        assert!(error.to_string().contains("Invalid digit [G]"));
+        assert!(bin_string_to_bit_vec("0b102").is_err());
+        assert!(oct_string_to_bit_vec("0o8").is_err());
+    }
+
+    #[test]
+    fn hexdump_test() {
+        let dump = hexdump(b"Hello, world!\n\x00\x01\xffutils-box");
+
+        assert_eq!(
+            "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 210a 0001  Hello, world!...\n\
+             00000010: ff75 7469 6c73 2d62 6f78                 .utils-box\n",
+            dump
+        );
+        assert_eq!("", hexdump(&[]));
     }
 }
@@ -0,0 +1,487 @@
+//! # Bit stream utilities
+//! Read and write fields of arbitrary bit width that straddle byte boundaries.
+//! Useful for parsing custom telemetry frames and building protocol packets
+//! over byte slices, buffers or any `std::io::Read`/`Write` stream.
+
+use anyhow::{Result, bail};
+use std::io::{self, Read, Write};
+
+use crate::bitvec::BitVec;
+
+/// Order in which the bits of each byte are consumed and the bits of each field are assembled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Bit 7 of each byte comes first and the first bit of a field is its MSB (network protocols)
+    #[default]
+    MsbFirst,
+    /// Bit 0 of each byte comes first and the first bit of a field is its LSB (e.g. DEFLATE)
+    LsbFirst,
+}
+
+/// Reads bit fields from a byte stream (`&[u8]` implements `Read`)
+pub struct BitReader<R: Read> {
+    reader: R,
+    order: BitOrder,
+    /// Byte currently being consumed
+    current: u8,
+    /// Bits of the current byte not consumed yet
+    remaining: u8,
+    bits_read: u64,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(reader: R, order: BitOrder) -> Self {
+        Self {
+            reader,
+            order,
+            current: 0,
+            remaining: 0,
+            bits_read: 0,
+        }
+    }
+
+    /// Number of bits consumed so far
+    pub fn bits_read(&self) -> u64 {
+        self.bits_read
+    }
+
+    /// Whether the reader is at a byte boundary
+    pub fn is_aligned(&self) -> bool {
+        self.remaining == 0
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool> {
+        match self.next_bit()? {
+            Some(bit) => Ok(bit),
+            None => bail!(
+                "[BitReader::read_bit] Ran out of bits after reading [{}] bits!",
+                self.bits_read
+            ),
+        }
+    }
+
+    /// Next bit of the stream, `None` at the end of the stream (other I/O errors are returned as they are)
+    fn next_bit(&mut self) -> io::Result<Option<bool>> {
+        if self.remaining == 0 {
+            let mut byte = [0u8; 1];
+
+            match self.reader.read_exact(&mut byte) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+
+            self.current = byte[0];
+            self.remaining = 8;
+        }
+
+        self.remaining -= 1;
+        self.bits_read += 1;
+
+        let bit = match self.order {
+            BitOrder::MsbFirst => (self.current >> self.remaining) & 0x01,
+            BitOrder::LsbFirst => (self.current >> (7 - self.remaining)) & 0x01,
+        };
+
+        Ok(Some(bit == 1))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        self.read_bit()
+    }
+
+    /// Read an unsigned field of `width` bits (up to 64)
+    pub fn read_unsigned(&mut self, width: u32) -> Result<u64> {
+        check_width("BitReader::read_unsigned", width)?;
+
+        let start = self.bits_read;
+        let mut value: u64 = 0;
+
+        for i in 0..width {
+            let bit = match self.next_bit()? {
+                Some(bit) => bit as u64,
+                None => bail!(
+                    "[BitReader::read_unsigned] Ran out of bits reading a [{}] bit field at bit [{}], only [{}] bits available!",
+                    width,
+                    start,
+                    i
+                ),
+            };
+
+            match self.order {
+                BitOrder::MsbFirst => value = (value << 1) | bit,
+                BitOrder::LsbFirst => value |= bit << i,
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Read a two's complement signed field of `width` bits (up to 64)
+    pub fn read_signed(&mut self, width: u32) -> Result<i64> {
+        let value = self.read_unsigned(width)?;
+
+        Ok(sign_extend(value, width))
+    }
+
+    /// Read `len` bits into a `BitVec` (in the order they are consumed)
+    pub fn read_bit_vec(&mut self, len: usize) -> Result<BitVec> {
+        let mut bit_vec = BitVec::with_capacity(len);
+
+        for _ in 0..len {
+            bit_vec.push(self.read_bit()?);
+        }
+
+        Ok(bit_vec)
+    }
+
+    /// Skip `bits` bits of padding
+    pub fn skip(&mut self, bits: u64) -> Result<()> {
+        for _ in 0..bits {
+            self.read_bit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Skip the rest of the current byte
+    pub fn align_to_byte(&mut self) -> Result<()> {
+        self.skip(self.remaining as u64)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Writes bit fields to a byte stream (`Vec<u8>` implements `Write`)
+/// The last partial byte is only written by `align_to_byte` or `finish`
+pub struct BitWriter<W: Write> {
+    writer: W,
+    order: BitOrder,
+    /// Byte currently being filled
+    current: u8,
+    /// Bits of the current byte filled so far
+    filled: u8,
+    bits_written: u64,
+}
+
+impl<W: Write> BitWriter<W> {
+    pub fn new(writer: W, order: BitOrder) -> Self {
+        Self {
+            writer,
+            order,
+            current: 0,
+            filled: 0,
+            bits_written: 0,
+        }
+    }
+
+    /// Number of bits written so far
+    pub fn bits_written(&self) -> u64 {
+        self.bits_written
+    }
+
+    /// Whether the writer is at a byte boundary
+    pub fn is_aligned(&self) -> bool {
+        self.filled == 0
+    }
+
+    pub fn write_bit(&mut self, bit: bool) -> Result<()> {
+        let bit = bit as u8;
+
+        let current = match self.order {
+            BitOrder::MsbFirst => self.current | bit << (7 - self.filled),
+            BitOrder::LsbFirst => self.current | bit << self.filled,
+        };
+
+        // The state is only updated once the byte is written, so a failed write can be retried
+        if self.filled == 7 {
+            self.writer.write_all(&[current])?;
+            self.current = 0;
+            self.filled = 0;
+        } else {
+            self.current = current;
+            self.filled += 1;
+        }
+
+        self.bits_written += 1;
+
+        Ok(())
+    }
+
+    pub fn write_bool(&mut self, bit: bool) -> Result<()> {
+        self.write_bit(bit)
+    }
+
+    /// Write an unsigned field of `width` bits (up to 64), fails if the value does not fit
+    pub fn write_unsigned(&mut self, value: u64, width: u32) -> Result<()> {
+        check_width("BitWriter::write_unsigned", width)?;
+
+        if width < 64 && value >> width != 0 {
+            bail!(
+                "[BitWriter::write_unsigned] Value [{}] does not fit in [{}] bits!",
+                value,
+                width
+            );
+        }
+
+        for i in 0..width {
+            let shift = match self.order {
+                BitOrder::MsbFirst => width - 1 - i,
+                BitOrder::LsbFirst => i,
+            };
+
+            self.write_bit((value >> shift) & 0x01 == 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a two's complement signed field of `width` bits (up to 64), fails if the value does not fit
+    pub fn write_signed(&mut self, value: i64, width: u32) -> Result<()> {
+        check_width("BitWriter::write_signed", width)?;
+
+        if width < 64 {
+            let min = -(1i64 << (width - 1));
+            let max = (1i64 << (width - 1)) - 1;
+
+            if value < min || value > max {
+                bail!(
+                    "[BitWriter::write_signed] Value [{}] does not fit in [{}] bits!",
+                    value,
+                    width
+                );
+            }
+        }
+
+        let mask = match width {
+            64 => u64::MAX,
+            _ => (1u64 << width) - 1,
+        };
+
+        self.write_unsigned(value as u64 & mask, width)
+    }
+
+    /// Write all bits of the `BitVec` (in the order they are stored)
+    pub fn write_bit_vec(&mut self, bit_vec: &BitVec) -> Result<()> {
+        for bit in bit_vec {
+            self.write_bit(bit)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write `bits` zero bits of padding
+    pub fn write_padding(&mut self, bits: u64) -> Result<()> {
+        for _ in 0..bits {
+            self.write_bit(false)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pad the current byte with zero bits and write it
+    pub fn align_to_byte(&mut self) -> Result<()> {
+        if self.filled != 0 {
+            self.write_padding((8 - self.filled) as u64)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pad and write the last partial byte, flush the stream and return it
+    pub fn finish(mut self) -> Result<W> {
+        self.align_to_byte()?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+fn check_width(caller: &str, width: u32) -> Result<()> {
+    if width == 0 || width > 64 {
+        bail!(
+            "[{}] Invalid field width [{}], expected 1 to 64 bits!",
+            caller,
+            width
+        );
+    }
+
+    Ok(())
+}
+
+/// Interpret the lowest `width` bits as a two's complement number
+fn sign_extend(value: u64, width: u32) -> i64 {
+    let shift = 64 - width;
+    ((value << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::*;
+    use crate::bitstream::*;
+
+    #[test]
+    fn bit_reader_test() {
+        // 3, 11 and 27 bit fields followed by a flag and 6 bits of padding
+        let frame = [
+            0b1010_0000,
+            0b0011_1100,
+            0b0000_0000,
+            0b0000_0000,
+            0b0000_0110,
+            0b1100_0000,
+            0b1000_0000,
+        ];
+        let mut reader = BitReader::new(&frame[..], BitOrder::MsbFirst);
+
+        assert_eq!(0b101, reader.read_unsigned(3).unwrap());
+        assert_eq!(0b000_0000_1111, reader.read_unsigned(11).unwrap());
+        assert_eq!(13, reader.read_unsigned(27).unwrap());
+        assert!(reader.read_bool().unwrap());
+        assert!(!reader.is_aligned());
+        reader.align_to_byte().unwrap();
+        assert_eq!(48, reader.bits_read());
+
+        assert_eq!(-2, reader.read_signed(2).unwrap());
+        assert_eq!(
+            bits_to_vec(0b000000, 6),
+            reader.read_bit_vec(6).unwrap().to_bit_vec()
+        );
+
+        let error = reader.read_unsigned(3).unwrap_err();
+        assert!(error.to_string().contains("only [0] bits available"));
+
+        // Fields wider than the data left
+        let mut reader = BitReader::new(std::io::Cursor::new(vec![0xFF]), BitOrder::LsbFirst);
+        assert_eq!(0b1111, reader.read_unsigned(4).unwrap());
+        let error = reader.read_unsigned(11).unwrap_err();
+        assert!(error.to_string().contains("only [4] bits available"));
+    }
+
+    #[test]
+    fn bit_reader_io_error_test() {
+        /// Reader that delivers one byte and then fails
+        struct FailingReader(bool);
+
+        impl Read for FailingReader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.0 {
+                    return Err(io::Error::other("device error"));
+                }
+                self.0 = true;
+                buf[0] = 0xA5;
+                Ok(1)
+            }
+        }
+
+        let mut reader = BitReader::new(FailingReader(false), BitOrder::MsbFirst);
+        assert_eq!(0xA, reader.read_unsigned(4).unwrap());
+
+        // The I/O error is propagated, not reported as running out of bits
+        let error = reader.read_unsigned(8).unwrap_err();
+        assert!(!error.to_string().contains("Ran out of bits"));
+        assert_eq!(
+            io::ErrorKind::Other,
+            error.downcast_ref::<io::Error>().unwrap().kind()
+        );
+
+        let error = reader.read_bit_vec(8).unwrap_err();
+        assert_eq!("device error", error.to_string());
+    }
+
+    #[test]
+    fn bit_writer_io_error_test() {
+        /// Writer that fails once and then accepts everything
+        struct FailingWriter {
+            failed: bool,
+            bytes: Vec<u8>,
+        }
+
+        impl Write for FailingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if !self.failed {
+                    self.failed = true;
+                    return Err(io::Error::other("device error"));
+                }
+                self.bytes.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        for (order, expected) in [
+            (BitOrder::MsbFirst, vec![0xA5, 0xC0]),
+            (BitOrder::LsbFirst, vec![0xA5, 0x03]),
+        ] {
+            let writer = FailingWriter {
+                failed: false,
+                bytes: vec![],
+            };
+            let mut writer = BitWriter::new(writer, order);
+
+            // The last bit of the byte is not counted when the byte cannot be written
+            let error = writer.write_unsigned(0xA5, 8).unwrap_err();
+            assert_eq!("device error", error.to_string());
+            assert_eq!(7, writer.bits_written());
+            assert!(!writer.is_aligned());
+
+            // Retrying the bit (set in both orders) completes the byte and more bits can be written
+            writer.write_bit(true).unwrap();
+            assert!(writer.is_aligned());
+            writer.write_unsigned(0b11, 2).unwrap();
+            assert_eq!(10, writer.bits_written());
+
+            assert_eq!(expected, writer.finish().unwrap().bytes);
+        }
+    }
+
+    #[test]
+    fn bit_writer_round_trip_test() {
+        for order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = BitWriter::new(vec![], order);
+
+            writer.write_unsigned(0b101, 3).unwrap();
+            writer.write_unsigned(1234, 11).unwrap();
+            writer.write_signed(-12_345_678, 27).unwrap();
+            writer.write_bool(true).unwrap();
+            writer.write_padding(2).unwrap();
+            writer.write_unsigned(u64::MAX, 64).unwrap();
+            writer.write_signed(i64::MIN, 64).unwrap();
+            assert_eq!(172, writer.bits_written());
+
+            let bytes = writer.finish().unwrap();
+            assert_eq!(22, bytes.len());
+
+            let mut reader = BitReader::new(&bytes[..], order);
+            assert_eq!(0b101, reader.read_unsigned(3).unwrap());
+            assert_eq!(1234, reader.read_unsigned(11).unwrap());
+            assert_eq!(-12_345_678, reader.read_signed(27).unwrap());
+            assert!(reader.read_bool().unwrap());
+            reader.skip(2).unwrap();
+            assert_eq!(u64::MAX, reader.read_unsigned(64).unwrap());
+            assert_eq!(i64::MIN, reader.read_signed(64).unwrap());
+        }
+
+        // MSB first streams match the bit vector representation
+        let mut writer = BitWriter::new(vec![], BitOrder::MsbFirst);
+        writer.write_unsigned(0b110101000100111010110, 21).unwrap();
+        let bytes = writer.finish().unwrap();
+        assert_eq!(
+            bit_vec_to_byte_vec(&bits_to_vec(0b1101_0100_0100_1110_1011_0000, 24)),
+            bytes
+        );
+
+        // Values that do not fit are rejected
+        let mut writer = BitWriter::new(vec![], BitOrder::MsbFirst);
+        assert!(writer.write_unsigned(8, 3).is_err());
+        assert!(writer.write_signed(4, 3).is_err());
+        assert!(writer.write_signed(-5, 3).is_err());
+        assert!(writer.write_unsigned(0, 65).is_err());
+        assert_eq!(0, writer.bits_written());
+    }
+}
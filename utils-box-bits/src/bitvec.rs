@@ -0,0 +1,397 @@
+//! # BitVec utilities
+//! Packed bit vector of arbitrary length that stores one bit per bit.
+//! Useful for long bit streams (e.g. FPGA register dumps) that do not fit in a `u64`
+//! and would take eight times their size as `Vec<u8>` of bits.
+
+use anyhow::{Result, bail};
+use std::{
+    fmt,
+    ops::{Bound, Index, RangeBounds},
+};
+
+use crate::bits::hex_string_to_bit_vec;
+
+/// Packed bit vector (MSB first, bit `0` is the first bit of the stream)
+/// The unused bits of the last byte are always kept at zero
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct BitVec {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitVec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty vector with room for `bits` bits
+    pub fn with_capacity(bits: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(bits.div_ceil(8)),
+            len: 0,
+        }
+    }
+
+    /// Create a vector of `len` zero bits
+    pub fn zeros(len: usize) -> Self {
+        Self {
+            bytes: vec![0; len.div_ceil(8)],
+            len,
+        }
+    }
+
+    /// Create a vector from the one-bit-per-byte representation (`Vec[0]` is MSB)
+    /// Any non-zero byte is considered a set bit
+    pub fn from_bit_vec(vec: &[u8]) -> Self {
+        vec.iter().map(|&bit| bit != 0).collect()
+    }
+
+    /// Create a vector with the `len` lowest bits of the stream (same as `bits_to_vec`)
+    pub fn from_bits(bits: u64, len: u8) -> Self {
+        (0..len)
+            .rev()
+            .map(|i| i < 64 && (bits >> i) & 0x01 == 1)
+            .collect()
+    }
+
+    /// Create a vector holding all bits of the bytes (MSB of `bytes[0]` first)
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.to_vec(),
+            len: bytes.len() * 8,
+        }
+    }
+
+    /// Parse a string of hex digits, each digit holding 4 bits (e.g. `D44EB`)
+    /// An optional `0x` prefix, underscores and whitespace are accepted (same as `hex_string_to_bit_vec`)
+    pub fn from_hex_string(hex: &str) -> Result<Self> {
+        Ok(Self::from_bit_vec(&hex_string_to_bit_vec(hex)?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.len = 0;
+    }
+
+    /// Append a bit at the end of the stream
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+
+        self.len += 1;
+        self.set(self.len - 1, bit);
+    }
+
+    /// Remove the last bit of the stream
+    pub fn pop(&mut self) -> Option<bool> {
+        let bit = self.get(self.len.checked_sub(1)?)?;
+
+        self.truncate(self.len - 1);
+
+        Some(bit)
+    }
+
+    /// Keep only the first `len` bits
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+
+        self.len = len;
+        self.bytes.truncate(len.div_ceil(8));
+
+        // Keep the unused bits at zero so that equal vectors have equal bytes
+        if !len.is_multiple_of(8)
+            && let Some(last) = self.bytes.last_mut()
+        {
+            *last &= 0xFF << (8 - len % 8);
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+
+        Some((self.bytes[index / 8] >> (7 - index % 8)) & 0x01 == 1)
+    }
+
+    /// Set the selected bit, panics if the index is out of bounds
+    pub fn set(&mut self, index: usize, bit: bool) {
+        assert!(
+            index < self.len,
+            "[BitVec::set] Index [{}] out of bounds for length [{}]!",
+            index,
+            self.len
+        );
+
+        let mask = 0x80 >> (index % 8);
+
+        match bit {
+            true => self.bytes[index / 8] |= mask,
+            false => self.bytes[index / 8] &= !mask,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            bit_vec: self,
+            front: 0,
+            back: self.len,
+        }
+    }
+
+    /// Copy of the selected range of bits, panics if the range is out of bounds
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> BitVec {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len,
+        };
+
+        assert!(
+            start <= end && end <= self.len,
+            "[BitVec::slice] Range [{}..{}] out of bounds for length [{}]!",
+            start,
+            end,
+            self.len
+        );
+
+        (start..end).map(|i| self[i]).collect()
+    }
+
+    /// Append all bits of the other vector
+    pub fn extend_from_bit_vec(&mut self, other: &BitVec) {
+        // Byte aligned vectors can be joined byte by byte
+        if self.len.is_multiple_of(8) {
+            self.bytes.extend_from_slice(&other.bytes);
+            self.len += other.len;
+        } else {
+            self.extend(other.iter());
+        }
+    }
+
+    /// New vector holding the bits of `self` followed by the bits of `other`
+    pub fn concat(&self, other: &BitVec) -> BitVec {
+        let mut bit_vec = self.clone();
+        bit_vec.extend_from_bit_vec(other);
+        bit_vec
+    }
+
+    /// Convert to the one-bit-per-byte representation (`Vec[0]` is MSB)
+    pub fn to_bit_vec(&self) -> Vec<u8> {
+        self.iter().map(|bit| bit as u8).collect()
+    }
+
+    /// Convert to a bits stream (first bit is MSB), fails if it does not fit in 64 bits
+    pub fn to_u64(&self) -> Result<u64> {
+        if self.len > 64 {
+            bail!("[BitVec::to_u64] [{}] bits do not fit in a u64!", self.len);
+        }
+
+        Ok(self.iter().fold(0, |acc, bit| (acc << 1) | bit as u64))
+    }
+
+    /// Packed bytes of the stream, the last byte is padded with zero bits
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Packed bytes of the stream, the last byte is padded with zero bits
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    /// Upper case hex digits of the stream, the last digit is padded with zero bits
+    /// (e.g. the 21 bits `1_1010_1000_1001_1101_0110` are padded to 24 bits and printed as `D44EB0`)
+    pub fn to_hex_string(&self) -> String {
+        let digits = self.len.div_ceil(4);
+
+        self.bytes
+            .iter()
+            .flat_map(|byte| [byte >> 4, byte & 0x0F])
+            .take(digits)
+            .map(|nibble| char::from_digit(nibble as u32, 16).unwrap_or('0'))
+            .collect::<String>()
+            .to_uppercase()
+    }
+}
+
+impl Index<usize> for BitVec {
+    type Output = bool;
+
+    fn index(&self, index: usize) -> &bool {
+        match self.get(index) {
+            Some(true) => &true,
+            Some(false) => &false,
+            None => panic!(
+                "[BitVec::index] Index [{}] out of bounds for length [{}]!",
+                index, self.len
+            ),
+        }
+    }
+}
+
+impl FromIterator<bool> for BitVec {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut bit_vec = BitVec::new();
+        bit_vec.extend(iter);
+        bit_vec
+    }
+}
+
+impl Extend<bool> for BitVec {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, iter: I) {
+        for bit in iter {
+            self.push(bit);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a BitVec {
+    type Item = bool;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Prints the bits as `0` and `1` characters
+impl fmt::Display for BitVec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for bit in self.iter() {
+            write!(f, "{}", bit as u8)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BitVec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BitVec[{}]({})", self.len, self)
+    }
+}
+
+/// Iterator over the bits of a `BitVec`
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    bit_vec: &'a BitVec,
+    front: usize,
+    back: usize,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        self.front += 1;
+        self.bit_vec.get(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<bool> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        self.back -= 1;
+        self.bit_vec.get(self.back)
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::*;
+    use crate::bitvec::*;
+
+    #[test]
+    fn bit_vec_conversions_test() {
+        let received_bit_stream: u64 = 0b110101000100111010110;
+        let bit_array = bits_to_vec(received_bit_stream, 21);
+
+        let bit_vec = BitVec::from_bits(received_bit_stream, 21);
+
+        assert_eq!(21, bit_vec.len());
+        assert_eq!(3, bit_vec.as_bytes().len());
+        assert_eq!(bit_array, bit_vec.to_bit_vec());
+        assert_eq!(bit_vec, BitVec::from_bit_vec(&bit_array));
+        assert_eq!(received_bit_stream, bit_vec.to_u64().unwrap());
+        assert_eq!(vec![0xD4, 0x4E, 0xB0], bit_vec.to_bytes());
+        assert_eq!("D44EB0", bit_vec.to_hex_string());
+
+        let bytes = BitVec::from_bytes(&[0xD4, 0x4E]);
+        assert_eq!(bit_vec_to_byte_vec(&bytes.to_bit_vec()), bytes.to_bytes());
+        assert_eq!(bytes, BitVec::from_hex_string("0xd44e").unwrap());
+        assert!(BitVec::from_hex_string("D4G").is_err());
+
+        // Longer than a u64
+        let long = BitVec::from_hex_string(&"A5".repeat(16)).unwrap();
+        assert_eq!(128, long.len());
+        assert!(long.to_u64().is_err());
+        assert_eq!("A5".repeat(16), long.to_hex_string());
+    }
+
+    #[test]
+    fn bit_vec_operations_test() {
+        let mut bit_vec = BitVec::new();
+        for bit in [true, false, true, true, false, false, true, false, true] {
+            bit_vec.push(bit);
+        }
+
+        assert_eq!("101100101", bit_vec.to_string());
+        assert!(bit_vec[0]);
+        assert!(!bit_vec[1]);
+        assert_eq!(None, bit_vec.get(9));
+
+        assert_eq!(Some(true), bit_vec.pop());
+        assert_eq!(BitVec::from_bytes(&[0b1011_0010]), bit_vec);
+
+        bit_vec.set(1, true);
+        assert_eq!("11110010", bit_vec.to_string());
+
+        assert_eq!("1100", bit_vec.slice(2..6).to_string());
+        assert_eq!("111", bit_vec.slice(..=2).to_string());
+        assert_eq!("0010", bit_vec.slice(4..).to_string());
+
+        let joined = bit_vec.slice(..3).concat(&bit_vec.slice(6..));
+        assert_eq!("11110", joined.to_string());
+        assert_eq!(
+            "0100",
+            bit_vec.iter().rev().take(4).collect::<BitVec>().to_string()
+        );
+
+        // Truncated bits do not leak into equality
+        let mut truncated = BitVec::from_bytes(&[0xFF]);
+        truncated.truncate(3);
+        assert_eq!(BitVec::from_bit_vec(&[1, 1, 1]), truncated);
+        assert_eq!(BitVec::zeros(3), BitVec::from_bit_vec(&[0, 0, 0]));
+    }
+}
@@ -0,0 +1,466 @@
+//! # CRC and checksum utilities
+//! Table-driven CRC engine for any width up to 64 bits, parameterised like the CRC catalogue
+//! (width, polynomial, init, reflect in/out, xorout), with the common presets and incremental updates.
+//! Also provides the simple XOR, Fletcher and Adler-32 checksums.
+
+use anyhow::{Result, bail};
+
+/// CRC parameters in the notation of the CRC catalogue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrcParams {
+    pub name: &'static str,
+    /// Width of the CRC in bits (1 to 64)
+    pub width: u8,
+    /// Generator polynomial without the top bit, in normal (MSB first) form
+    pub poly: u64,
+    /// Initial register value, in normal form
+    pub init: u64,
+    /// Whether each input byte is processed LSB first
+    pub refin: bool,
+    /// Whether the final register is reflected before the `xorout`
+    pub refout: bool,
+    pub xorout: u64,
+    /// CRC of the ASCII string `123456789`
+    pub check: u64,
+}
+
+/// CRC-8/SMBUS (also known as plain CRC-8)
+pub const CRC_8_SMBUS: CrcParams = CrcParams {
+    name: "CRC-8/SMBUS",
+    width: 8,
+    poly: 0x07,
+    init: 0x00,
+    refin: false,
+    refout: false,
+    xorout: 0x00,
+    check: 0xF4,
+};
+
+/// CRC-8/MAXIM-DOW (1-Wire devices)
+pub const CRC_8_MAXIM_DOW: CrcParams = CrcParams {
+    name: "CRC-8/MAXIM-DOW",
+    width: 8,
+    poly: 0x31,
+    init: 0x00,
+    refin: true,
+    refout: true,
+    xorout: 0x00,
+    check: 0xA1,
+};
+
+/// CRC-16/IBM-3740 (also known as CRC-16/CCITT-FALSE)
+pub const CRC_16_IBM_3740: CrcParams = CrcParams {
+    name: "CRC-16/IBM-3740",
+    width: 16,
+    poly: 0x1021,
+    init: 0xFFFF,
+    refin: false,
+    refout: false,
+    xorout: 0x0000,
+    check: 0x29B1,
+};
+
+/// CRC-16/KERMIT (also known as CRC-16/CCITT)
+pub const CRC_16_KERMIT: CrcParams = CrcParams {
+    name: "CRC-16/KERMIT",
+    width: 16,
+    poly: 0x1021,
+    init: 0x0000,
+    refin: true,
+    refout: true,
+    xorout: 0x0000,
+    check: 0x2189,
+};
+
+/// CRC-16/XMODEM
+pub const CRC_16_XMODEM: CrcParams = CrcParams {
+    name: "CRC-16/XMODEM",
+    width: 16,
+    poly: 0x1021,
+    init: 0x0000,
+    refin: false,
+    refout: false,
+    xorout: 0x0000,
+    check: 0x31C3,
+};
+
+/// CRC-16/IBM-SDLC (also known as CRC-16/X-25, used by HDLC)
+pub const CRC_16_IBM_SDLC: CrcParams = CrcParams {
+    name: "CRC-16/IBM-SDLC",
+    width: 16,
+    poly: 0x1021,
+    init: 0xFFFF,
+    refin: true,
+    refout: true,
+    xorout: 0xFFFF,
+    check: 0x906E,
+};
+
+/// CRC-16/MODBUS
+pub const CRC_16_MODBUS: CrcParams = CrcParams {
+    name: "CRC-16/MODBUS",
+    width: 16,
+    poly: 0x8005,
+    init: 0xFFFF,
+    refin: true,
+    refout: true,
+    xorout: 0x0000,
+    check: 0x4B37,
+};
+
+/// CRC-16/ARC (also known as CRC-16/IBM)
+pub const CRC_16_ARC: CrcParams = CrcParams {
+    name: "CRC-16/ARC",
+    width: 16,
+    poly: 0x8005,
+    init: 0x0000,
+    refin: true,
+    refout: true,
+    xorout: 0x0000,
+    check: 0xBB3D,
+};
+
+/// CRC-32/ISO-HDLC (Ethernet, zip, PNG)
+pub const CRC_32_ISO_HDLC: CrcParams = CrcParams {
+    name: "CRC-32/ISO-HDLC",
+    width: 32,
+    poly: 0x04C11DB7,
+    init: 0xFFFFFFFF,
+    refin: true,
+    refout: true,
+    xorout: 0xFFFFFFFF,
+    check: 0xCBF43926,
+};
+
+/// CRC-32/ISCSI (also known as CRC-32C)
+pub const CRC_32_ISCSI: CrcParams = CrcParams {
+    name: "CRC-32/ISCSI",
+    width: 32,
+    poly: 0x1EDC6F41,
+    init: 0xFFFFFFFF,
+    refin: true,
+    refout: true,
+    xorout: 0xFFFFFFFF,
+    check: 0xE3069283,
+};
+
+/// CRC-32/BZIP2
+pub const CRC_32_BZIP2: CrcParams = CrcParams {
+    name: "CRC-32/BZIP2",
+    width: 32,
+    poly: 0x04C11DB7,
+    init: 0xFFFFFFFF,
+    refin: false,
+    refout: false,
+    xorout: 0xFFFFFFFF,
+    check: 0xFC891918,
+};
+
+/// CRC-32/MPEG-2
+pub const CRC_32_MPEG_2: CrcParams = CrcParams {
+    name: "CRC-32/MPEG-2",
+    width: 32,
+    poly: 0x04C11DB7,
+    init: 0xFFFFFFFF,
+    refin: false,
+    refout: false,
+    xorout: 0x00000000,
+    check: 0x0376E6E7,
+};
+
+/// CRC-64/XZ
+pub const CRC_64_XZ: CrcParams = CrcParams {
+    name: "CRC-64/XZ",
+    width: 64,
+    poly: 0x42F0E1EBA9EA3693,
+    init: 0xFFFFFFFFFFFFFFFF,
+    refin: true,
+    refout: true,
+    xorout: 0xFFFFFFFFFFFFFFFF,
+    check: 0x995DC9BBDF1939FA,
+};
+
+/// CRC-64/ECMA-182
+pub const CRC_64_ECMA_182: CrcParams = CrcParams {
+    name: "CRC-64/ECMA-182",
+    width: 64,
+    poly: 0x42F0E1EBA9EA3693,
+    init: 0x0000000000000000,
+    refin: false,
+    refout: false,
+    xorout: 0x0000000000000000,
+    check: 0x6C40DF5F0B497347,
+};
+
+/// All the presets of this module
+pub const CRC_PRESETS: &[CrcParams] = &[
+    CRC_8_SMBUS,
+    CRC_8_MAXIM_DOW,
+    CRC_16_IBM_3740,
+    CRC_16_KERMIT,
+    CRC_16_XMODEM,
+    CRC_16_IBM_SDLC,
+    CRC_16_MODBUS,
+    CRC_16_ARC,
+    CRC_32_ISO_HDLC,
+    CRC_32_ISCSI,
+    CRC_32_BZIP2,
+    CRC_32_MPEG_2,
+    CRC_64_XZ,
+    CRC_64_ECMA_182,
+];
+
+/// Table-driven CRC engine
+#[derive(Clone)]
+pub struct Crc {
+    params: CrcParams,
+    table: [u64; 256],
+}
+
+impl Crc {
+    /// Build the lookup table of the CRC, fails if the parameters do not fit in its width
+    pub fn new(params: CrcParams) -> Result<Self> {
+        if params.width == 0 || params.width > 64 {
+            bail!(
+                "[Crc::new] Invalid width [{}] of [{}], expected 1 to 64 bits!",
+                params.width,
+                params.name
+            );
+        }
+
+        let mask = mask(params.width);
+
+        if params.poly & !mask != 0 || params.init & !mask != 0 || params.xorout & !mask != 0 {
+            bail!(
+                "[Crc::new] Polynomial, init or xorout of [{}] do not fit in [{}] bits!",
+                params.name,
+                params.width
+            );
+        }
+
+        let mut table = [0u64; 256];
+
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = match params.refin {
+                // Reflected register, the LSB is processed first
+                true => {
+                    let poly = reflect(params.poly, params.width);
+                    (0..8).fold(i as u64, |reg, _| match reg & 0x01 {
+                        1 => (reg >> 1) ^ poly,
+                        _ => reg >> 1,
+                    })
+                }
+                // Register aligned to the top of the u64, the MSB is processed first
+                false => {
+                    let poly = params.poly << (64 - params.width);
+                    (0..8).fold((i as u64) << 56, |reg, _| match reg >> 63 {
+                        1 => (reg << 1) ^ poly,
+                        _ => reg << 1,
+                    })
+                }
+            };
+        }
+
+        Ok(Self { params, table })
+    }
+
+    pub fn params(&self) -> &CrcParams {
+        &self.params
+    }
+
+    /// CRC of the whole buffer
+    pub fn checksum(&self, data: &[u8]) -> u64 {
+        let mut digest = self.digest();
+        digest.update(data);
+        digest.finalize()
+    }
+
+    /// Start an incremental CRC calculation
+    pub fn digest(&self) -> CrcDigest<'_> {
+        let register = match self.params.refin {
+            true => reflect(self.params.init, self.params.width),
+            false => self.params.init << (64 - self.params.width),
+        };
+
+        CrcDigest {
+            crc: self,
+            register,
+        }
+    }
+}
+
+/// Incremental CRC calculation
+#[derive(Clone)]
+pub struct CrcDigest<'a> {
+    crc: &'a Crc,
+    register: u64,
+}
+
+impl CrcDigest<'_> {
+    pub fn update(&mut self, data: &[u8]) {
+        let table = &self.crc.table;
+
+        match self.crc.params.refin {
+            true => {
+                for &byte in data {
+                    let index = (self.register as u8 ^ byte) as usize;
+                    self.register = (self.register >> 8) ^ table[index];
+                }
+            }
+            false => {
+                for &byte in data {
+                    let index = ((self.register >> 56) as u8 ^ byte) as usize;
+                    self.register = (self.register << 8) ^ table[index];
+                }
+            }
+        }
+    }
+
+    /// CRC of all the data so far (the digest can keep being updated)
+    pub fn finalize(&self) -> u64 {
+        let params = &self.crc.params;
+
+        // Bring the register to normal form
+        let crc = match params.refin {
+            true => reflect(self.register, params.width),
+            false => self.register >> (64 - params.width),
+        };
+
+        let crc = match params.refout {
+            true => reflect(crc, params.width),
+            false => crc,
+        };
+
+        (crc ^ params.xorout) & mask(params.width)
+    }
+}
+
+/// XOR of all bytes (longitudinal redundancy check)
+pub fn xor_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, &byte| acc ^ byte)
+}
+
+/// Fletcher-16 checksum over bytes
+pub fn fletcher16(data: &[u8]) -> u16 {
+    let (sum1, sum2) = data.iter().fold((0u32, 0u32), |(sum1, sum2), &byte| {
+        let sum1 = (sum1 + byte as u32) % 255;
+        (sum1, (sum2 + sum1) % 255)
+    });
+
+    ((sum2 << 8) | sum1) as u16
+}
+
+/// Fletcher-32 checksum over little endian 16-bit words, an odd length is padded with a zero byte
+pub fn fletcher32(data: &[u8]) -> u32 {
+    let (sum1, sum2) = data.chunks(2).fold((0u64, 0u64), |(sum1, sum2), word| {
+        let word = word[0] as u64 | (*word.get(1).unwrap_or(&0) as u64) << 8;
+        let sum1 = (sum1 + word) % 65535;
+        (sum1, (sum2 + sum1) % 65535)
+    });
+
+    ((sum2 << 16) | sum1) as u32
+}
+
+/// Adler-32 checksum (zlib)
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % MOD_ADLER;
+        (a, (b + a) % MOD_ADLER)
+    });
+
+    (b << 16) | a
+}
+
+fn mask(width: u8) -> u64 {
+    match width {
+        64 => u64::MAX,
+        _ => (1u64 << width) - 1,
+    }
+}
+
+/// Reverse the order of the lowest `width` bits
+fn reflect(value: u64, width: u8) -> u64 {
+    value.reverse_bits() >> (64 - width)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crc::*;
+
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn crc_presets_test() {
+        for params in CRC_PRESETS {
+            let crc = Crc::new(*params).unwrap();
+            assert_eq!(params.check, crc.checksum(CHECK), "{}", params.name);
+
+            // Incremental updates match the one-shot calculation
+            let mut digest = crc.digest();
+            digest.update(&CHECK[..4]);
+            digest.update(&[]);
+            digest.update(&CHECK[4..]);
+            assert_eq!(params.check, digest.finalize(), "{}", params.name);
+        }
+
+        // Widths that are not a multiple of 8 (CRC-5/USB and CRC-15/CAN)
+        let crc5 = CrcParams {
+            name: "CRC-5/USB",
+            width: 5,
+            poly: 0x05,
+            init: 0x1F,
+            refin: true,
+            refout: true,
+            xorout: 0x1F,
+            check: 0x19,
+        };
+        let crc15 = CrcParams {
+            name: "CRC-15/CAN",
+            width: 15,
+            poly: 0x4599,
+            init: 0x0000,
+            refin: false,
+            refout: false,
+            xorout: 0x0000,
+            check: 0x059E,
+        };
+        for params in [crc5, crc15] {
+            assert_eq!(
+                params.check,
+                Crc::new(params).unwrap().checksum(CHECK),
+                "{}",
+                params.name
+            );
+        }
+
+        assert!(
+            Crc::new(CrcParams {
+                width: 8,
+                poly: 0x107,
+                ..CRC_8_SMBUS
+            })
+            .is_err()
+        );
+        assert!(
+            Crc::new(CrcParams {
+                width: 0,
+                ..CRC_8_SMBUS
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn checksums_test() {
+        assert_eq!(0x31, xor_checksum(CHECK));
+        assert_eq!(0x1EDE, fletcher16(CHECK));
+        assert_eq!(0xDF09D509, fletcher32(CHECK));
+        assert_eq!(0xF04FC729, fletcher32(b"abcde"));
+        assert_eq!(0x091E01DE, adler32(CHECK));
+
+        assert_eq!(0, xor_checksum(&[]));
+        assert_eq!(1, adler32(&[]));
+    }
+}
@@ -0,0 +1,431 @@
+//! # Endian utilities
+//! Read and write fixed-width integers (including odd widths like 24 and 48 bits) and floats
+//! in big or little endian order, with bounds-checked errors.
+//! Useful for building and parsing the payloads exchanged with devices over TCP/UDP.
+
+use anyhow::{Result, bail};
+
+/// Byte order of a packed value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Endian {
+    /// Most significant byte first
+    #[default]
+    Big,
+    /// Least significant byte first
+    Little,
+}
+
+/// Reads fixed-width values from a byte buffer, advancing its position
+pub struct ByteCursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    endian: Endian,
+}
+
+impl<'a> ByteCursor<'a> {
+    pub fn new(bytes: &'a [u8], endian: Endian) -> Self {
+        Self {
+            bytes,
+            position: 0,
+            endian,
+        }
+    }
+
+    /// Offset of the next byte to be read
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Number of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    /// Change the byte order of the next reads (e.g. for mixed endian payloads)
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian;
+    }
+
+    /// Move to an absolute offset, fails if it is past the end of the buffer
+    pub fn seek(&mut self, position: usize) -> Result<()> {
+        if position > self.bytes.len() {
+            bail!(
+                "[ByteCursor::seek] Offset [{}] is past the end of the buffer ([{}] bytes)!",
+                position,
+                self.bytes.len()
+            );
+        }
+
+        self.position = position;
+
+        Ok(())
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<()> {
+        self.take("ByteCursor::skip", len)?;
+
+        Ok(())
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        self.take("ByteCursor::read_bytes", len)
+    }
+
+    /// Read an unsigned integer of `width` bytes (1 to 8)
+    pub fn read_uint(&mut self, width: usize) -> Result<u64> {
+        self.uint("ByteCursor::read_uint", width)
+    }
+
+    /// Read a two's complement signed integer of `width` bytes (1 to 8)
+    pub fn read_int(&mut self, width: usize) -> Result<i64> {
+        let value = self.uint("ByteCursor::read_int", width)?;
+
+        Ok(sign_extend(value, width))
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.uint("ByteCursor::read_u8", 1)? as u8)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(self.uint("ByteCursor::read_u16", 2)? as u16)
+    }
+
+    pub fn read_u24(&mut self) -> Result<u32> {
+        Ok(self.uint("ByteCursor::read_u24", 3)? as u32)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(self.uint("ByteCursor::read_u32", 4)? as u32)
+    }
+
+    pub fn read_u48(&mut self) -> Result<u64> {
+        self.uint("ByteCursor::read_u48", 6)
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        self.uint("ByteCursor::read_u64", 8)
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8> {
+        Ok(sign_extend(self.uint("ByteCursor::read_i8", 1)?, 1) as i8)
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16> {
+        Ok(sign_extend(self.uint("ByteCursor::read_i16", 2)?, 2) as i16)
+    }
+
+    pub fn read_i24(&mut self) -> Result<i32> {
+        Ok(sign_extend(self.uint("ByteCursor::read_i24", 3)?, 3) as i32)
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32> {
+        Ok(sign_extend(self.uint("ByteCursor::read_i32", 4)?, 4) as i32)
+    }
+
+    pub fn read_i48(&mut self) -> Result<i64> {
+        Ok(sign_extend(self.uint("ByteCursor::read_i48", 6)?, 6))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64> {
+        Ok(sign_extend(self.uint("ByteCursor::read_i64", 8)?, 8))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.uint("ByteCursor::read_f32", 4)? as u32))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_bits(self.uint("ByteCursor::read_f64", 8)?))
+    }
+
+    fn uint(&mut self, caller: &str, width: usize) -> Result<u64> {
+        check_width(caller, width)?;
+
+        let bytes = self.take(caller, width)?;
+
+        Ok(match self.endian {
+            Endian::Big => bytes.iter().fold(0, |acc, &b| (acc << 8) | b as u64),
+            Endian::Little => bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64),
+        })
+    }
+
+    fn take(&mut self, caller: &str, len: usize) -> Result<&'a [u8]> {
+        if len > self.remaining() {
+            bail!(
+                "[{}] Out of bounds: need [{}] bytes at offset [{}], only [{}] available!",
+                caller,
+                len,
+                self.position,
+                self.remaining()
+            );
+        }
+
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+
+        Ok(bytes)
+    }
+}
+
+/// Appends fixed-width values to a byte buffer, failing if a value does not fit in its width
+#[derive(Debug, Clone, Default)]
+pub struct ByteWriter {
+    bytes: Vec<u8>,
+    endian: Endian,
+}
+
+impl ByteWriter {
+    pub fn new(endian: Endian) -> Self {
+        Self {
+            bytes: vec![],
+            endian,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Change the byte order of the next writes (e.g. for mixed endian payloads)
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Write an unsigned integer as `width` bytes (1 to 8)
+    pub fn write_uint(&mut self, value: u64, width: usize) -> Result<()> {
+        self.uint("ByteWriter::write_uint", value, width)
+    }
+
+    /// Write a two's complement signed integer as `width` bytes (1 to 8)
+    pub fn write_int(&mut self, value: i64, width: usize) -> Result<()> {
+        self.int("ByteWriter::write_int", value, width)
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.put(value as u64, 2);
+    }
+
+    pub fn write_u24(&mut self, value: u32) -> Result<()> {
+        self.uint("ByteWriter::write_u24", value as u64, 3)
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.put(value as u64, 4);
+    }
+
+    pub fn write_u48(&mut self, value: u64) -> Result<()> {
+        self.uint("ByteWriter::write_u48", value, 6)
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.put(value, 8);
+    }
+
+    pub fn write_i8(&mut self, value: i8) {
+        self.bytes.push(value as u8);
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.put(value as u16 as u64, 2);
+    }
+
+    pub fn write_i24(&mut self, value: i32) -> Result<()> {
+        self.int("ByteWriter::write_i24", value as i64, 3)
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.put(value as u32 as u64, 4);
+    }
+
+    pub fn write_i48(&mut self, value: i64) -> Result<()> {
+        self.int("ByteWriter::write_i48", value, 6)
+    }
+
+    pub fn write_i64(&mut self, value: i64) {
+        self.put(value as u64, 8);
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.put(value.to_bits() as u64, 4);
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.put(value.to_bits(), 8);
+    }
+
+    fn uint(&mut self, caller: &str, value: u64, width: usize) -> Result<()> {
+        check_width(caller, width)?;
+
+        if width < 8 && value >> (width * 8) != 0 {
+            bail!(
+                "[{}] Value [{}] does not fit in [{}] bytes!",
+                caller,
+                value,
+                width
+            );
+        }
+
+        self.put(value, width);
+
+        Ok(())
+    }
+
+    fn int(&mut self, caller: &str, value: i64, width: usize) -> Result<()> {
+        check_width(caller, width)?;
+
+        if width < 8 {
+            let min = -(1i64 << (width * 8 - 1));
+            let max = (1i64 << (width * 8 - 1)) - 1;
+
+            if value < min || value > max {
+                bail!(
+                    "[{}] Value [{}] does not fit in [{}] bytes!",
+                    caller,
+                    value,
+                    width
+                );
+            }
+        }
+
+        self.put(value as u64, width);
+
+        Ok(())
+    }
+
+    /// Append the lowest `width` bytes of the value
+    fn put(&mut self, value: u64, width: usize) {
+        match self.endian {
+            Endian::Big => self
+                .bytes
+                .extend_from_slice(&value.to_be_bytes()[8 - width..]),
+            Endian::Little => self.bytes.extend_from_slice(&value.to_le_bytes()[..width]),
+        }
+    }
+}
+
+fn check_width(caller: &str, width: usize) -> Result<()> {
+    if width == 0 || width > 8 {
+        bail!(
+            "[{}] Invalid width [{}], expected 1 to 8 bytes!",
+            caller,
+            width
+        );
+    }
+
+    Ok(())
+}
+
+/// Interpret the lowest `width` bytes as a two's complement number
+fn sign_extend(value: u64, width: usize) -> i64 {
+    let shift = 64 - width * 8;
+    ((value << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::endian::*;
+
+    #[test]
+    fn byte_cursor_test() {
+        let payload = [
+            0x12, 0x34, 0xFF, 0xFF, 0xFE, 0x3F, 0x80, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05,
+            0x06, 0xAA,
+        ];
+
+        let mut cursor = ByteCursor::new(&payload, Endian::Big);
+        assert_eq!(0x1234, cursor.read_u16().unwrap());
+        assert_eq!(-2, cursor.read_i24().unwrap());
+        assert_eq!(1.0, cursor.read_f32().unwrap());
+        assert_eq!(0x0102_0304_0506, cursor.read_u48().unwrap());
+        assert_eq!(1, cursor.remaining());
+
+        let error = cursor.read_u16().unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("need [2] bytes at offset [15], only [1] available")
+        );
+        assert_eq!(15, cursor.position());
+        assert_eq!(-86, cursor.read_i8().unwrap());
+
+        let mut cursor = ByteCursor::new(&payload, Endian::Little);
+        assert_eq!(0x3412, cursor.read_u16().unwrap());
+        assert_eq!(0xFEFFFF, cursor.read_u24().unwrap());
+        cursor.set_endian(Endian::Big);
+        assert_eq!(0x3F800000, cursor.read_uint(4).unwrap());
+        cursor.seek(2).unwrap();
+        assert_eq!(-2, cursor.read_int(3).unwrap());
+        assert!(cursor.seek(17).is_err());
+        assert!(cursor.read_uint(9).is_err());
+        assert_eq!(&payload[5..7], cursor.read_bytes(2).unwrap());
+    }
+
+    #[test]
+    fn byte_writer_round_trip_test() {
+        for endian in [Endian::Big, Endian::Little] {
+            let mut writer = ByteWriter::new(endian);
+
+            writer.write_u8(0xAB);
+            writer.write_u16(0x1234);
+            writer.write_u24(0xABCDEF).unwrap();
+            writer.write_i24(-8_388_608).unwrap();
+            writer.write_u32(0xDEADBEEF);
+            writer.write_u48(0xFFFF_FFFF_FFFF).unwrap();
+            writer.write_i48(-1).unwrap();
+            writer.write_i64(i64::MIN);
+            writer.write_f32(-1.5);
+            writer.write_f64(std::f64::consts::PI);
+            writer.write_int(-300, 2).unwrap();
+            assert_eq!(47, writer.len());
+
+            let bytes = writer.into_bytes();
+            let mut cursor = ByteCursor::new(&bytes, endian);
+            assert_eq!(0xAB, cursor.read_u8().unwrap());
+            assert_eq!(0x1234, cursor.read_u16().unwrap());
+            assert_eq!(0xABCDEF, cursor.read_u24().unwrap());
+            assert_eq!(-8_388_608, cursor.read_i24().unwrap());
+            assert_eq!(0xDEADBEEF, cursor.read_u32().unwrap());
+            assert_eq!(0xFFFF_FFFF_FFFF, cursor.read_u48().unwrap());
+            assert_eq!(-1, cursor.read_i48().unwrap());
+            assert_eq!(i64::MIN, cursor.read_i64().unwrap());
+            assert_eq!(-1.5, cursor.read_f32().unwrap());
+            assert_eq!(std::f64::consts::PI, cursor.read_f64().unwrap());
+            assert_eq!(-300, cursor.read_i16().unwrap());
+            assert_eq!(0, cursor.remaining());
+        }
+
+        let mut writer = ByteWriter::new(Endian::Little);
+        writer.write_u24(0x010203).unwrap();
+        assert_eq!(&[0x03, 0x02, 0x01], writer.as_bytes());
+
+        // Values that do not fit are rejected without writing anything
+        assert!(writer.write_u24(0x0100_0000).is_err());
+        assert!(writer.write_i24(8_388_608).is_err());
+        assert!(writer.write_u48(1 << 48).is_err());
+        assert!(writer.write_uint(256, 1).is_err());
+        assert!(writer.write_int(-129, 1).is_err());
+        assert!(writer.write_uint(0, 0).is_err());
+        assert_eq!(3, writer.len());
+    }
+}
@@ -0,0 +1,641 @@
+//! # Forward error correction utilities
+//! Parity bits, Hamming(7,4) and Hamming(8,4) SECDED over bit vectors,
+//! and a configurable Reed-Solomon codec over GF(2^8) (e.g. RS(255,223)) over bytes.
+//! Decoders report the number of corrected errors and the blocks that could not be corrected.
+
+use anyhow::{Result, bail};
+
+/// Parity conventions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Parity {
+    /// The number of set bits including the parity bit is even
+    #[default]
+    Even,
+    /// The number of set bits including the parity bit is odd
+    Odd,
+}
+
+/// Result of a decoder
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FecDecoded {
+    /// Decoded data, uncorrectable blocks are passed through as received
+    pub data: Vec<u8>,
+    /// Number of corrected errors (bits for Hamming, symbols for Reed-Solomon)
+    pub corrected: usize,
+    /// Indexes of the blocks with more errors than the code can correct
+    pub uncorrectable: Vec<usize>,
+}
+
+/// Parity bit of the bits (any non-zero byte is a set bit)
+pub fn parity_bit(bits: &[u8], parity: Parity) -> u8 {
+    let ones = bits.iter().filter(|&&bit| bit != 0).count() as u8 & 0x01;
+
+    match parity {
+        Parity::Even => ones,
+        Parity::Odd => ones ^ 1,
+    }
+}
+
+/// Append the parity bit to the bits
+pub fn parity_encode(bits: &[u8], parity: Parity) -> Vec<u8> {
+    let mut word = bits.to_vec();
+    word.push(parity_bit(bits, parity));
+
+    word
+}
+
+/// Whether the word (data followed by its parity bit) has the right parity
+pub fn parity_check(word: &[u8], parity: Parity) -> bool {
+    match parity {
+        Parity::Even => parity_bit(word, Parity::Even) == 0,
+        Parity::Odd => parity_bit(word, Parity::Even) == 1,
+    }
+}
+
+/// Encode every 4 data bits as a Hamming(7,4) block `p1 p2 d1 p3 d2 d3 d4`
+pub fn hamming74_encode(data: &[u8]) -> Result<Vec<u8>> {
+    check_blocks("hamming74_encode", data, 4)?;
+
+    Ok(data.chunks(4).flat_map(hamming74_block).collect())
+}
+
+/// Decode Hamming(7,4) blocks, correcting a single bit error per block
+/// Blocks with two errors are silently miscorrected, use Hamming(8,4) to detect them
+pub fn hamming74_decode(code: &[u8]) -> Result<FecDecoded> {
+    check_blocks("hamming74_decode", code, 7)?;
+
+    let mut decoded = FecDecoded::default();
+
+    for block in code.chunks(7) {
+        let mut block = block.to_vec();
+
+        let syndrome = hamming74_syndrome(&block);
+        if syndrome != 0 {
+            block[syndrome - 1] ^= 1;
+            decoded.corrected += 1;
+        }
+
+        decoded.data.extend(hamming74_data(&block));
+    }
+
+    Ok(decoded)
+}
+
+/// Encode every 4 data bits as a Hamming(8,4) SECDED block (Hamming(7,4) followed by an even overall parity bit)
+pub fn hamming84_encode(data: &[u8]) -> Result<Vec<u8>> {
+    check_blocks("hamming84_encode", data, 4)?;
+
+    Ok(data
+        .chunks(4)
+        .flat_map(|nibble| parity_encode(&hamming74_block(nibble), Parity::Even))
+        .collect())
+}
+
+/// Decode Hamming(8,4) SECDED blocks, correcting a single bit error and detecting two bit errors per block
+pub fn hamming84_decode(code: &[u8]) -> Result<FecDecoded> {
+    check_blocks("hamming84_decode", code, 8)?;
+
+    let mut decoded = FecDecoded::default();
+
+    for (i, block) in code.chunks(8).enumerate() {
+        let mut block = block.to_vec();
+
+        let syndrome = hamming74_syndrome(&block[..7]);
+        let parity_error = !parity_check(&block, Parity::Even);
+
+        match (syndrome, parity_error) {
+            (0, false) => {}
+            // Error in the overall parity bit, the data is intact
+            (0, true) => decoded.corrected += 1,
+            (syndrome, true) => {
+                block[syndrome - 1] ^= 1;
+                decoded.corrected += 1;
+            }
+            (_, false) => decoded.uncorrectable.push(i),
+        }
+
+        decoded.data.extend(hamming74_data(&block));
+    }
+
+    Ok(decoded)
+}
+
+fn hamming74_block(nibble: &[u8]) -> [u8; 7] {
+    let d: Vec<u8> = nibble.iter().map(|&bit| (bit != 0) as u8).collect();
+
+    [
+        d[0] ^ d[1] ^ d[3],
+        d[0] ^ d[2] ^ d[3],
+        d[0],
+        d[1] ^ d[2] ^ d[3],
+        d[1],
+        d[2],
+        d[3],
+    ]
+}
+
+/// Position (1 to 7) of the erroneous bit, `0` if there is none
+fn hamming74_syndrome(block: &[u8]) -> usize {
+    let c: Vec<u8> = block.iter().map(|&bit| (bit != 0) as u8).collect();
+
+    let s1 = c[0] ^ c[2] ^ c[4] ^ c[6];
+    let s2 = c[1] ^ c[2] ^ c[5] ^ c[6];
+    let s3 = c[3] ^ c[4] ^ c[5] ^ c[6];
+
+    (s1 | s2 << 1 | s3 << 2) as usize
+}
+
+fn hamming74_data(block: &[u8]) -> [u8; 4] {
+    [block[2], block[4], block[5], block[6]].map(|bit| (bit != 0) as u8)
+}
+
+fn check_blocks(caller: &str, bits: &[u8], block: usize) -> Result<()> {
+    if !bits.len().is_multiple_of(block) {
+        bail!(
+            "[{}] Expected a multiple of [{}] bits but got [{}]!",
+            caller,
+            block,
+            bits.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Reed-Solomon codec over GF(2^8) with `nsym` parity symbols, correcting up to `nsym / 2` symbol errors per block
+/// Blocks hold up to 255 symbols, shorter blocks are shortened codes.
+#[derive(Debug, Clone)]
+pub struct ReedSolomon {
+    nsym: usize,
+    fcr: u8,
+    exp: [u8; 512],
+    log: [u8; 256],
+    generator: Vec<u8>,
+}
+
+impl ReedSolomon {
+    /// Codec with the common parameters: primitive polynomial `0x11D` and first consecutive root `0`
+    /// (e.g. `ReedSolomon::new(32)` for RS(255,223))
+    pub fn new(nsym: usize) -> Result<Self> {
+        Self::with_params(nsym, 0x11D, 0)
+    }
+
+    /// Codec with a custom primitive polynomial (degree 8) and first consecutive root of the generator
+    pub fn with_params(nsym: usize, primitive: u16, fcr: u8) -> Result<Self> {
+        if nsym == 0 || nsym >= 255 {
+            bail!(
+                "[ReedSolomon::with_params] Invalid number of parity symbols [{}], expected 1 to 254!",
+                nsym
+            );
+        }
+
+        let mut exp = [0u8; 512];
+        let mut log = [0u8; 256];
+        let mut x: u16 = 1;
+
+        for (i, power) in exp.iter_mut().take(255).enumerate() {
+            // A primitive polynomial of degree 8 only returns to 1 after all 255 non-zero elements
+            if primitive >> 8 != 1 || (i > 0 && x == 1) {
+                bail!(
+                    "[ReedSolomon::with_params] Polynomial [{:#x}] is not primitive over GF(2^8)!",
+                    primitive
+                );
+            }
+
+            *power = x as u8;
+            log[x as usize] = i as u8;
+
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= primitive;
+            }
+        }
+
+        // Doubled so that the sum of two logarithms needs no modulo
+        exp.copy_within(..255, 255);
+
+        let mut codec = Self {
+            nsym,
+            fcr,
+            exp,
+            log,
+            generator: vec![1],
+        };
+
+        for i in 0..nsym {
+            let root = codec.pow(2, i as i64 + fcr as i64);
+            codec.generator = codec.poly_mul(&codec.generator, &[1, root]);
+        }
+
+        Ok(codec)
+    }
+
+    /// Number of parity symbols per block
+    pub fn nsym(&self) -> usize {
+        self.nsym
+    }
+
+    /// Append the `nsym` parity symbols to the data (up to `255 - nsym` bytes)
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() + self.nsym > 255 {
+            bail!(
+                "[ReedSolomon::encode] Block of [{}] data and [{}] parity symbols is longer than 255!",
+                data.len(),
+                self.nsym
+            );
+        }
+
+        let mut remainder = data.to_vec();
+        remainder.resize(data.len() + self.nsym, 0);
+
+        for i in 0..data.len() {
+            let coef = remainder[i];
+
+            if coef != 0 {
+                for (j, &g) in self.generator.iter().enumerate().skip(1) {
+                    remainder[i + j] ^= self.mul(g, coef);
+                }
+            }
+        }
+
+        let mut block = data.to_vec();
+        block.extend_from_slice(&remainder[data.len()..]);
+
+        Ok(block)
+    }
+
+    /// Correct a block (data followed by parity) and return its data
+    /// A block with too many errors is reported as uncorrectable (index `0`) and its data is returned as received.
+    pub fn decode(&self, block: &[u8]) -> Result<FecDecoded> {
+        if block.len() <= self.nsym || block.len() > 255 {
+            bail!(
+                "[ReedSolomon::decode] Invalid block length [{}], expected [{}] to 255 symbols!",
+                block.len(),
+                self.nsym + 1
+            );
+        }
+
+        let data_len = block.len() - self.nsym;
+
+        let decoded = match self.correct(block) {
+            Some((corrected, errors)) => FecDecoded {
+                data: corrected[..data_len].to_vec(),
+                corrected: errors,
+                uncorrectable: vec![],
+            },
+            None => FecDecoded {
+                data: block[..data_len].to_vec(),
+                corrected: 0,
+                uncorrectable: vec![0],
+            },
+        };
+
+        Ok(decoded)
+    }
+
+    /// Split the data in chunks of `data_len` bytes and encode each one as a block (the last one may be shorter)
+    pub fn encode_blocks(&self, data: &[u8], data_len: usize) -> Result<Vec<u8>> {
+        if data_len == 0 {
+            bail!("[ReedSolomon::encode_blocks] The data length of a block cannot be zero!");
+        }
+
+        let mut code = vec![];
+
+        for chunk in data.chunks(data_len) {
+            code.extend(self.encode(chunk)?);
+        }
+
+        Ok(code)
+    }
+
+    /// Decode consecutive blocks of `block_len` symbols (the last one may be shorter)
+    pub fn decode_blocks(&self, code: &[u8], block_len: usize) -> Result<FecDecoded> {
+        if block_len <= self.nsym || block_len > 255 {
+            bail!(
+                "[ReedSolomon::decode_blocks] Invalid block length [{}], expected [{}] to 255 symbols!",
+                block_len,
+                self.nsym + 1
+            );
+        }
+
+        let mut decoded = FecDecoded::default();
+
+        for (i, block) in code.chunks(block_len).enumerate() {
+            let block = self.decode(block)?;
+
+            decoded.data.extend(block.data);
+            decoded.corrected += block.corrected;
+            if !block.uncorrectable.is_empty() {
+                decoded.uncorrectable.push(i);
+            }
+        }
+
+        Ok(decoded)
+    }
+
+    /// Corrected block and number of corrected symbols, `None` if the block is uncorrectable
+    fn correct(&self, block: &[u8]) -> Option<(Vec<u8>, usize)> {
+        let syndromes = self.syndromes(block);
+
+        if syndromes.iter().all(|&s| s == 0) {
+            return Some((block.to_vec(), 0));
+        }
+
+        let locator = self.error_locator(&syndromes);
+        let errors = locator.len() - 1;
+
+        if errors * 2 > self.nsym {
+            return None;
+        }
+
+        // Chien search over the positions of the (possibly shortened) block
+        let mut reversed = locator.clone();
+        reversed.reverse();
+
+        let positions: Vec<usize> = (0..block.len())
+            .filter(|&i| self.poly_eval(&reversed, self.pow(2, i as i64)) == 0)
+            .map(|i| block.len() - 1 - i)
+            .collect();
+
+        if positions.len() != errors {
+            return None;
+        }
+
+        let corrected = self.forney(block, &syndromes, &positions);
+
+        match self.syndromes(&corrected).iter().all(|&s| s == 0) {
+            true => Some((corrected, errors)),
+            false => None,
+        }
+    }
+
+    /// Syndromes of the block, preceded by a zero
+    fn syndromes(&self, block: &[u8]) -> Vec<u8> {
+        let mut syndromes = vec![0];
+
+        for i in 0..self.nsym {
+            let root = self.pow(2, i as i64 + self.fcr as i64);
+            syndromes.push(self.poly_eval(block, root));
+        }
+
+        syndromes
+    }
+
+    /// Error locator polynomial (Berlekamp-Massey)
+    fn error_locator(&self, syndromes: &[u8]) -> Vec<u8> {
+        let mut locator = vec![1u8];
+        let mut old_locator = vec![1u8];
+
+        for i in 0..self.nsym {
+            let k = i + 1;
+            let mut delta = syndromes[k];
+
+            for j in 1..locator.len() {
+                delta ^= self.mul(locator[locator.len() - 1 - j], syndromes[k - j]);
+            }
+
+            old_locator.push(0);
+
+            if delta != 0 {
+                if old_locator.len() > locator.len() {
+                    let new_locator = self.poly_scale(&old_locator, delta);
+                    old_locator = self.poly_scale(&locator, self.inverse(delta));
+                    locator = new_locator;
+                }
+
+                locator = poly_add(&locator, &self.poly_scale(&old_locator, delta));
+            }
+        }
+
+        let leading = locator.iter().take_while(|&&c| c == 0).count();
+        locator.split_off(leading)
+    }
+
+    /// Correct the symbols at the error positions (Forney)
+    fn forney(&self, block: &[u8], syndromes: &[u8], positions: &[usize]) -> Vec<u8> {
+        let coef_positions: Vec<usize> = positions.iter().map(|&p| block.len() - 1 - p).collect();
+
+        // Errata locator from the known positions
+        let mut locator = vec![1u8];
+        for &position in &coef_positions {
+            locator = self.poly_mul(&locator, &[self.pow(2, position as i64), 1]);
+        }
+
+        // Error evaluator: (syndromes * locator) mod x^(errors + 1)
+        let mut reversed_syndromes = syndromes.to_vec();
+        reversed_syndromes.reverse();
+        let product = self.poly_mul(&reversed_syndromes, &locator);
+        let evaluator = &product[product.len().saturating_sub(locator.len())..];
+
+        let x: Vec<u8> = coef_positions
+            .iter()
+            .map(|&position| self.pow(2, position as i64))
+            .collect();
+
+        let mut corrected = block.to_vec();
+
+        for (i, &xi) in x.iter().enumerate() {
+            let xi_inverse = self.inverse(xi);
+
+            let locator_prime = x
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .fold(1, |acc, (_, &xj)| {
+                    self.mul(acc, 1 ^ self.mul(xi_inverse, xj))
+                });
+
+            let y = self.poly_eval(evaluator, xi_inverse);
+            let y = self.mul(self.pow(xi, 1 - self.fcr as i64), y);
+
+            corrected[positions[i]] ^= self.div(y, locator_prime);
+        }
+
+        corrected
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        match a == 0 || b == 0 {
+            true => 0,
+            false => self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize],
+        }
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        match a == 0 || b == 0 {
+            true => 0,
+            false => {
+                self.exp
+                    [(self.log[a as usize] as usize + 255 - self.log[b as usize] as usize) % 255]
+            }
+        }
+    }
+
+    fn pow(&self, a: u8, power: i64) -> u8 {
+        self.exp[(self.log[a as usize] as i64 * power).rem_euclid(255) as usize]
+    }
+
+    fn inverse(&self, a: u8) -> u8 {
+        self.exp[255 - self.log[a as usize] as usize]
+    }
+
+    fn poly_scale(&self, p: &[u8], x: u8) -> Vec<u8> {
+        p.iter().map(|&c| self.mul(c, x)).collect()
+    }
+
+    fn poly_mul(&self, p: &[u8], q: &[u8]) -> Vec<u8> {
+        let mut product = vec![0u8; p.len() + q.len() - 1];
+
+        for (i, &a) in p.iter().enumerate() {
+            for (j, &b) in q.iter().enumerate() {
+                product[i + j] ^= self.mul(a, b);
+            }
+        }
+
+        product
+    }
+
+    /// Evaluate the polynomial (highest degree first) at `x`
+    fn poly_eval(&self, p: &[u8], x: u8) -> u8 {
+        p.iter().fold(0, |y, &c| self.mul(y, x) ^ c)
+    }
+}
+
+fn poly_add(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let len = p.len().max(q.len());
+    let mut sum = vec![0u8; len];
+
+    for (i, &c) in p.iter().enumerate() {
+        sum[i + len - p.len()] = c;
+    }
+    for (i, &c) in q.iter().enumerate() {
+        sum[i + len - q.len()] ^= c;
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::*;
+    use crate::fec::*;
+
+    #[test]
+    fn parity_hamming_test() {
+        assert_eq!(
+            vec![1, 0, 1, 1, 1],
+            parity_encode(&[1, 0, 1, 1], Parity::Even)
+        );
+        assert_eq!(
+            vec![1, 0, 1, 1, 0],
+            parity_encode(&[1, 0, 1, 1], Parity::Odd)
+        );
+        assert!(parity_check(&[1, 0, 1, 1, 1], Parity::Even));
+        assert!(!parity_check(&[1, 0, 1, 1, 1], Parity::Odd));
+
+        let data = bits_to_vec(0xD44E, 16);
+
+        // Single bit error in every position of every block
+        let code = hamming74_encode(&data).unwrap();
+        assert_eq!(28, code.len());
+        assert_eq!(
+            vec![0, 1, 1, 0, 0, 1, 1],
+            hamming74_encode(&[1, 0, 1, 1]).unwrap()
+        );
+        for position in 0..code.len() {
+            let mut received = code.clone();
+            received[position] ^= 1;
+
+            let decoded = hamming74_decode(&received).unwrap();
+            assert_eq!(data, decoded.data);
+            assert_eq!(1, decoded.corrected);
+        }
+
+        let code = hamming84_encode(&data).unwrap();
+        assert_eq!(32, code.len());
+        let decoded = hamming84_decode(&code).unwrap();
+        assert_eq!(data, decoded.data);
+        assert_eq!(0, decoded.corrected);
+
+        for position in 0..code.len() {
+            let mut received = code.clone();
+            received[position] ^= 1;
+
+            let decoded = hamming84_decode(&received).unwrap();
+            assert_eq!(data, decoded.data);
+            assert_eq!(1, decoded.corrected);
+            assert!(decoded.uncorrectable.is_empty());
+        }
+
+        // Double errors are detected, single errors in other blocks are still corrected
+        let mut received = code.clone();
+        received[9] ^= 1;
+        received[14] ^= 1;
+        received[2] ^= 1;
+        received[31] ^= 1;
+        let decoded = hamming84_decode(&received).unwrap();
+        assert_eq!(vec![1], decoded.uncorrectable);
+        assert_eq!(2, decoded.corrected);
+        assert_eq!(data[..4], decoded.data[..4]);
+        assert_eq!(data[8..], decoded.data[8..]);
+
+        assert!(hamming74_encode(&[1, 0, 1]).is_err());
+        assert!(hamming84_decode(&code[..30]).is_err());
+    }
+
+    #[test]
+    fn reed_solomon_test() {
+        // Reference vector of the widely used reedsolo implementation (RSCodec(10))
+        let rs = ReedSolomon::new(10).unwrap();
+        let block = rs.encode(b"hello world").unwrap();
+        assert_eq!(
+            b"hello world\xed\x25\x54\xc4\xfd\xfd\x89\xf3\xa8\xaa".to_vec(),
+            block
+        );
+
+        // RS(255,223) corrects up to 16 symbol errors
+        let rs = ReedSolomon::new(32).unwrap();
+        let data: Vec<u8> = (0..223).map(|i| (i * 7 + 3) as u8).collect();
+        let block = rs.encode(&data).unwrap();
+        assert_eq!(255, block.len());
+
+        let decoded = rs.decode(&block).unwrap();
+        assert_eq!(data, decoded.data);
+        assert_eq!(0, decoded.corrected);
+
+        let mut received = block.clone();
+        for i in 0..16 {
+            received[i * 15 + 1] ^= 0x5A ^ i as u8;
+        }
+        let decoded = rs.decode(&received).unwrap();
+        assert_eq!(data, decoded.data);
+        assert_eq!(16, decoded.corrected);
+
+        received[254] ^= 0xFF;
+        let decoded = rs.decode(&received).unwrap();
+        assert_eq!(vec![0], decoded.uncorrectable);
+        assert_eq!(received[..223], decoded.data[..]);
+
+        // Shortened blocks, another generator and single bit errors
+        let rs = ReedSolomon::with_params(16, 0x187, 1).unwrap();
+        let data: Vec<u8> = (0..100).map(|i| (i * 13) as u8).collect();
+        let code = rs.encode_blocks(&data, 40).unwrap();
+        assert_eq!(100 + 3 * 16, code.len());
+
+        let mut received = code.clone();
+        received[0] ^= 0x01;
+        received[55] ^= 0x80;
+        for i in 0..9 {
+            received[112 + i] ^= 0xFF;
+        }
+        let decoded = rs.decode_blocks(&received, 56).unwrap();
+        assert_eq!(vec![2], decoded.uncorrectable);
+        assert_eq!(2, decoded.corrected);
+        assert_eq!(data[..80], decoded.data[..80]);
+
+        assert!(ReedSolomon::new(0).is_err());
+        assert!(ReedSolomon::with_params(8, 0x11B, 0).is_err());
+        assert!(rs.encode(&[0; 240]).is_err());
+        assert!(rs.decode(&[0; 16]).is_err());
+    }
+}
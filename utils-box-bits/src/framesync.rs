@@ -0,0 +1,409 @@
+//! # Frame sync utilities
+//! Find a sync word (preamble) at any bit offset of a captured bit stream, tolerating bit errors
+//! and inverted polarity, and slice the frames that follow it.
+//! Streams use the one-bit-per-byte representation of `bits_to_vec` (`Vec[0]` is the first bit received).
+
+use anyhow::{Result, bail};
+
+/// Size of the frames that follow the sync word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameLength {
+    /// Fixed number of bits after the sync word
+    Fixed(usize),
+    /// Unsigned MSB-first length field, `offset` bits after the end of the sync word.
+    /// The frame (after the sync word) is `offset + width + (value + adjust) * unit` bits long.
+    Field {
+        offset: usize,
+        width: u32,
+        /// Bits per unit of the length field (e.g. `8` when it counts bytes)
+        unit: usize,
+        /// Correction of the value (e.g. `2` when a CRC-16 follows but is not counted)
+        adjust: i64,
+    },
+}
+
+/// Sync word search and frame extraction settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameSync {
+    /// Sync word bits (`Vec[0]` is the first bit received)
+    pub sync_word: Vec<u8>,
+    /// Maximum number of bit errors (Hamming distance) tolerated in the sync word
+    pub max_errors: u32,
+    /// Also detect the complemented sync word, the frame bits are then inverted back
+    pub detect_inverted: bool,
+    pub length: FrameLength,
+}
+
+/// Position of a sync word in a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncMatch {
+    /// Bit offset of the first bit of the sync word
+    pub offset: usize,
+    /// Bit errors in the sync word
+    pub errors: u32,
+    /// Whether the sync word was received with inverted polarity
+    pub inverted: bool,
+}
+
+/// Frame found after a sync word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub sync: SyncMatch,
+    /// Bits after the sync word, with the polarity corrected
+    pub bits: Vec<u8>,
+}
+
+impl FrameSync {
+    /// Create settings for frames of `frame_bits` bits after an exact sync word
+    pub fn new(sync_word: &[u8], frame_bits: usize) -> Self {
+        Self {
+            sync_word: sync_word.to_vec(),
+            max_errors: 0,
+            detect_inverted: false,
+            length: FrameLength::Fixed(frame_bits),
+        }
+    }
+
+    /// Every position of the sync word, overlapping matches included
+    pub fn find(&self, stream: &[u8]) -> Result<Vec<SyncMatch>> {
+        self.check("FrameSync::find")?;
+
+        Ok((0..stream.len())
+            .filter_map(|offset| self.match_at(stream, offset))
+            .collect())
+    }
+
+    /// First position of the sync word at or after `start`
+    pub fn find_next(&self, stream: &[u8], start: usize) -> Result<Option<SyncMatch>> {
+        self.check("FrameSync::find_next")?;
+
+        Ok((start..stream.len()).find_map(|offset| self.match_at(stream, offset)))
+    }
+
+    /// Every complete frame of the stream
+    /// The search resumes after the end of each frame, so sync word look-alikes in the payload are skipped.
+    /// A frame running past the end of the stream (truncated or with a corrupted length) is skipped,
+    /// only a sync word followed by a cut off length field ends the search.
+    pub fn frames(&self, stream: &[u8]) -> Result<Vec<Frame>> {
+        self.check("FrameSync::frames")?;
+
+        let mut frames = vec![];
+        let mut start = 0;
+
+        while let Some(sync) = (start..stream.len()).find_map(|o| self.match_at(stream, o)) {
+            let frame_start = sync.offset + self.sync_word.len();
+
+            let frame_end = match self.frame_end(stream, frame_start, sync.inverted) {
+                Some(Some(frame_end)) if frame_end <= stream.len() => frame_end,
+                // Invalid length or incomplete frame, keep searching after this sync word
+                Some(_) => {
+                    start = sync.offset + 1;
+                    continue;
+                }
+                None => break,
+            };
+
+            let bits = stream[frame_start..frame_end]
+                .iter()
+                .map(|&bit| bit ^ sync.inverted as u8)
+                .collect();
+
+            frames.push(Frame { sync, bits });
+            start = frame_end;
+        }
+
+        Ok(frames)
+    }
+
+    fn check(&self, caller: &str) -> Result<()> {
+        if self.sync_word.is_empty() {
+            bail!("[{}] The sync word is empty!", caller);
+        }
+
+        if self.sync_word.iter().any(|&bit| bit > 1) {
+            bail!("[{}] The sync word must only hold 0 and 1 bits!", caller);
+        }
+
+        if let FrameLength::Field { width, .. } = self.length
+            && (width == 0 || width > 64)
+        {
+            bail!(
+                "[{}] Invalid length field width [{}], expected 1 to 64 bits!",
+                caller,
+                width
+            );
+        }
+
+        Ok(())
+    }
+
+    fn match_at(&self, stream: &[u8], offset: usize) -> Option<SyncMatch> {
+        let window = stream.get(offset..offset + self.sync_word.len())?;
+        let errors = hamming_distance(window, &self.sync_word);
+
+        if errors <= self.max_errors {
+            return Some(SyncMatch {
+                offset,
+                errors,
+                inverted: false,
+            });
+        }
+
+        let inverted_errors = self.sync_word.len() as u32 - errors;
+
+        if self.detect_inverted && inverted_errors <= self.max_errors {
+            return Some(SyncMatch {
+                offset,
+                errors: inverted_errors,
+                inverted: true,
+            });
+        }
+
+        None
+    }
+
+    /// End of the frame starting at `frame_start`,
+    /// `None` if the length field is cut off and `Some(None)` if it holds an invalid (or overflowing) length
+    fn frame_end(
+        &self,
+        stream: &[u8],
+        frame_start: usize,
+        inverted: bool,
+    ) -> Option<Option<usize>> {
+        match self.length {
+            FrameLength::Fixed(bits) => Some(frame_start.checked_add(bits)),
+            FrameLength::Field {
+                offset,
+                width,
+                unit,
+                adjust,
+            } => {
+                let Some(field_start) = frame_start.checked_add(offset) else {
+                    return Some(None);
+                };
+                let Some(field_end) = field_start.checked_add(width as usize) else {
+                    return Some(None);
+                };
+                let field = stream.get(field_start..field_end)?;
+
+                let value = field
+                    .iter()
+                    .fold(0u64, |acc, &bit| (acc << 1) | (bit ^ inverted as u8) as u64);
+
+                let frame_end = i128::from(value)
+                    .checked_add(i128::from(adjust))
+                    .and_then(|units| units.checked_mul(unit as i128))
+                    .and_then(|payload| usize::try_from(payload).ok())
+                    .and_then(|payload| field_end.checked_add(payload));
+
+                Some(frame_end)
+            }
+        }
+    }
+}
+
+/// Number of positions where the bits differ (only the common length is compared)
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> u32 {
+    a.iter()
+        .zip(b)
+        .filter(|&(&a, &b)| (a != 0) != (b != 0))
+        .count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::*;
+    use crate::framesync::*;
+
+    /// CCSDS attached sync marker
+    fn asm() -> Vec<u8> {
+        bits_to_vec(0x1ACFFC1D, 32)
+    }
+
+    #[test]
+    fn frame_sync_fixed_test() {
+        let first = bits_to_vec(0xDEAD, 16);
+        let second = bits_to_vec(0xBEEF, 16);
+
+        let mut corrupted = asm();
+        corrupted[3] ^= 1;
+        corrupted[20] ^= 1;
+
+        let mut stream = vec![1, 0, 1];
+        stream.extend(asm());
+        stream.extend(&first);
+        stream.extend(vec![0; 5]);
+        stream.extend(&corrupted);
+        stream.extend(&second);
+        stream.extend(asm());
+        stream.extend(vec![1; 8]);
+
+        let mut sync = FrameSync::new(&asm(), 16);
+
+        // Exact matches only
+        let frames = sync.frames(&stream).unwrap();
+        assert_eq!(1, frames.len());
+        assert_eq!(3, frames[0].sync.offset);
+        assert_eq!(first, frames[0].bits);
+
+        sync.max_errors = 2;
+        let frames = sync.frames(&stream).unwrap();
+        assert_eq!(2, frames.len());
+        assert_eq!(56, frames[1].sync.offset);
+        assert_eq!(2, frames[1].sync.errors);
+        assert_eq!(second, frames[1].bits);
+
+        let matches = sync.find(&stream).unwrap();
+        assert_eq!(
+            vec![3, 56, 104],
+            matches.iter().map(|m| m.offset).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(104),
+            sync.find_next(&stream, 57).unwrap().map(|m| m.offset)
+        );
+
+        // Inverted polarity
+        let inverted: Vec<u8> = stream.iter().map(|bit| bit ^ 1).collect();
+        assert!(sync.frames(&inverted).unwrap().is_empty());
+
+        sync.detect_inverted = true;
+        let frames = sync.frames(&inverted).unwrap();
+        assert_eq!(2, frames.len());
+        assert!(frames.iter().all(|frame| frame.sync.inverted));
+        assert_eq!(first, frames[0].bits);
+        assert_eq!(second, frames[1].bits);
+
+        assert!(FrameSync::new(&[], 8).frames(&stream).is_err());
+        assert!(FrameSync::new(&[0, 2], 8).find(&stream).is_err());
+    }
+
+    #[test]
+    fn frame_sync_length_field_test() {
+        // Length byte counting the payload bytes, followed by a CRC-16 that is not counted
+        let sync = FrameSync {
+            sync_word: bits_to_vec(0xB5, 8),
+            max_errors: 0,
+            detect_inverted: false,
+            length: FrameLength::Field {
+                offset: 0,
+                width: 8,
+                unit: 8,
+                adjust: 2,
+            },
+        };
+
+        let mut stream = vec![0, 0];
+        for payload in [vec![0x01, 0x02, 0x03], vec![], vec![0xB5]] {
+            stream.extend(bits_to_vec(0xB5, 8));
+            stream.extend(bits_to_vec(payload.len() as u64, 8));
+            for byte in &payload {
+                stream.extend(bits_to_vec(*byte as u64, 8));
+            }
+            stream.extend(bits_to_vec(0xCAFE, 16));
+        }
+
+        // Truncated last frame
+        stream.extend(bits_to_vec(0xB5, 8));
+        stream.extend(bits_to_vec(0x10, 8));
+
+        let frames = sync.frames(&stream).unwrap();
+        assert_eq!(3, frames.len());
+        assert_eq!(
+            bit_vec_to_byte_vec(&frames[0].bits),
+            vec![0x03, 0x01, 0x02, 0x03, 0xCA, 0xFE]
+        );
+        assert_eq!(bit_vec_to_byte_vec(&frames[1].bits), vec![0x00, 0xCA, 0xFE]);
+        assert_eq!(
+            bit_vec_to_byte_vec(&frames[2].bits),
+            vec![0x01, 0xB5, 0xCA, 0xFE]
+        );
+
+        assert_eq!(2, hamming_distance(&asm(), &bits_to_vec(0x1ACFFC1E, 32)));
+    }
+
+    #[test]
+    fn frame_sync_corrupted_length_test() {
+        let sync = FrameSync {
+            sync_word: bits_to_vec(0xB5, 8),
+            max_errors: 0,
+            detect_inverted: false,
+            length: FrameLength::Field {
+                offset: 0,
+                width: 16,
+                unit: 8,
+                adjust: 0,
+            },
+        };
+
+        // Corrupted header whose length runs past the end of the capture
+        let mut stream = bits_to_vec(0xB5, 8);
+        stream.extend(bits_to_vec(0xFFFF, 16));
+        for payload in [0x11, 0x22, 0x33] {
+            stream.extend(bits_to_vec(0xB5, 8));
+            stream.extend(bits_to_vec(1, 16));
+            stream.extend(bits_to_vec(payload, 8));
+        }
+
+        let frames = sync.frames(&stream).unwrap();
+        assert_eq!(3, frames.len());
+        assert_eq!(24, frames[0].sync.offset);
+        assert_eq!(
+            vec![
+                vec![0x00, 0x01, 0x11],
+                vec![0x00, 0x01, 0x22],
+                vec![0x00, 0x01, 0x33]
+            ],
+            frames
+                .iter()
+                .map(|frame| bit_vec_to_byte_vec(&frame.bits))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn frame_sync_length_overflow_test() {
+        let mut sync = FrameSync {
+            sync_word: asm(),
+            max_errors: 0,
+            detect_inverted: false,
+            length: FrameLength::Field {
+                offset: 0,
+                width: 64,
+                unit: 1,
+                adjust: 0,
+            },
+        };
+
+        // All-ones length field followed by a valid frame
+        let mut stream = asm();
+        stream.extend(vec![1; 64]);
+        stream.extend(asm());
+        stream.extend(bits_to_vec(16, 64));
+        stream.extend(bits_to_vec(0xBEEF, 16));
+
+        let frames = sync.frames(&stream).unwrap();
+        assert_eq!(1, frames.len());
+        assert_eq!(96, frames[0].sync.offset);
+        assert_eq!(bit_vec_to_byte_vec(&frames[0].bits[64..]), vec![0xBE, 0xEF]);
+
+        for length in [
+            FrameLength::Field {
+                offset: 0,
+                width: 64,
+                unit: usize::MAX,
+                adjust: 0,
+            },
+            FrameLength::Field {
+                offset: usize::MAX,
+                width: 64,
+                unit: 1,
+                adjust: 0,
+            },
+            FrameLength::Fixed(usize::MAX),
+        ] {
+            sync.length = length;
+            assert!(sync.frames(&stream).unwrap().is_empty());
+        }
+    }
+}
@@ -16,5 +16,201 @@
 //!
 //! ```
 //!
+//! ## BitVec
+//! Packed bit vector of arbitrary length (one bit per bit) with push/pop, indexing, slicing, iteration and concatenation
+//! and conversions to and from the `Vec<u8>`-of-bits representation, packed bytes, `u64` streams and hex strings
+//!
+//! Mininal Example:
+//! ```ignore
+//! let mut dump = bitvec::BitVec::from_hex_string("0xDEADBEEFCAFEF00D5A5A").unwrap();
+//! dump.push(true);
+//!
+//! let register = dump.slice(64..80);
+//! println!("Register: {} ({:?})", register.to_hex_string(), register.to_u64());
+//!
+//! let bytes = bits::bit_vec_to_byte_vec(&dump.to_bit_vec());
+//!
+//! ```
+//!
+//! ## BitStream
+//! Bit-level `BitReader`/`BitWriter` for fields of arbitrary width (signed or unsigned), booleans and padding
+//! that straddle byte boundaries, in MSB-first or LSB-first order over `&[u8]` and any `std::io::Read`/`Write`
+//!
+//! Mininal Example:
+//! ```ignore
+//! let mut writer = bitstream::BitWriter::new(vec![], bitstream::BitOrder::MsbFirst);
+//! writer.write_unsigned(0b101, 3).unwrap();
+//! writer.write_signed(-42, 11).unwrap();
+//! writer.write_bool(true).unwrap();
+//! let frame = writer.finish().unwrap();
+//!
+//! let mut reader = bitstream::BitReader::new(&frame[..], bitstream::BitOrder::MsbFirst);
+//! let kind = reader.read_unsigned(3).unwrap();
+//! let offset = reader.read_signed(11).unwrap();
+//! let valid = reader.read_bool().unwrap();
+//!
+//! ```
+//!
+//! ## Bitfield
+//! Derive pack/unpack functions for hardware registers from an annotated struct,
+//! with per-field bit ranges (bit 0 is the LSB), big or little endian byte order (per register or per field), reserved-bit checking and a field-wise `Debug`
+//!
+//! Mininal Example:
+//! ```ignore
+//! use utils_box_bits::bitfield::Bitfield;
+//!
+//! #[derive(Bitfield)]
+//! #[bitfield(bytes = 4, endian = "big")]
+//! struct Status {
+//!     #[bits(0..=2)]
+//!     mode: u8,
+//!     #[bits(3)]
+//!     enabled: bool,
+//!     #[bits(4..=15)]
+//!     threshold: u16,
+//!     #[bits(20..=27)]
+//!     offset: i8,
+//! }
+//!
+//! let status = Status::unpack(&[0x0F, 0xD0, 0xAB, 0xCD]).unwrap();
+//! println!("{:?}", status);
+//!
+//! let bytes = status.pack().unwrap();
+//!
+//! ```
+//!
+//! ## Hex strings
+//! Parse hex/binary/octal strings (optional `0x`/`0b`/`0o` prefix, underscores and whitespace) into bit and byte vectors
+//! and format bytes as an `xxd`-style hexdump with offsets and an ASCII column.
+//! Streams that are not a multiple of 4 (hex) or 8 (bytes) bits are padded with zero bits at the end (`ABC` becomes `[0xAB, 0xC0]`)
+//!
+//! Mininal Example:
+//! ```ignore
+//! let bits = bits::bin_string_to_bit_vec("0b1_1010_1000_1001_1101_0110").unwrap();
+//! println!("Received bit stream: {} ", bits::bit_vec_to_lower_hex_string(&bits));
+//!
+//! let frame = bits::hex_string_to_byte_vec("0xDEAD_BEEF 0A0D").unwrap();
+//! print!("{}", bits::hexdump(&frame));
+//!
+//! ```
+//!
+//! ## Endian
+//! Read and write fixed-width integers (including 24 and 48 bits) and floats in big or little endian order
+//! with a `ByteCursor` over a byte buffer and a `ByteWriter`, with bounds-checked errors
+//!
+//! Mininal Example:
+//! ```ignore
+//! let mut writer = endian::ByteWriter::new(endian::Endian::Big);
+//! writer.write_u16(0x1234);
+//! writer.write_i24(-42).unwrap();
+//! writer.write_f32(21.5);
+//! let payload = writer.into_bytes();
+//!
+//! let mut cursor = endian::ByteCursor::new(&payload, endian::Endian::Big);
+//! let id = cursor.read_u16().unwrap();
+//! let offset = cursor.read_i24().unwrap();
+//! let temperature = cursor.read_f32().unwrap();
+//!
+//! ```
+//!
+//! ## CRC
+//! Table-driven CRC engine for any width up to 64 bits (width, polynomial, init, reflect in/out, xorout)
+//! with common presets (CRC-16/MODBUS, CRC-16/IBM-3740, CRC-32/ISO-HDLC, CRC-32/ISCSI, ...) and incremental updates,
+//! plus XOR, Fletcher-16/32 and Adler-32 checksums
+//!
+//! Mininal Example:
+//! ```ignore
+//! let crc = crc::Crc::new(crc::CRC_16_MODBUS).unwrap();
+//! println!("CRC: {:04X}", crc.checksum(b"123456789"));
+//!
+//! let mut digest = crc.digest();
+//! digest.update(&[0x01, 0x03]);
+//! digest.update(&[0x00, 0x00, 0x00, 0x0A]);
+//! println!("Frame CRC: {:04X}", digest.finalize());
+//!
+//! println!("Adler-32: {:08X}", crc::adler32(b"123456789"));
+//!
+//! ```
+//!
+//! ## Line coding
+//! Encoders and decoders for Manchester (IEEE and Thomas), differential Manchester, NRZ/NRZI,
+//! HDLC bit stuffing with flag detection and Gray code over the `bits_to_vec` representation, reporting code violations
+//!
+//! Mininal Example:
+//! ```ignore
+//! let bits = bits::bits_to_vec(0b110101000100111010110, 21);
+//!
+//! let symbols = linecode::manchester_encode(&bits, linecode::Manchester::Ieee);
+//! let decoded = linecode::manchester_decode(&symbols, linecode::Manchester::Ieee).unwrap();
+//!
+//! let stream = linecode::hdlc_frame(&bits);
+//! for frame in linecode::hdlc_deframe(&stream).unwrap().frames {
+//!     println!("Frame: {}", bits::bit_vec_to_hex_string(&frame));
+//! }
+//!
+//! ```
+//!
+//! ## Frame sync
+//! Search a bit stream for a sync word at any bit offset with a tolerated number of bit errors and inverted polarity detection,
+//! and slice the frames that follow it, with a fixed size or a size read from a length field
+//!
+//! Mininal Example:
+//! ```ignore
+//! let stream = bits::hex_string_to_bit_vec("0x5_1ACF_FC1D_DEAD_BEEF").unwrap();
+//!
+//! let mut sync = framesync::FrameSync::new(&bits::bits_to_vec(0x1ACFFC1D, 32), 32);
+//! sync.max_errors = 2;
+//! sync.detect_inverted = true;
+//!
+//! for frame in sync.frames(&stream).unwrap() {
+//!     println!("Frame at bit {}: {}", frame.sync.offset, bits::bit_vec_to_hex_string(&frame.bits));
+//! }
+//!
+//! ```
+//!
+//! ## FEC
+//! Forward error correction: parity bits, Hamming(7,4) and Hamming(8,4) SECDED over bit vectors
+//! and a configurable Reed-Solomon codec over GF(2^8) (e.g. RS(255,223)), reporting corrected errors and uncorrectable blocks
+//!
+//! Mininal Example:
+//! ```ignore
+//! let code = fec::hamming84_encode(&bits::bits_to_vec(0xD44E, 16)).unwrap();
+//! let decoded = fec::hamming84_decode(&code).unwrap();
+//! println!("Corrected: {} Uncorrectable blocks: {:?}", decoded.corrected, decoded.uncorrectable);
+//!
+//! let rs = fec::ReedSolomon::new(32).unwrap();
+//! let block = rs.encode(b"telemetry").unwrap();
+//! let decoded = rs.decode(&block).unwrap();
+//!
+//! ```
+//!
+//! ## BER
+//! Bit error rate measurement: align a received bit stream to its reference, count the errors with their positions and bursts,
+//! and generate or check the PRBS7/9/15/23/31 test sequences (raw or ITU-T O.150 polarity) with a self-synchronising checker
+//!
+//! Mininal Example:
+//! ```ignore
+//! let reference = ber::PrbsGenerator::new(ber::Prbs::Prbs9).bits(1000);
+//! let alignment = ber::align_bits(&reference, &received, 64).unwrap();
+//! println!("Offset: {} BER: {} Bursts: {:?}", alignment.offset, alignment.errors.ber(), alignment.errors.burst_stats(8));
+//!
+//! let mut checker = ber::PrbsChecker::o150(ber::Prbs::Prbs15);
+//! let report = checker.check(&received);
+//! println!("Locked: {} Errors: {} BER: {}", report.locked, report.errors, report.ber());
+//!
+//! ```
+//!
+
+// The code generated by the derive macros refers to `::utils_box_bits`
+extern crate self as utils_box_bits;
 
+pub mod ber;
+pub mod bitfield;
 pub mod bits;
+pub mod bitstream;
+pub mod bitvec;
+pub mod crc;
+pub mod endian;
+pub mod fec;
+pub mod framesync;
+pub mod linecode;
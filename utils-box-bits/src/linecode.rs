@@ -0,0 +1,446 @@
+//! # Line coding utilities
+//! Encoders and decoders for the line codes of raw bit streams (e.g. from SDR front ends):
+//! Manchester, differential Manchester, NRZ/NRZI, HDLC bit stuffing and Gray code.
+//! Streams use the one-bit-per-byte representation of `bits_to_vec` (`Vec[0]` is the first bit on the line).
+//! Encoders consider any non-zero byte a set bit, decoders reject anything but `0` and `1`.
+
+use anyhow::{Result, bail};
+
+/// HDLC flag that opens and closes every frame
+pub const HDLC_FLAG: [u8; 8] = [0, 1, 1, 1, 1, 1, 1, 0];
+
+/// Frames found by `hdlc_deframe`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HdlcFrames {
+    /// Unstuffed payload of the valid frames
+    pub frames: Vec<Vec<u8>>,
+    /// Bit offset (after the opening flag) of the dropped frames that held six or more consecutive `1`s
+    pub aborted: Vec<usize>,
+}
+
+/// Manchester conventions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Manchester {
+    /// IEEE 802.3: `0` is sent as high-low (`10`) and `1` as low-high (`01`)
+    #[default]
+    Ieee,
+    /// G. E. Thomas: `0` is sent as low-high (`01`) and `1` as high-low (`10`)
+    Thomas,
+}
+
+/// NRZI conventions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Nrzi {
+    /// NRZ-M: the level toggles on every `1`
+    #[default]
+    Mark,
+    /// NRZ-S: the level toggles on every `0` (USB, HDLC)
+    Space,
+}
+
+/// Encode bits as Manchester symbols (two half-bit levels per bit)
+pub fn manchester_encode(bits: &[u8], convention: Manchester) -> Vec<u8> {
+    bits.iter()
+        .flat_map(|&bit| {
+            let high_low = match convention {
+                Manchester::Ieee => bit == 0,
+                Manchester::Thomas => bit != 0,
+            };
+
+            match high_low {
+                true => [1, 0],
+                false => [0, 1],
+            }
+        })
+        .collect()
+}
+
+/// Decode Manchester symbols, fails on an odd number of half-bits or a pair without a mid-bit transition
+pub fn manchester_decode(symbols: &[u8], convention: Manchester) -> Result<Vec<u8>> {
+    check_levels("manchester_decode", symbols)?;
+    check_pairs("manchester_decode", symbols)?;
+
+    symbols
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| match (pair[0], pair[1], convention) {
+            (1, 0, Manchester::Ieee) | (0, 1, Manchester::Thomas) => Ok(0),
+            (0, 1, Manchester::Ieee) | (1, 0, Manchester::Thomas) => Ok(1),
+            _ => bail!(
+                "[manchester_decode] Code violation [{}{}] at bit [{}]!",
+                pair[0],
+                pair[1],
+                i
+            ),
+        })
+        .collect()
+}
+
+/// Encode bits as differential Manchester symbols, starting from the line level `initial_level`
+/// Every bit has a mid-bit transition, a `0` also has a transition at its start
+pub fn differential_manchester_encode(bits: &[u8], initial_level: u8) -> Vec<u8> {
+    let mut level = (initial_level != 0) as u8;
+    let mut symbols = Vec::with_capacity(bits.len() * 2);
+
+    for &bit in bits {
+        if bit == 0 {
+            level ^= 1;
+        }
+
+        symbols.push(level);
+        level ^= 1;
+        symbols.push(level);
+    }
+
+    symbols
+}
+
+/// Decode differential Manchester symbols that started from the line level `initial_level`
+/// Fails on an odd number of half-bits or a pair without a mid-bit transition
+pub fn differential_manchester_decode(symbols: &[u8], initial_level: u8) -> Result<Vec<u8>> {
+    check_levels("differential_manchester_decode", symbols)?;
+    check_pairs("differential_manchester_decode", symbols)?;
+
+    let mut level = (initial_level != 0) as u8;
+    let mut bits = Vec::with_capacity(symbols.len() / 2);
+
+    for (i, pair) in symbols.chunks(2).enumerate() {
+        if pair[0] == pair[1] {
+            bail!(
+                "[differential_manchester_decode] Code violation [{}{}] at bit [{}]!",
+                pair[0],
+                pair[1],
+                i
+            );
+        }
+
+        bits.push((pair[0] == level) as u8);
+        level = pair[1];
+    }
+
+    Ok(bits)
+}
+
+/// Encode bits as NRZ-L levels (`1` is high), or with inverted polarity (`1` is low)
+pub fn nrz_encode(bits: &[u8], inverted: bool) -> Vec<u8> {
+    bits.iter()
+        .map(|&bit| ((bit != 0) != inverted) as u8)
+        .collect()
+}
+
+/// Decode NRZ-L levels, fails on anything but `0` and `1`
+pub fn nrz_decode(levels: &[u8], inverted: bool) -> Result<Vec<u8>> {
+    check_levels("nrz_decode", levels)?;
+
+    Ok(nrz_encode(levels, inverted))
+}
+
+/// Encode bits as NRZI levels, starting from the line level `initial_level`
+pub fn nrzi_encode(bits: &[u8], convention: Nrzi, initial_level: u8) -> Vec<u8> {
+    let toggle_on = match convention {
+        Nrzi::Mark => true,
+        Nrzi::Space => false,
+    };
+
+    let mut level = (initial_level != 0) as u8;
+
+    bits.iter()
+        .map(|&bit| {
+            if (bit != 0) == toggle_on {
+                level ^= 1;
+            }
+            level
+        })
+        .collect()
+}
+
+/// Decode NRZI levels that started from the line level `initial_level`
+pub fn nrzi_decode(levels: &[u8], convention: Nrzi, initial_level: u8) -> Result<Vec<u8>> {
+    check_levels("nrzi_decode", levels)?;
+
+    let mut previous = (initial_level != 0) as u8;
+
+    Ok(levels
+        .iter()
+        .map(|&level| {
+            let toggled = level != previous;
+            previous = level;
+
+            match convention {
+                Nrzi::Mark => toggled as u8,
+                Nrzi::Space => !toggled as u8,
+            }
+        })
+        .collect())
+}
+
+/// Insert a `0` after every five consecutive `1`s
+pub fn hdlc_stuff(bits: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(bits.len() + bits.len() / 5);
+    let mut ones = 0;
+
+    for &bit in bits {
+        let bit = (bit != 0) as u8;
+        stuffed.push(bit);
+
+        ones = match bit {
+            1 => ones + 1,
+            _ => 0,
+        };
+
+        if ones == 5 {
+            stuffed.push(0);
+            ones = 0;
+        }
+    }
+
+    stuffed
+}
+
+/// Remove the `0` after every five consecutive `1`s
+/// Fails if six or more consecutive `1`s are found (a flag or an abort sequence)
+pub fn hdlc_unstuff(bits: &[u8]) -> Result<Vec<u8>> {
+    check_levels("hdlc_unstuff", bits)?;
+
+    let mut unstuffed = Vec::with_capacity(bits.len());
+    let mut ones = 0;
+
+    for (i, &bit) in bits.iter().enumerate() {
+        if ones == 5 {
+            if bit == 1 {
+                bail!(
+                    "[hdlc_unstuff] Code violation: six consecutive ones at bit [{}]!",
+                    i
+                );
+            }
+
+            // Stuffed bit
+            ones = 0;
+            continue;
+        }
+
+        unstuffed.push(bit);
+
+        ones = match bit {
+            1 => ones + 1,
+            _ => 0,
+        };
+    }
+
+    Ok(unstuffed)
+}
+
+/// Stuff the payload and wrap it between two HDLC flags
+pub fn hdlc_frame(bits: &[u8]) -> Vec<u8> {
+    let mut frame = HDLC_FLAG.to_vec();
+    frame.extend(hdlc_stuff(bits));
+    frame.extend_from_slice(&HDLC_FLAG);
+
+    frame
+}
+
+/// Find the frames between HDLC flags and unstuff their payload
+/// Bits before the first flag and after the last one (an incomplete frame) are ignored,
+/// back-to-back flags (idle line) produce no frames.
+/// Frames holding six or more consecutive `1`s (an abort sequence or a code violation) are dropped
+/// and reported in `aborted`, the search resynchronises on the next flag.
+pub fn hdlc_deframe(stream: &[u8]) -> Result<HdlcFrames> {
+    check_levels("hdlc_deframe", stream)?;
+
+    let mut deframed = HdlcFrames::default();
+    let mut frame_start: Option<usize> = None;
+    let mut i = 0;
+
+    while i + HDLC_FLAG.len() <= stream.len() {
+        if stream[i..i + HDLC_FLAG.len()] != HDLC_FLAG {
+            i += 1;
+            continue;
+        }
+
+        if let Some(start) = frame_start
+            && i > start
+        {
+            match hdlc_unstuff(&stream[start..i]) {
+                Ok(frame) => deframed.frames.push(frame),
+                Err(_) => deframed.aborted.push(start),
+            }
+        }
+
+        i += HDLC_FLAG.len();
+        frame_start = Some(i);
+    }
+
+    Ok(deframed)
+}
+
+/// Convert a binary number to its Gray code
+pub fn gray_encode(value: u64) -> u64 {
+    value ^ (value >> 1)
+}
+
+/// Convert a Gray code to its binary number
+pub fn gray_decode(gray: u64) -> u64 {
+    let mut value = gray;
+    let mut shift = 1;
+
+    while shift < 64 {
+        value ^= value >> shift;
+        shift <<= 1;
+    }
+
+    value
+}
+
+/// Convert a bit vector (MSB is Vec[0]) of any length to its Gray code
+pub fn gray_encode_bits(bits: &[u8]) -> Vec<u8> {
+    let mut previous = 0;
+
+    bits.iter()
+        .map(|&bit| {
+            let bit = (bit != 0) as u8;
+            let gray = bit ^ previous;
+            previous = bit;
+            gray
+        })
+        .collect()
+}
+
+/// Convert a Gray code bit vector (MSB is Vec[0]) of any length to binary
+pub fn gray_decode_bits(gray: &[u8]) -> Result<Vec<u8>> {
+    check_levels("gray_decode_bits", gray)?;
+
+    let mut previous = 0;
+
+    Ok(gray
+        .iter()
+        .map(|&bit| {
+            previous ^= bit;
+            previous
+        })
+        .collect())
+}
+
+fn check_levels(caller: &str, bits: &[u8]) -> Result<()> {
+    match bits.iter().position(|&bit| bit > 1) {
+        Some(i) => bail!(
+            "[{}] Invalid bit value [{}] at position [{}]!",
+            caller,
+            bits[i],
+            i
+        ),
+        None => Ok(()),
+    }
+}
+
+fn check_pairs(caller: &str, symbols: &[u8]) -> Result<()> {
+    if !symbols.len().is_multiple_of(2) {
+        bail!(
+            "[{}] Expected an even number of half-bits but got [{}]!",
+            caller,
+            symbols.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::*;
+    use crate::linecode::*;
+
+    #[test]
+    fn manchester_nrz_test() {
+        let bits = bits_to_vec(0b110101000100111010110, 21);
+
+        assert_eq!(
+            vec![0, 1, 1, 0, 0, 1],
+            manchester_encode(&[1, 0, 1], Manchester::Ieee)
+        );
+        assert_eq!(
+            vec![1, 0, 0, 1, 1, 0],
+            manchester_encode(&[1, 0, 1], Manchester::Thomas)
+        );
+
+        for convention in [Manchester::Ieee, Manchester::Thomas] {
+            let symbols = manchester_encode(&bits, convention);
+            assert_eq!(42, symbols.len());
+            assert_eq!(bits, manchester_decode(&symbols, convention).unwrap());
+        }
+
+        let error = manchester_decode(&[0, 1, 1, 1], Manchester::Ieee).unwrap_err();
+        assert!(error.to_string().contains("Code violation [11] at bit [1]"));
+        assert!(manchester_decode(&[0, 1, 1], Manchester::Ieee).is_err());
+        assert!(manchester_decode(&[0, 2], Manchester::Ieee).is_err());
+
+        // Differential Manchester is immune to a polarity swap
+        assert_eq!(
+            vec![1, 0, 0, 1, 1, 0],
+            differential_manchester_encode(&[0, 1, 1], 0)
+        );
+        let symbols = differential_manchester_encode(&bits, 1);
+        assert_eq!(bits, differential_manchester_decode(&symbols, 1).unwrap());
+        let inverted = nrz_encode(&symbols, true);
+        assert_eq!(bits, differential_manchester_decode(&inverted, 0).unwrap());
+        assert!(differential_manchester_decode(&[1, 0, 0, 0], 0).is_err());
+
+        assert_eq!(vec![0, 1, 0], nrz_encode(&[1, 0, 1], true));
+        assert_eq!(bits, nrz_decode(&nrz_encode(&bits, false), false).unwrap());
+
+        assert_eq!(
+            vec![1, 1, 0, 0, 1],
+            nrzi_encode(&[1, 0, 1, 0, 1], Nrzi::Mark, 0)
+        );
+        assert_eq!(
+            vec![0, 1, 1, 0, 0],
+            nrzi_encode(&[1, 0, 1, 0, 1], Nrzi::Space, 0)
+        );
+        for convention in [Nrzi::Mark, Nrzi::Space] {
+            let levels = nrzi_encode(&bits, convention, 1);
+            assert_eq!(bits, nrzi_decode(&levels, convention, 1).unwrap());
+        }
+    }
+
+    #[test]
+    fn hdlc_gray_test() {
+        let payload = bits_to_vec(0b0111_1110_1111_1111, 16);
+
+        let stuffed = hdlc_stuff(&payload);
+        assert_eq!(bits_to_vec(0b01_1111_0101_1111_0111, 18), stuffed);
+        assert_eq!(payload, hdlc_unstuff(&stuffed).unwrap());
+        assert!(hdlc_unstuff(&HDLC_FLAG).is_err());
+
+        // Idle flags and noise around two frames
+        let second = bits_to_vec(0b1010_0101, 8);
+        let mut stream = vec![1, 0, 1];
+        stream.extend_from_slice(&HDLC_FLAG);
+        stream.extend(hdlc_frame(&payload));
+        stream.extend(hdlc_frame(&second));
+        stream.extend_from_slice(&[0, 1, 1]);
+
+        let deframed = hdlc_deframe(&stream).unwrap();
+        assert_eq!(vec![payload.clone(), second.clone()], deframed.frames);
+        assert!(deframed.aborted.is_empty());
+
+        // Aborted frame between two good ones
+        let mut stream = hdlc_frame(&payload);
+        stream.extend_from_slice(&[1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 0]);
+        stream.extend(hdlc_frame(&second));
+        let deframed = hdlc_deframe(&stream).unwrap();
+        assert_eq!(vec![payload, second], deframed.frames);
+        assert_eq!(vec![8 + stuffed.len() + 8], deframed.aborted);
+
+        assert!(hdlc_deframe(&[0, 2]).is_err());
+
+        let gray: Vec<u64> = (0..8).map(gray_encode).collect();
+        assert_eq!(vec![0, 1, 3, 2, 6, 7, 5, 4], gray);
+        for value in [0, 1, 0xDEAD_BEEF, u64::MAX] {
+            assert_eq!(value, gray_decode(gray_encode(value)));
+        }
+
+        let bits = bits_to_vec(0b110101000100111010110, 21);
+        let gray_bits = gray_encode_bits(&bits);
+        assert_eq!(bits_to_vec(gray_encode(vec_to_bits(&bits)), 21), gray_bits);
+        assert_eq!(bits, gray_decode_bits(&gray_bits).unwrap());
+    }
+}
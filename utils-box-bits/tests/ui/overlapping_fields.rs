@@ -0,0 +1,12 @@
+use utils_box_bits::bitfield::Bitfield;
+
+#[derive(Bitfield)]
+#[bitfield(bytes = 2)]
+struct Overlapping {
+    #[bits(0..=7)]
+    low: u8,
+    #[bits(4..=11)]
+    middle: u8,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use utils_box_bits::bitfield::Bitfield;
+
+#[derive(Bitfield)]
+#[bitfield(bytes = 2)]
+struct Partial {
+    #[bits(0..=11, endian = "little")]
+    value: u16,
+}
+
+fn main() {}
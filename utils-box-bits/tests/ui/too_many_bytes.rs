@@ -0,0 +1,10 @@
+use utils_box_bits::bitfield::Bitfield;
+
+#[derive(Bitfield)]
+#[bitfield(bytes = 17)]
+struct Huge {
+    #[bits(0..=7)]
+    value: u8,
+}
+
+fn main() {}
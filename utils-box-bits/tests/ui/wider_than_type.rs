@@ -0,0 +1,10 @@
+use utils_box_bits::bitfield::Bitfield;
+
+#[derive(Bitfield)]
+#[bitfield(bytes = 2)]
+struct Wide {
+    #[bits(0..=11)]
+    value: u8,
+}
+
+fn main() {}
@@ -474,7 +474,7 @@ mod tests {
 
         let update_ini_path = updated_ini.into_temp_path().keep().unwrap();
 
-        ini_update(&update_ini_path, &results, &vec![]).unwrap();
+        ini_update(&update_ini_path, &results, &[]).unwrap();
 
         let expected_config = indoc! {r#"
         [version]
@@ -158,7 +158,7 @@ mod tests {
         let expected_version = Version::new(0, 9, 0);
 
         assert_eq!(
-            semver_parse_regex(&input, &pattern, "_").unwrap(),
+            semver_parse_regex(input, pattern, "_").unwrap(),
             expected_version
         );
     }